@@ -1,40 +1,339 @@
 //! Database related core stuff
+use std::fmt;
 use std::ptr;
-use crate::{RowID, Schema};
-use crate::query_cond::Cond;
+use crate::{ColumnValue, FieldUpdates, RowID, Schema};
+use crate::query_cond::{Aggregate, Cond, SortDir};
+
+/// A failure reported by the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbFailure {
+    /// the primary result code of SQLite, e.g. `SQLITE_CONSTRAINT`, or 0 for
+    /// a failure of PostgreSQL
+    pub code: i32,
+    /// the extended result code of SQLite, e.g. `SQLITE_CONSTRAINT_UNIQUE`,
+    /// which is the primary result code if there is no extended one
+    pub extended_code: i32,
+    /// the SQLSTATE of a failure of PostgreSQL, e.g. `23505`
+    pub sqlstate: Option<String>,
+    /// the message of the database, e.g. from `sqlite3_errmsg`
+    pub message: String,
+    /// the SQL of the statement that failed, if the failure came from one
+    pub sql: Option<String>
+}
+
+impl fmt::Display for DbFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(sqlstate) = &self.sqlstate {
+            write!(f, "Database error {}: {}", sqlstate, self.message)?;
+        } else if self.extended_code == self.code {
+            write!(f, "Database error {}: {}", self.code, self.message)?;
+        } else {
+            write!(f, "Database error {} (extended {}): {}", self.code, self.extended_code, self.message)?;
+        }
+        if let Some(sql) = &self.sql {
+            write!(f, " (SQL: {})", sql)?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of constraint that a change violated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConstraintKind {
+    /// a field or group of fields marked unique
+    Unique,
+    /// a duplicate primary key, e.g. an explicit row id that's taken
+    PrimaryKey,
+    /// a NULL in a non-nullable field
+    NotNull,
+    /// a reference to a missing record, or deleting a record that is referred to
+    ForeignKey,
+    /// a value rejected by a `CHECK` expression of the table
+    Check,
+    /// any other constraint, e.g. one raised by a trigger
+    Other
+}
+
+/// The constraint that a change violated, as far as the database tells.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ViolatedConstraint {
+    pub kind: ConstraintKind,
+    /// the table of the columns, if the database names them
+    pub table: Option<String>,
+    /// the columns of a unique, primary key or not null constraint, e.g.
+    /// `["user_name"]`, or empty if the database doesn't name them
+    pub columns: Vec<String>,
+    /// the name of a check constraint, or its expression if it has no name
+    pub name: Option<String>
+}
 
 /// Database error
 #[derive(Debug, Clone)]
-pub struct DbError(pub String);
+pub enum DbError {
+    /// A failure reported by the database that isn't one of the kinds below.
+    DatabaseFailure(DbFailure),
+    /// A constraint failed, e.g. a duplicate value of a unique field, a NULL
+    /// in a non-nullable field or a dangling foreign key, with the constraint
+    /// that failed.
+    ConstraintViolation(DbFailure, Box<ViolatedConstraint>),
+    /// The database is locked by another connection and the busy timeout has
+    /// expired, or a concurrent transaction made PostgreSQL abort this one,
+    /// e.g. a serialization failure or a deadlock, so it may be retried.
+    Busy(DbFailure),
+    /// A table is locked by another statement or connection sharing the cache.
+    Locked(DbFailure),
+    /// The SQLite API was used in a way it doesn't support.
+    Misuse(DbFailure),
+    /// The file is not a database, or it's encrypted.
+    NotADatabase(DbFailure),
+    /// The database can't be written, e.g. as it's opened read-only.
+    ReadOnly(DbFailure),
+    /// The operating system failed to read or write the database.
+    Io(DbFailure),
+    /// The statement was interrupted, e.g. to cancel a long query.
+    Interrupted,
+    /// No pooled connection became available before the timeout.
+    PoolTimeout,
+    /// A text value read from the database isn't valid UTF-8, e.g. as another
+    /// tool wrote it in a different encoding. `column` is its index in the
+    /// columns read, which is the index of the field when reading records.
+    InvalidUtf8 { column: usize },
+    /// Any other error, with a message explaining it.
+    Other(String),
+}
+
+impl DbError {
+    /// The failure reported by the database, if the error came from it.
+    pub fn failure(&self) -> Option<&DbFailure> {
+        match self {
+            DbError::DatabaseFailure(failure)
+            | DbError::ConstraintViolation(failure, _)
+            | DbError::Busy(failure)
+            | DbError::Locked(failure)
+            | DbError::Misuse(failure)
+            | DbError::NotADatabase(failure)
+            | DbError::ReadOnly(failure)
+            | DbError::Io(failure) => Some(failure),
+            DbError::Interrupted | DbError::PoolTimeout | DbError::InvalidUtf8 { .. } | DbError::Other(_) => None
+        }
+    }
+
+    /// The failure reported by the database, to add context to its message.
+    pub fn failure_mut(&mut self) -> Option<&mut DbFailure> {
+        match self {
+            DbError::DatabaseFailure(failure)
+            | DbError::ConstraintViolation(failure, _)
+            | DbError::Busy(failure)
+            | DbError::Locked(failure)
+            | DbError::Misuse(failure)
+            | DbError::NotADatabase(failure)
+            | DbError::ReadOnly(failure)
+            | DbError::Io(failure) => Some(failure),
+            DbError::Interrupted | DbError::PoolTimeout | DbError::InvalidUtf8 { .. } | DbError::Other(_) => None
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::DatabaseFailure(failure) => write!(f, "{}", failure),
+            DbError::ConstraintViolation(failure, _) => write!(f, "Constraint violation: {}", failure),
+            DbError::Busy(failure) => write!(f, "Database is busy: {}", failure),
+            DbError::Locked(failure) => write!(f, "Database table is locked: {}", failure),
+            DbError::Misuse(failure) => write!(f, "SQLite API misuse: {}", failure),
+            DbError::NotADatabase(failure) => write!(f, "File is not a database: {}", failure),
+            DbError::ReadOnly(failure) => write!(f, "Database is read-only: {}", failure),
+            DbError::Io(failure) => write!(f, "Disk I/O error: {}", failure),
+            DbError::Interrupted => write!(f, "The statement was interrupted"),
+            DbError::PoolTimeout => write!(f, "Timed out waiting for a pooled connection"),
+            DbError::InvalidUtf8 { column } => write!(f, "The text in column {} is not valid UTF-8", column),
+            DbError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
 
 /// Query result from the data base. It's a wrapper of DB result iterator.
+///
+/// Iterating ends at the first error, which is kept and can be read with
+/// `error`, so a failed read isn't mistaken for the end of the results.
+/// `try_next` and `results` return the error in place of the record instead.
 pub struct DbQueryResult<T:Schema> {
-    pub data_iter: Box<dyn Iterator<Item=T>>
+    pub data_iter: Box<dyn Iterator<Item=Result<T, DbError>>>,
+    error: Option<DbError>
+}
+
+impl<T:Schema> DbQueryResult<T> {
+    /// Wrap an iterator of records from the database, which yields an error
+    /// if a record can't be read.
+    pub fn new(data_iter: Box<dyn Iterator<Item=Result<T, DbError>>>) -> DbQueryResult<T> {
+        DbQueryResult { data_iter, error: None }
+    }
+
+    /// The error that ended the iteration, if any.
+    pub fn error(&self) -> Option<&DbError> {
+        self.error.as_ref()
+    }
+
+    /// Read the next record, or `None` at the end of the results. Unlike
+    /// `next`, a failed read is returned as the error, so each record can be
+    /// read with `?` like `while let Some(record) = result.try_next()? { ... }`.
+    pub fn try_next(&mut self) -> Result<Option<T>, DbError> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.data_iter.next().transpose()
+    }
+
+    /// Iterate over the remaining records and the error that ends them, so
+    /// each one can be read with `?` in a `for` loop.
+    pub fn results(self) -> impl Iterator<Item=Result<T, DbError>> {
+        self.error.map(Err).into_iter().chain(self.data_iter)
+    }
+
+    /// Read all remaining records into a `Vec`. Return the error instead if a
+    /// record can't be read.
+    pub fn collect_all(mut self) -> Result<Vec<T>, DbError> {
+        let records: Vec<T> = self.by_ref().collect();
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(records)
+        }
+    }
 }
 
 impl<T:Schema> Iterator for DbQueryResult<T>{
     type Item = T;
     fn next(&mut self) -> Option<Self::Item> {
-        self.data_iter.next()
+        if self.error.is_some() {
+            return None;
+        }
+        match self.data_iter.next()? {
+            Ok(record) => Some(record),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
     }
 }
 
 /// Yoshino database adaptor trait.
 /// 
 /// Every database adaptor implementation should implement this trait.
+///
+/// All methods take `&mut self`, as a connection runs one statement at a time
+/// and adaptors may keep per-connection state like prepared statements. Use a
+/// connection per thread to query concurrently.
 pub trait DbAdaptor {
     /// Create data table in the database for a Yoshino schema.
     fn create_table_for_schema<T: crate::types::Schema>(&mut self) -> Result<(), DbError>;
-    /// Insert a record to the database.
-    fn insert_record<T: crate::types::Schema>(&mut self, record: T) -> Result<(), DbError>;
+    /// Insert a record to the database. Return the row id assigned to the new record.
+    ///
+    /// For a schema without a `RowID` field, the SQLite adaptor returns the
+    /// implicit rowid of the new row, and the PostgreSQL adaptor returns 0 as
+    /// its tables have no such column.
+    fn insert_record<T: crate::types::Schema>(&mut self, record: T) -> Result<i64, DbError>;
     /// Query all records of the schema.
     fn query_all<T: crate::types::Schema>(&mut self) -> Result<DbQueryResult<T>, DbError>;
     /// Query records of the schema that matches the condition.
     fn query_with_cond<T: crate::types::Schema>(&mut self, cond: Cond) -> Result<DbQueryResult<T>, DbError>;
+    /// Query all records of the schema, sorted by the fields in `order`. Earlier fields take precedence.
+    fn query_sorted<T: crate::types::Schema>(&mut self, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError>;
+    /// Query the first record of the schema that matches the condition.
+    /// Return `None` if no record matches. Only one row is read, so it's the
+    /// way to look up a single record, e.g. by its row id.
+    fn query_first_with_cond<T: crate::types::Schema>(&mut self, cond: Cond) -> Result<Option<T>, DbError>;
+    /// Query the single record of the schema that matches the condition, the
+    /// same as `query_first_with_cond`.
+    fn query_one<T: crate::types::Schema>(&mut self, cond: Cond) -> Result<Option<T>, DbError> {
+        self.query_first_with_cond(cond)
+    }
+    /// Query records of the schema that matches the condition, sorted by the fields in `order`.
+    fn query_with_cond_sorted<T: crate::types::Schema>(&mut self, cond: Cond, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError>;
+    /// Query at most `limit` records of the schema, skipping the first `offset` ones.
+    fn query_limit<T: crate::types::Schema>(&mut self, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError>;
+    /// Query at most `limit` records of the schema that matches the condition,
+    /// skipping the first `offset` ones.
+    fn query_with_cond_limit<T: crate::types::Schema>(&mut self, cond: Cond, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError>;
+    /// Count all records of the schema.
+    fn count_all<T: crate::types::Schema>(&mut self) -> Result<u64, DbError>;
+    /// Count records of the schema that matches the condition, without reading them.
+    fn count_with_cond<T: crate::types::Schema>(&mut self, cond: Cond) -> Result<u64, DbError>;
+    /// Compute the aggregate over the records of the schema that matches the
+    /// condition, read as `V`, e.g. `i64` for the sum of an integer field or
+    /// `f64` for an average. Return `None` if no record matches.
+    ///
+    /// Return an error if the field is not in the schema or the aggregate can't
+    /// be computed over it and read as `V`, see `Aggregate::check_types`.
+    fn aggregate_with_cond<T: crate::types::Schema, V: ColumnValue>(&mut self, aggregate: Aggregate, cond: Cond) -> Result<Option<V>, DbError>;
     /// Delete records of the schema that matches the condition.
     fn delete_with_cond<T: crate::types::Schema>(&mut self, cond: Cond) -> Result<(), DbError>;
-    /// Update records of the schema that matches the condition.
+    /// Delete all records of the schema. Return the number of records deleted.
+    ///
+    /// Return an error if the table of the schema doesn't exist.
+    fn delete_all<T: crate::types::Schema>(&mut self) -> Result<u64, DbError>;
+    /// Delete the stored record with the same row id as `record`, or the same
+    /// primary key if the schema has no row id field. Return whether a record
+    /// was deleted, so `false` means it was already gone.
+    ///
+    /// Return an error if the schema has neither a row id field nor a primary
+    /// key, or the row id is `RowID::NEW`.
+    fn delete_record<T: crate::types::Schema>(&mut self, record: &T) -> Result<bool, DbError>;
+    /// Update records of the schema that matches the condition. All fields except
+    /// the row id are set to the values of `record`.
     fn update_with_cond<T: crate::types::Schema>(&mut self, cond:Cond, record: T) -> Result<(), DbError>;
+    /// Set the fields in `updates` on the records of the schema that matches the
+    /// condition, without reading the records. Return the number of records
+    /// changed, which is 0 if none matches.
+    ///
+    /// Return an error if the updates don't fit the schema, see `FieldUpdates::into_field_values`.
+    fn update_fields_with_cond<T: crate::types::Schema>(&mut self, updates: FieldUpdates, cond: Cond) -> Result<u64, DbError>;
+    /// Update the stored record with the same row id as `record`, or the same
+    /// primary key if the schema has no row id field. All fields except the row
+    /// id are set to the values of `record`.
+    ///
+    /// Return an error if the schema has neither a row id field nor a primary
+    /// key, the row id is `RowID::NEW`, or there is no stored record with the key.
+    #[doc(alias = "save")]
+    fn update_record<T: crate::types::Schema>(&mut self, record: &T) -> Result<(), DbError>;
+    /// Query the records of `C` whose `fk_column` refers to the row id of
+    /// `parent`, e.g. the posts of a user.
+    ///
+    /// Return an error if `P` has no row id field, the row id is `RowID::NEW`,
+    /// `fk_column` is not a field of `C`, or it's a foreign key to another schema.
+    fn query_children<P: crate::types::Schema, C: crate::types::Schema>(&mut self, parent: &P, fk_column: &str) -> Result<DbQueryResult<C>, DbError> {
+        let parent_name = P::get_schema_name();
+        let child_name = C::get_schema_name();
+        let id = match parent.try_get_row_id_field()? {
+            Some((_, RowID::ID(id))) => id,
+            Some((_, RowID::NEW)) => return Err(DbError::Other(format!("Cannot query the children of a new {} record", parent_name))),
+            None => return Err(DbError::Other(format!("{} has no row id field", parent_name)))
+        };
+        if !C::get_fields().iter().any(|(field_name, _)| field_name == fk_column) {
+            return Err(DbError::Other(format!("Unknown field in {}: {}", child_name, fk_column)));
+        }
+        if let Some(foreign_key) = C::get_foreign_keys().iter().find(|foreign_key| foreign_key.column == fk_column) {
+            if foreign_key.referred_schema != parent_name {
+                return Err(DbError::Other(format!("{} of {} refers to {}, not {}",
+                    fk_column, child_name, foreign_key.referred_schema, parent_name)));
+            }
+        }
+        self.query_with_cond(Cond::integer_equal_to(fk_column, id))
+    }
+}
+
+/// Quote an identifier with double quotes, so it can't be confused with
+/// keywords or other SQL. Both SQLite and PostgreSQL quote identifiers this way.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Quote the identifiers and join them with commas, e.g. for a list of columns.
+pub fn quote_identifiers<S: AsRef<str>>(identifiers: &[S]) -> String {
+    identifiers.iter().map(|identifier| quote_identifier(identifier.as_ref())).collect::<Vec<_>>().join(", ")
 }
 
 /// Database data type supported by Yoshino.
@@ -44,10 +343,14 @@ pub enum DbDataType {
     Text,
     Int,
     Float,
+    NullableFloat,
+    Blob,
+    NullableBlob,
     RowID 
 }
 
 /// The mark trait to indicate that this type can be directly obtained from data base.
+#[allow(clippy::borrowed_box)]
 pub trait DbData {
     /// data type in `DbDataType`
     fn db_data_type(&self) -> DbDataType;
@@ -73,13 +376,14 @@ impl DbData for String {
     }
 
     fn from_boxed_db_data(src: &Box<dyn DbData>) -> String {
-        unsafe {
-            {
-                let str_len = src.db_data_len();
-                let str_copy = libc::malloc(str_len) as *mut i8;
-                libc::strncpy(str_copy, src.db_data_ptr() as *mut i8, str_len);
-                String::from_raw_parts(str_copy as *mut u8, str_len, str_len)
-            }
+        let str_len = src.db_data_len();
+        if str_len == 0 {
+            String::new()
+        } else {
+            let bytes = unsafe {
+                std::slice::from_raw_parts(src.db_data_ptr() as *const u8, str_len)
+            };
+            String::from_utf8_lossy(bytes).into_owned()
         }
     }
 }
@@ -112,7 +416,7 @@ impl DbData for Option<i64> {
     fn db_data_ptr(&self) -> *const core::ffi::c_void {
         match self {
             None => ptr::null(),
-            Some(v) => (v as *const i64 as *const core::ffi::c_void)
+            Some(v) => v as *const i64 as *const core::ffi::c_void
         }
     }
     
@@ -154,12 +458,7 @@ impl DbData for Option<String> {
         if src.db_data_ptr().is_null() {
             None
         } else {
-            Some(unsafe {
-                let str_len = src.db_data_len();
-                let str_copy = libc::malloc(str_len) as *mut i8;
-                libc::strncpy(str_copy, src.db_data_ptr() as *mut i8, str_len);
-                String::from_raw_parts(str_copy as *mut u8, str_len, str_len)
-            })
+            Some(<String as DbData>::from_boxed_db_data(src))
         }
     }
 }
@@ -204,4 +503,76 @@ impl DbData for f64 {
             *(src.db_data_ptr() as *const f64)    
         }
     }
-}
\ No newline at end of file
+}
+
+impl DbData for Option<f64> {
+    fn db_data_type(&self) -> DbDataType {
+        DbDataType::NullableFloat
+    }
+    fn db_data_ptr(&self) -> *const core::ffi::c_void {
+        match self {
+            None => ptr::null(),
+            Some(v) => v as *const f64 as *const core::ffi::c_void
+        }
+    }
+    fn db_data_len(&self) -> usize {
+        8
+    }
+    fn from_boxed_db_data(src: &Box<dyn DbData>) -> Option<f64> {
+        if src.db_data_ptr().is_null() {
+            None
+        } else {
+            Some(unsafe {
+                *(src.db_data_ptr() as *const f64)
+            })
+        }
+    }
+}
+
+impl DbData for Vec<u8> {
+    fn db_data_type(&self) -> DbDataType {
+        DbDataType::Blob
+    }
+    fn db_data_ptr(&self) -> *const core::ffi::c_void {
+        self.as_ptr() as *const core::ffi::c_void
+    }
+    fn db_data_len(&self) -> usize {
+        self.len()
+    }
+    fn from_boxed_db_data(src: &Box<dyn DbData>) -> Vec<u8> {
+        let len = src.db_data_len();
+        if len == 0 {
+            Vec::new()
+        } else {
+            unsafe {
+                std::slice::from_raw_parts(src.db_data_ptr() as *const u8, len).to_vec()
+            }
+        }
+    }
+}
+
+impl DbData for Option<Vec<u8>> {
+    fn db_data_type(&self) -> DbDataType {
+        DbDataType::NullableBlob
+    }
+    fn db_data_ptr(&self) -> *const core::ffi::c_void {
+        match self {
+            None => ptr::null(),
+            Some(v) => v.as_ptr() as *const core::ffi::c_void
+        }
+    }
+    fn db_data_len(&self) -> usize {
+        match self {
+            None => 0,
+            Some(v) => v.len()
+        }
+    }
+    fn from_boxed_db_data(src: &Box<dyn DbData>) -> Option<Vec<u8>> {
+        if src.db_data_ptr().is_null() {
+            None
+        } else {
+            // an empty Vec still has a non-null dangling pointer, so it's kept apart from NULL
+            Some(<Vec<u8> as DbData>::from_boxed_db_data(src))
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! New values for some fields of a schema, set without a whole record.
+use crate::Schema;
+use crate::db::{DbData, DbDataType, DbError};
+use crate::query_cond::get_value_kind;
+
+/// A field name with the value to set the field to.
+pub type FieldValue = (String, Box<dyn DbData>);
+
+/// New values for some fields of a schema, like
+/// `FieldUpdates::new().set_integer("stock", 0).set_text("name", "sold out")`.
+/// They are set with `DbAdaptor::update_fields_with_cond`.
+///
+/// Fields are referred to by their column names. Setting a field twice keeps
+/// the last value.
+#[derive(Default)]
+pub struct FieldUpdates {
+    /// field names with their new values, `None` for NULL
+    updates: Vec<(String, Option<Box<dyn DbData>>)>
+}
+
+impl FieldUpdates {
+    pub fn new() -> FieldUpdates {
+        FieldUpdates::default()
+    }
+
+    /// Set an integer field.
+    pub fn set_integer(self, field_name: &str, value: i64) -> FieldUpdates {
+        self.set(field_name, Some(Box::new(value)))
+    }
+
+    /// Set a text field.
+    pub fn set_text(self, field_name: &str, value: &str) -> FieldUpdates {
+        self.set(field_name, Some(Box::new(value.to_string())))
+    }
+
+    /// Set a floating point field.
+    pub fn set_float(self, field_name: &str, value: f64) -> FieldUpdates {
+        self.set(field_name, Some(Box::new(value)))
+    }
+
+    /// Set a binary data field.
+    pub fn set_blob(self, field_name: &str, value: Vec<u8>) -> FieldUpdates {
+        self.set(field_name, Some(Box::new(value)))
+    }
+
+    /// Set a nullable field to NULL.
+    pub fn set_null(self, field_name: &str) -> FieldUpdates {
+        self.set(field_name, None)
+    }
+
+    fn set(mut self, field_name: &str, value: Option<Box<dyn DbData>>) -> FieldUpdates {
+        self.updates.retain(|(name, _)| name != field_name);
+        self.updates.push((field_name.to_string(), value));
+        self
+    }
+
+    /// Whether no field is set.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Check the updates against the fields of the schema and get the fields
+    /// with their new values. A NULL value is given as a nullable value of the
+    /// field type.
+    ///
+    /// Return an error if no field is set, or if a field is not in the schema,
+    /// is the row id, or can't hold its new value.
+    pub fn into_field_values<T: Schema>(self) -> Result<Vec<FieldValue>, DbError> {
+        if self.updates.is_empty() {
+            return Err(DbError::Other("No field to update".to_string()));
+        }
+        let schema_name = T::get_schema_name();
+        let fields = T::get_fields();
+        let mut field_values = Vec::new();
+        for (field_name, value) in self.updates {
+            let (_, field_type) = fields.iter().find(|(name, _)| name == &field_name)
+                .ok_or_else(|| DbError::Other(format!("Unknown field in {}: {}", schema_name, field_name)))?;
+            let value: Box<dyn DbData> = match (field_type, value) {
+                (DbDataType::RowID, _) => {
+                    return Err(DbError::Other(format!("Cannot update the row id field {} of {}", field_name, schema_name)));
+                }
+                (field_type, Some(value)) => {
+                    if get_value_kind(field_type) != get_value_kind(&value.db_data_type()) {
+                        return Err(DbError::Other(format!("Cannot set the {} field {} of {} to a {} value",
+                            get_value_kind(field_type), field_name, schema_name, get_value_kind(&value.db_data_type()))));
+                    }
+                    value
+                }
+                (DbDataType::NullableInt, None) => Box::new(None::<i64>),
+                (DbDataType::NullableText, None) => Box::new(None::<String>),
+                (DbDataType::NullableFloat, None) => Box::new(None::<f64>),
+                (DbDataType::NullableBlob, None) => Box::new(None::<Vec<u8>>),
+                (_, None) => {
+                    return Err(DbError::Other(format!("Cannot set the non-nullable field {} of {} to NULL", field_name, schema_name)));
+                }
+            };
+            field_values.push((field_name, value));
+        }
+        Ok(field_values)
+    }
+}
@@ -6,5 +6,14 @@
 pub mod types;
 pub mod db;
 pub mod query_cond;
-pub use types::{IntegerField, TextField, Schema, NullableTextField, NullableIntegerField, RowID, FloatField};
-pub use query_cond::Cond;
\ No newline at end of file
+pub mod field_updates;
+pub use types::{IntegerField, TextField, Schema, NullableTextField, NullableIntegerField, RowID, FloatField, NullableFloatField, BlobField, NullableBlobField, Timestamp, ForeignKey, OnDelete, Collation, CollatableField, ColumnValue, IntegerEnum};
+pub use query_cond::{Aggregate, Cond, SortDir};
+pub use field_updates::FieldUpdates;
+#[cfg(feature = "decimal")]
+pub use rust_decimal::Decimal;
+#[cfg(feature = "uuid")]
+pub use uuid::Uuid;
+
+#[cfg(test)]
+mod test;
@@ -1,6 +1,76 @@
 //! Yoshino query conditions
 
 use crate::Schema;
+use crate::db::{quote_identifier, DbData, DbDataType, DbError};
+
+/// Direction to sort query results by a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDir {
+    /// Ascending order.
+    Asc,
+    /// Descending order.
+    Desc
+}
+
+/// Aggregate function over a field of the records, computed by the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate<'a> {
+    /// Sum of a numeric field.
+    Sum(&'a str),
+    /// Minimum value of a field.
+    Min(&'a str),
+    /// Maximum value of a field.
+    Max(&'a str),
+    /// Average of a numeric field, as a float.
+    Avg(&'a str)
+}
+
+impl<'a> Aggregate<'a> {
+    /// The field to aggregate.
+    pub fn field_name(&self) -> &'a str {
+        match self {
+            Aggregate::Sum(field_name) | Aggregate::Min(field_name) | Aggregate::Max(field_name) | Aggregate::Avg(field_name) => field_name
+        }
+    }
+
+    /// The name of the SQL aggregate function.
+    pub fn function_name(&self) -> &'static str {
+        match self {
+            Aggregate::Sum(_) => "SUM",
+            Aggregate::Min(_) => "MIN",
+            Aggregate::Max(_) => "MAX",
+            Aggregate::Avg(_) => "AVG"
+        }
+    }
+
+    /// Check that the aggregate can be computed over a field of `field_type`
+    /// and read as a value of `value_type`. Sums and averages need a numeric
+    /// field, averages are read as floats, and the others as the field type.
+    pub fn check_types(&self, field_type: &DbDataType, value_type: &DbDataType) -> Result<(), DbError> {
+        let field_kind = get_value_kind(field_type);
+        if matches!(self, Aggregate::Sum(_) | Aggregate::Avg(_)) && !matches!(field_kind, "integer" | "float") {
+            return Err(DbError::Other(format!("Cannot compute {} of the {} field {}", self.function_name(), field_kind, self.field_name())));
+        }
+        let result_kind = match self {
+            Aggregate::Avg(_) => "float",
+            _ => field_kind
+        };
+        if get_value_kind(value_type) != result_kind {
+            return Err(DbError::Other(format!("{} of field {} is a {}, but it's read as a {}", self.function_name(), self.field_name(), result_kind, get_value_kind(value_type))));
+        }
+        Ok(())
+    }
+}
+
+/// The kind of values of a field type, regardless of nullability.
+pub(crate) fn get_value_kind(field_type: &DbDataType) -> &'static str {
+    match field_type {
+        DbDataType::Int | DbDataType::NullableInt | DbDataType::RowID => "integer",
+        DbDataType::Float | DbDataType::NullableFloat => "float",
+        DbDataType::Text | DbDataType::NullableText => "text",
+        DbDataType::Blob | DbDataType::NullableBlob => "blob"
+    }
+}
 
 /// Query conditions.
 /// 
@@ -14,18 +84,30 @@ pub enum Cond {
     IsNotNull {field_name: String},
     /// The field is a text and it's equal to `value`.
     TextEqualTo{field_name: String, value: String},
+    /// The field is a text and it's not equal to `value`.
+    TextNotEqualTo{field_name: String, value: String},
+    /// The field is a text and it's equal to `value`, ignoring the case of ASCII letters.
+    TextEqualToIgnoreCase{field_name: String, value: String},
+    /// The field is a text and it matches the LIKE `pattern`, where `\` escapes wildcards.
+    TextLike{field_name: String, pattern: String},
+    /// The field is a text and it's equal to one of `values`.
+    TextIn{field_name: String, values: Vec<String>},
     /// The field is an integer and it's equal to `value`.
     IntegerEqualTo{field_name: String, value: i64},
     /// The field is an integer and it's not equal to `value`.
     IntegerNotEqualTo{field_name: String, value: i64},
-    /// The field is an integer and it's great than `value`.
+    /// The field is an integer and it's greater than `value`.
     IntegerGreaterThan{field_name: String, value: i64},
     /// The field is an integer and it's less than `value`.
     IntegerLessThan{field_name: String, value: i64},
-    /// The field is an integer and it's great than or equal to `value`.
+    /// The field is an integer and it's greater than or equal to `value`.
     IntegerGreaterThanOrEqualTo{field_name: String, value: i64},
     /// The field is an integer and it's less than or equal to `value`.
     IntegerLessThanOrEqualTo{field_name: String, value: i64},
+    /// The field is an integer and it's equal to one of `values`.
+    IntegerIn{field_name: String, values: Vec<i64>},
+    /// The field is an integer between `low` and `high`, both inclusive.
+    IntegerBetween{field_name: String, low: i64, high: i64},
     /// Both conditions are true.
     And {left: Box<Cond>, right: Box<Cond>},
     /// At least one of the two conditions is true.
@@ -46,6 +128,7 @@ impl Cond {
     }
 
     /// The negation of the condition expression.
+    #[allow(clippy::should_implement_trait)]
     pub fn not(cond: Cond) -> Cond {
         Cond::Not { cond: Box::new(cond) }
     }
@@ -70,9 +153,14 @@ impl Cond {
         Cond::IntegerNotEqualTo { field_name: field_name.to_string(), value}
     }
     
-    /// The field is an integer and it's great than `value`.
-    pub fn integer_great_than(field_name: &str, value: i64) -> Cond {
+    /// The field is an integer and it's greater than `value`.
+    pub fn integer_greater_than(field_name: &str, value: i64) -> Cond {
         Cond::IntegerGreaterThan { field_name: field_name.to_string(), value}
+    }
+
+    #[deprecated(note = "use `integer_greater_than`")]
+    pub fn integer_great_than(field_name: &str, value: i64) -> Cond {
+        Cond::integer_greater_than(field_name, value)
     }    
     
     /// The field is an integer and it's less than `value`.
@@ -80,9 +168,14 @@ impl Cond {
         Cond::IntegerLessThan { field_name: field_name.to_string(), value}
     }
 
-    /// The field is an integer and it's great than or equal to `value`.
-    pub fn integer_great_than_or_equal_to(field_name: &str, value: i64) -> Cond {
+    /// The field is an integer and it's greater than or equal to `value`.
+    pub fn integer_greater_than_or_equal_to(field_name: &str, value: i64) -> Cond {
         Cond::IntegerGreaterThanOrEqualTo { field_name: field_name.to_string(), value}
+    }
+
+    #[deprecated(note = "use `integer_greater_than_or_equal_to`")]
+    pub fn integer_great_than_or_equal_to(field_name: &str, value: i64) -> Cond {
+        Cond::integer_greater_than_or_equal_to(field_name, value)
     }    
 
     /// The field is an integer and it's less than or equal to `value`.
@@ -90,11 +183,143 @@ impl Cond {
         Cond::IntegerLessThanOrEqualTo { field_name: field_name.to_string(), value}
     }
 
+    /// The field is an integer between `low` and `high`, both inclusive, like
+    /// a time range of `Timestamp` fields. Nothing matches if `low` is greater than `high`.
+    pub fn integer_between(field_name: &str, low: i64, high: i64) -> Cond {
+        Cond::IntegerBetween { field_name: field_name.to_string(), low, high }
+    }
+
+    /// The field is an integer and it's equal to one of `values`.
+    /// Nothing matches an empty list.
+    pub fn integer_in(field_name: &str, values: Vec<i64>) -> Cond {
+        Cond::IntegerIn { field_name: field_name.to_string(), values }
+    }
+
+    /// The field is a text and it's equal to one of `values`.
+    /// Nothing matches an empty list.
+    pub fn text_in(field_name: &str, values: Vec<String>) -> Cond {
+        Cond::TextIn { field_name: field_name.to_string(), values }
+    }
+
     /// The field is a text and it's equal to `value`.
     pub fn text_equal_to(field_name: &str, value: &str) -> Cond {
         Cond::TextEqualTo { field_name: field_name.to_string(), value: value.to_string() }
     }
 
+    /// The field is a text and it's not equal to `value`.
+    pub fn text_not_equal_to(field_name: &str, value: &str) -> Cond {
+        Cond::TextNotEqualTo { field_name: field_name.to_string(), value: value.to_string() }
+    }
+
+    /// The field is a text and it's equal to `value`, ignoring the case of
+    /// ASCII letters, e.g. to look up a user name.
+    pub fn text_equal_ci(field_name: &str, value: &str) -> Cond {
+        Cond::TextEqualToIgnoreCase { field_name: field_name.to_string(), value: value.to_string() }
+    }
+
+    /// The field is a text and it matches the LIKE `pattern`. `%` matches any
+    /// sequence of characters, `_` matches any single character and `\` makes
+    /// the next character match literally. The match is case-insensitive for ASCII letters.
+    pub fn text_like(field_name: &str, pattern: &str) -> Cond {
+        Cond::TextLike { field_name: field_name.to_string(), pattern: pattern.to_string() }
+    }
+
+    /// The field is a text and it starts with `value`. Wildcards in `value` match literally.
+    pub fn text_starts_with(field_name: &str, value: &str) -> Cond {
+        Cond::text_like(field_name, &format!("{}%", escape_like_pattern(value)))
+    }
+
+    /// The field is a text and it contains `value`. Wildcards in `value` match literally.
+    pub fn text_contains(field_name: &str, value: &str) -> Cond {
+        Cond::text_like(field_name, &format!("%{}%", escape_like_pattern(value)))
+    }
+
+    /// The SQL of the condition, with a `?N` placeholder for each value
+    /// numbered from `next_param_index`, which is left at the number after the
+    /// last placeholder. The values to bind to them are `bind_values` in the
+    /// same order. Start after the placeholders of the statement before the
+    /// condition, e.g. at 1 for a plain `WHERE`.
+    ///
+    /// The SQL is in the dialect of SQLite, e.g. `COLLATE NOCASE` for
+    /// `text_equal_ci`. An adaptor for another database can still rely on the
+    /// order of `bind_values`, which is the order of the values in the tree.
+    pub fn to_sql(&self, next_param_index: &mut i32) -> String {
+        use Cond::*;
+        match self {
+            IsNull { field_name } => format!("{} IS NULL", quote_identifier(field_name)),
+            IsNotNull { field_name } => format!("{} IS NOT NULL", quote_identifier(field_name)),
+            TextEqualTo { field_name, .. } => comparison_sql(field_name, "=", next_param_index),
+            TextNotEqualTo { field_name, .. } => comparison_sql(field_name, "<>", next_param_index),
+            TextEqualToIgnoreCase { field_name, .. } => {
+                format!("{}={} COLLATE NOCASE", quote_identifier(field_name), next_placeholder(next_param_index))
+            }
+            TextLike { field_name, .. } => {
+                format!("{} LIKE {} ESCAPE '\\'", quote_identifier(field_name), next_placeholder(next_param_index))
+            }
+            TextIn { field_name, values } => in_sql(field_name, values.len(), next_param_index),
+            IntegerEqualTo { field_name, .. } => comparison_sql(field_name, "=", next_param_index),
+            IntegerNotEqualTo { field_name, .. } => comparison_sql(field_name, "<>", next_param_index),
+            IntegerGreaterThan { field_name, .. } => comparison_sql(field_name, ">", next_param_index),
+            IntegerLessThan { field_name, .. } => comparison_sql(field_name, "<", next_param_index),
+            IntegerGreaterThanOrEqualTo { field_name, .. } => comparison_sql(field_name, ">=", next_param_index),
+            IntegerLessThanOrEqualTo { field_name, .. } => comparison_sql(field_name, "<=", next_param_index),
+            IntegerIn { field_name, values } => in_sql(field_name, values.len(), next_param_index),
+            IntegerBetween { field_name, .. } => {
+                let low = next_placeholder(next_param_index);
+                let high = next_placeholder(next_param_index);
+                format!("{} BETWEEN {} AND {}", quote_identifier(field_name), low, high)
+            }
+            And { left, right } => {
+                let left_sql = left.to_sql(next_param_index);
+                let right_sql = right.to_sql(next_param_index);
+                format!("({}) AND ({})", left_sql, right_sql)
+            }
+            Or { left, right } => {
+                let left_sql = left.to_sql(next_param_index);
+                let right_sql = right.to_sql(next_param_index);
+                format!("({}) OR ({})", left_sql, right_sql)
+            }
+            Not { cond } => format!("NOT ({})", cond.to_sql(next_param_index))
+        }
+    }
+
+    /// The values to bind to the placeholders of `to_sql`, in their order.
+    pub fn bind_values(&self) -> Vec<Box<dyn DbData>> {
+        let mut values = Vec::new();
+        self.push_bind_values(&mut values);
+        values
+    }
+
+    fn push_bind_values(&self, values: &mut Vec<Box<dyn DbData>>) {
+        use Cond::*;
+        match self {
+            IsNull { .. } | IsNotNull { .. } => {}
+            TextEqualTo { value, .. } | TextNotEqualTo { value, .. } | TextEqualToIgnoreCase { value, .. } => {
+                values.push(Box::new(value.clone()));
+            }
+            TextLike { pattern, .. } => values.push(Box::new(pattern.clone())),
+            TextIn { values: texts, .. } => {
+                values.extend(texts.iter().map(|value| Box::new(value.clone()) as Box<dyn DbData>));
+            }
+            IntegerEqualTo { value, .. } | IntegerNotEqualTo { value, .. } | IntegerGreaterThan { value, .. }
+            | IntegerLessThan { value, .. } | IntegerGreaterThanOrEqualTo { value, .. } | IntegerLessThanOrEqualTo { value, .. } => {
+                values.push(Box::new(*value));
+            }
+            IntegerIn { values: integers, .. } => {
+                values.extend(integers.iter().map(|value| Box::new(*value) as Box<dyn DbData>));
+            }
+            IntegerBetween { low, high, .. } => {
+                values.push(Box::new(*low));
+                values.push(Box::new(*high));
+            }
+            And { left, right } | Or { left, right } => {
+                left.push_bind_values(values);
+                right.push_bind_values(values);
+            }
+            Not { cond } => cond.push_bind_values(values)
+        }
+    }
+
     /// Get the condition that the row id of record is equal to the given `record`.
     /// Return None if the given record doesn't have a row id field or the field is new.
     pub fn is_row_id_equal_to<T: Schema>(record: &T) -> Option<Cond> {
@@ -106,11 +331,64 @@ impl Cond {
                     crate::RowID::ID(value) => Some(
                         Cond::IntegerEqualTo { 
                             field_name: field, 
-                            value: value}
+                            value}
                         )
                 }
             }
             None => None
         }
     }
-}
\ No newline at end of file
+
+    /// Get the condition that the primary key is equal to the one of the given `record`.
+    /// Return None if the schema has no primary key, or it's neither a text nor an integer field.
+    /// Return the error if a value of the record can't be stored, see `Schema::try_get_values`.
+    pub fn is_primary_key_equal_to<T: Schema>(record: &T) -> Result<Option<Cond>, DbError> {
+        let primary_key = match T::get_primary_key() {
+            Some(primary_key) => primary_key,
+            None => return Ok(None)
+        };
+        let index = match T::get_fields().iter().position(|(field_name, _)| field_name == &primary_key) {
+            Some(index) => index,
+            None => return Ok(None)
+        };
+        let value = record.try_get_values()?.swap_remove(index);
+        Ok(match value.db_data_type() {
+            DbDataType::Text => Some(Cond::TextEqualTo { value: <String as DbData>::from_boxed_db_data(&value), field_name: primary_key }),
+            DbDataType::Int => Some(Cond::IntegerEqualTo { value: <i64 as DbData>::from_boxed_db_data(&value), field_name: primary_key }),
+            _ => None
+        })
+    }
+}
+
+/// The placeholder `?N` for the next parameter.
+fn next_placeholder(next_param_index: &mut i32) -> String {
+    let placeholder = format!("?{}", next_param_index);
+    *next_param_index += 1;
+    placeholder
+}
+
+fn comparison_sql(field_name: &str, operator: &str, next_param_index: &mut i32) -> String {
+    format!("{}{}{}", quote_identifier(field_name), operator, next_placeholder(next_param_index))
+}
+
+/// `field IN (?N, ...)` with a placeholder for each of the `count` values. An
+/// empty `IN ()` isn't valid SQL, so it becomes a condition that is always false.
+fn in_sql(field_name: &str, count: usize, next_param_index: &mut i32) -> String {
+    if count == 0 {
+        return "0=1".to_string();
+    }
+    let placeholders: Vec<String> = (0..count).map(|_| next_placeholder(next_param_index)).collect();
+    format!("{} IN ({})", quote_identifier(field_name), placeholders.join(", "))
+}
+
+/// Escape the LIKE wildcards and the escape character itself in `value`.
+fn escape_like_pattern(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
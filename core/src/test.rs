@@ -0,0 +1,40 @@
+use crate::Cond;
+use crate::db::DbData;
+
+#[test]
+fn test_cond_to_sql_numbering() {
+    let cond = Cond::and(
+        Cond::or(Cond::text_equal_to("name", "a"), Cond::integer_in("value", vec![1, 2])),
+        Cond::and(Cond::is_null("other"), Cond::not(Cond::integer_between("value", 5, 9)))
+    );
+    // numbered after two parameters of the statement before the condition
+    let mut next_param_index = 3;
+    assert_eq!(cond.to_sql(&mut next_param_index),
+        r#"(("name"=?3) OR ("value" IN (?4, ?5))) AND (("other" IS NULL) AND (NOT ("value" BETWEEN ?6 AND ?7)))"#);
+    assert_eq!(next_param_index, 8);
+    let values = cond.bind_values();
+    assert_eq!(values.len(), 5);
+    assert_eq!(String::from_boxed_db_data(&values[0]), "a");
+    assert_eq!(values[1..].iter().map(i64::from_boxed_db_data).collect::<Vec<_>>(), vec![1, 2, 5, 9]);
+
+    let cond = Cond::or(Cond::text_in("name", vec![]), Cond::text_like("name", "a%"));
+    let mut next_param_index = 1;
+    assert_eq!(cond.to_sql(&mut next_param_index), r#"(0=1) OR ("name" LIKE ?1 ESCAPE '\')"#);
+    assert_eq!(next_param_index, 2);
+    assert_eq!(cond.bind_values().len(), 1);
+}
+
+#[test]
+fn test_cond_bind_values_order() {
+    let cond = Cond::or(
+        Cond::not(Cond::text_equal_ci("name", "Admin")),
+        Cond::and(Cond::text_starts_with("name", "50%"), Cond::integer_less_than("value", 4))
+    );
+    let mut next_param_index = 1;
+    assert_eq!(cond.to_sql(&mut next_param_index),
+        r#"(NOT ("name"=?1 COLLATE NOCASE)) OR (("name" LIKE ?2 ESCAPE '\') AND ("value"<?3))"#);
+    let values = cond.bind_values();
+    assert_eq!(String::from_boxed_db_data(&values[0]), "Admin");
+    assert_eq!(String::from_boxed_db_data(&values[1]), "50\\%%");
+    assert_eq!(i64::from_boxed_db_data(&values[2]), 4);
+}
@@ -8,21 +8,52 @@
 //! * `IntegerField` - nonnull 64-bit integer field.
 //! * `NullableIntegerField` - nullable 64-bit integer field.
 //! * `FloatField` - nonnull 64-bit floating point field.
+//! * `NullableFloatField` - nullable 64-bit floating point field.
+//! * `BlobField` - nonnull binary data field.
+//! * `NullableBlobField` - nullable binary data field.
 //! 
+//! Besides `i64`, `i32`, `u32`, `u64` and `bool` are integer fields too, as
+//! are fieldless enums that implement `IntegerEnum`. `Option<T>` of any type
+//! `T` with a nonnull field trait has the nullable one, e.g. `Option<u32>` is a
+//! `NullableIntegerField`.
+//!
+//! With the `decimal` feature, `Decimal` is a text field for exact decimal
+//! numbers, e.g. amounts of money. With the `uuid` feature, `Uuid` is a text
+//! field, usually the primary key of a schema.
+//!
 //! If you want to use a custom type in schema struct, you need to implement 
-//! one field trait for this custom type.
+//! one field trait for this custom type. `Timestamp` is such a type: it
+//! implements `IntegerField` and stores a time as Unix epoch seconds.
 //! 
 //! All field traits declare the method to generate DbData object that can be 
 //! accepted by the Yoshino database interfaces.
 
-use crate::db::{DbData, DbDataType};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use bytes::Bytes;
+#[cfg(feature = "decimal")]
+use rust_decimal::Decimal;
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+use crate::db::{DbData, DbDataType, DbError};
 
 /// It can be serialized as a String in Yoshino.
+#[allow(clippy::borrowed_box)]
 pub trait TextField: Sized{
     /// Create an instance from a boxed DbData trait object.
     fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// Like `from_db_data`, but fail instead of panicking if the stored value
+    /// isn't valid for the type. Reading a record from a query uses it, so the
+    /// failure is returned as an error instead of a panic mid-iteration.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
     /// Create the string to be used by the Yoshino.
     fn to_db_data(&self) -> String;
+    /// Like `to_db_data`, but fail instead of panicking if the value can't be
+    /// stored, e.g. a `u64` above `i64::MAX`.
+    fn try_to_db_data(&self) -> Result<String, DbError> {
+        Ok(self.to_db_data())
+    }
     /// The `DbDataType` of this field. For all `TextField` objects, it's `DbDataType::Text`.
     fn db_field_type() -> DbDataType {
         DbDataType::Text
@@ -30,11 +61,20 @@ pub trait TextField: Sized{
 }
 
 /// It can be serialized as a nullable String in Yoshino.
+#[allow(clippy::borrowed_box)]
 pub trait NullableTextField: Sized {
     /// Create an instance from a boxed DbData trait object.
     fn from_db_data(data: &Box< dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
     /// Create the string to be used by the Yoshino.
     fn to_db_data(&self) -> Option<String>;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<Option<String>, DbError> {
+        Ok(self.to_db_data())
+    }
     /// The `DbDataType` of this field. For all `NullableTextField` objects, it's `DbDataType::NullableText`.
     fn db_field_type() -> DbDataType {
         DbDataType::NullableText
@@ -42,11 +82,21 @@ pub trait NullableTextField: Sized {
 }
 
 /// It can be serailized as a 64-bit integer in Yoshino.
+#[allow(clippy::borrowed_box)]
 pub trait IntegerField: Sized {
     /// Create an instance from a boxed DbData trait object.
     fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`. It fails for an integer out of the
+    /// range of the type, or no variant of an `IntegerEnum`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
     /// Create the i64 to be used by the Yoshino.
     fn to_db_data(&self)-> i64;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<i64, DbError> {
+        Ok(self.to_db_data())
+    }
     /// The `DbDataType` of this field. For all `IntegerField` objects, it's `DbDataType::Int`.
     fn db_field_type() -> DbDataType {
         DbDataType::Int
@@ -54,11 +104,20 @@ pub trait IntegerField: Sized {
 }
 
 /// It can be serailized as a nullable 64-bit integer in Yoshino.
+#[allow(clippy::borrowed_box)]
 pub trait NullableIntegerField: Sized {
     /// Create an instance from a boxed DbData trait object.
     fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
     /// Create the i64 to be used by the Yoshino.
     fn to_db_data(&self)-> Option<i64>;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<Option<i64>, DbError> {
+        Ok(self.to_db_data())
+    }
     /// The `DbDataType` of this field. For all `IntegerField` objects, it's `DbDataType::NullableInt`.
     fn db_field_type() -> DbDataType {
         DbDataType::NullableInt
@@ -66,17 +125,123 @@ pub trait NullableIntegerField: Sized {
 }
 
 /// It can be serailized as 64-bit floating point numeric number in Yoshino.
+#[allow(clippy::borrowed_box)]
 pub trait FloatField: Sized {
     /// Create an instance from a boxed DbData trait object.
     fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
     /// Create the f64 to be used by the Yoshino.
     fn to_db_data(&self)-> f64;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<f64, DbError> {
+        Ok(self.to_db_data())
+    }
     /// The `DbDataType` of this field. For all `FloatField` objects, it's `DbDataType::Float`.
     fn db_field_type() -> DbDataType {
         DbDataType::Float
     }
 }
 
+/// It can be serailized as nullable 64-bit floating point numeric number in Yoshino.
+#[allow(clippy::borrowed_box)]
+pub trait NullableFloatField: Sized {
+    /// Create an instance from a boxed DbData trait object.
+    fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
+    /// Create the f64 to be used by the Yoshino.
+    fn to_db_data(&self)-> Option<f64>;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<Option<f64>, DbError> {
+        Ok(self.to_db_data())
+    }
+    /// The `DbDataType` of this field. For all `NullableFloatField` objects, it's `DbDataType::NullableFloat`.
+    fn db_field_type() -> DbDataType {
+        DbDataType::NullableFloat
+    }
+}
+
+/// It can be serialized as binary data in Yoshino.
+#[allow(clippy::borrowed_box)]
+pub trait BlobField: Sized {
+    /// Create an instance from a boxed DbData trait object.
+    fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
+    /// Create the bytes to be used by the Yoshino.
+    fn to_db_data(&self) -> Vec<u8>;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<Vec<u8>, DbError> {
+        Ok(self.to_db_data())
+    }
+    /// The `DbDataType` of this field. For all `BlobField` objects, it's `DbDataType::Blob`.
+    fn db_field_type() -> DbDataType {
+        DbDataType::Blob
+    }
+}
+
+/// It can be serialized as nullable binary data in Yoshino.
+#[allow(clippy::borrowed_box)]
+pub trait NullableBlobField: Sized {
+    /// Create an instance from a boxed DbData trait object.
+    fn from_db_data(data: &Box<dyn DbData>) -> Self;
+    /// See `TextField::try_from_db_data`.
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_db_data(data))
+    }
+    /// Create the bytes to be used by the Yoshino.
+    fn to_db_data(&self) -> Option<Vec<u8>>;
+    /// See `TextField::try_to_db_data`.
+    fn try_to_db_data(&self) -> Result<Option<Vec<u8>>, DbError> {
+        Ok(self.to_db_data())
+    }
+    /// The `DbDataType` of this field. For all `NullableBlobField` objects, it's `DbDataType::NullableBlob`.
+    fn db_field_type() -> DbDataType {
+        DbDataType::NullableBlob
+    }
+}
+
+/// `Option<T>` of any field type `T` is the nullable field of the same kind,
+/// storing `None` as NULL. A non-null value is read with the field trait of `T`.
+macro_rules! impl_nullable_field {
+    ($($field_trait: ident => $nullable_trait: ident($value_type: ty)),*) => {
+        $(
+            impl<T: $field_trait> $nullable_trait for Option<T> {
+                fn from_db_data(data: &Box<dyn DbData>) -> Self {
+                    if data.db_data_ptr().is_null() {
+                        None
+                    } else {
+                        Some(T::from_db_data(data))
+                    }
+                }
+                fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+                    if data.db_data_ptr().is_null() {
+                        Ok(None)
+                    } else {
+                        T::try_from_db_data(data).map(Some)
+                    }
+                }
+                fn to_db_data(&self) -> Option<$value_type> {
+                    self.as_ref().map(T::to_db_data)
+                }
+                fn try_to_db_data(&self) -> Result<Option<$value_type>, DbError> {
+                    self.as_ref().map(T::try_to_db_data).transpose()
+                }
+            }
+        )*
+    };
+}
+
+impl_nullable_field!(TextField => NullableTextField(String), IntegerField => NullableIntegerField(i64),
+    FloatField => NullableFloatField(f64), BlobField => NullableBlobField(Vec<u8>));
+
 impl TextField for String {
     fn from_db_data(data: &Box<dyn DbData>) -> String {
         <String as DbData>::from_boxed_db_data(data)
@@ -86,18 +251,6 @@ impl TextField for String {
     }
 }
 
-impl NullableTextField for Option<String> {
-    fn from_db_data(data: &Box<dyn DbData>) -> Option<String> {
-        <Option<String> as DbData>::from_boxed_db_data(data)
-    }
-    fn to_db_data(&self) -> Option<String> {
-        match self {
-            None => None,
-            Some(x) => Some(x.to_owned())
-        }
-    }
-}
-
 impl IntegerField for i64 {
     fn from_db_data(data: &Box<dyn DbData>) -> Self {
         <i64 as DbData>::from_boxed_db_data(data)
@@ -107,15 +260,82 @@ impl IntegerField for i64 {
     }
 }
 
-impl NullableIntegerField for Option<i64> {
-    fn from_db_data(data: &Box< dyn DbData>) -> Self {
-        <Option<i64> as DbData>::from_boxed_db_data(data)
+/// Stored as 0 for false and 1 for true. Any non-zero integer is read as true.
+impl IntegerField for bool {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        <i64 as DbData>::from_boxed_db_data(data) != 0
     }
-    fn to_db_data(&self) -> Option<i64> {
-        *self
+    fn to_db_data(&self) -> i64 {
+        i64::from(*self)
+    }
+}
+
+/// Integers of other sizes are stored as 64-bit integers. Reading a stored
+/// value that doesn't fit the type, or storing a `u64` above `i64::MAX`, panics
+/// in `from_db_data` and `to_db_data`, and is a `DbError` when the record is
+/// read from a query or written by the adaptor.
+macro_rules! impl_sized_integer_field {
+    ($($t: ty),*) => {
+        $(
+            impl IntegerField for $t {
+                fn from_db_data(data: &Box<dyn DbData>) -> Self {
+                    <$t as IntegerField>::try_from_db_data(data).unwrap_or_else(|e| panic!("{}", e))
+                }
+                fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+                    integer_from_i64(<i64 as DbData>::from_boxed_db_data(data))
+                }
+                fn to_db_data(&self) -> i64 {
+                    self.try_to_db_data().unwrap_or_else(|e| panic!("{}", e))
+                }
+                fn try_to_db_data(&self) -> Result<i64, DbError> {
+                    integer_to_i64(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_sized_integer_field!(i32, u32, u64);
+
+fn integer_from_i64<T: TryFrom<i64>>(value: i64) -> Result<T, DbError> {
+    T::try_from(value).map_err(|_|
+        DbError::Other(format!("The stored integer {} is out of the range of {}", value, std::any::type_name::<T>())))
+}
+
+fn integer_to_i64<T: TryInto<i64> + Copy + std::fmt::Display>(value: T) -> Result<i64, DbError> {
+    value.try_into().map_err(|_|
+        DbError::Other(format!("The integer {} is out of the range of i64, so it can't be stored", value)))
+}
+
+/// A fieldless enum stored as the integer discriminant of its variant.
+///
+/// Derive it with `#[derive(SchemaEnum)]`, which makes the enum an integer
+/// field. Reading an integer that is no variant's discriminant panics in
+/// `from_db_data`, and is a `DbError` when the record is read from a query.
+pub trait IntegerEnum: Sized {
+    /// The discriminant of the variant.
+    fn to_integer(&self) -> i64;
+    /// The variant with the discriminant, or `None` if there is no such variant.
+    fn from_integer(value: i64) -> Option<Self>;
+}
+
+impl<E: IntegerEnum> IntegerField for E {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        E::try_from_db_data(data).unwrap_or_else(|e| panic!("{}", e))
+    }
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        enum_from_i64(<i64 as DbData>::from_boxed_db_data(data))
+    }
+    fn to_db_data(&self) -> i64 {
+        self.to_integer()
     }
 }
 
+fn enum_from_i64<E: IntegerEnum>(value: i64) -> Result<E, DbError> {
+    E::from_integer(value).ok_or_else(||
+        DbError::Other(format!("The stored integer {} is no variant of {}", value, std::any::type_name::<E>())))
+}
+
 impl FloatField for f64 {
     fn from_db_data(data: &Box<dyn DbData>) -> Self {
         <f64 as DbData>::from_boxed_db_data(data)
@@ -125,10 +345,182 @@ impl FloatField for f64 {
     }
 }
 
+impl BlobField for Vec<u8> {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        <Vec<u8> as DbData>::from_boxed_db_data(data)
+    }
+    fn to_db_data(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl BlobField for Bytes {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        Bytes::from(<Vec<u8> as DbData>::from_boxed_db_data(data))
+    }
+    fn to_db_data(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+/// A point in time with second-level precision, stored as Unix epoch seconds.
+///
+/// Times before the epoch are stored as negative numbers. Sub-second parts are
+/// truncated towards the past when converting from a `SystemTime`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    unix_seconds: i64
+}
+
+impl Timestamp {
+    /// The current time.
+    pub fn now() -> Timestamp {
+        Timestamp::from(SystemTime::now())
+    }
+
+    /// The time that is `unix_seconds` seconds after the Unix epoch.
+    pub fn from_unix_seconds(unix_seconds: i64) -> Timestamp {
+        Timestamp { unix_seconds }
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn unix_seconds(&self) -> i64 {
+        self.unix_seconds
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Timestamp {
+        let unix_seconds = match time.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(e) => {
+                let duration = e.duration();
+                let seconds = -(duration.as_secs() as i64);
+                if duration.subsec_nanos() > 0 { seconds - 1 } else { seconds }
+            }
+        };
+        Timestamp { unix_seconds }
+    }
+}
+
+impl From<Timestamp> for SystemTime {
+    fn from(timestamp: Timestamp) -> SystemTime {
+        let duration = Duration::from_secs(timestamp.unix_seconds.unsigned_abs());
+        if timestamp.unix_seconds >= 0 {
+            UNIX_EPOCH + duration
+        } else {
+            UNIX_EPOCH - duration
+        }
+    }
+}
+
+impl IntegerField for Timestamp {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        Timestamp::from_unix_seconds(<i64 as DbData>::from_boxed_db_data(data))
+    }
+    fn to_db_data(&self) -> i64 {
+        self.unix_seconds
+    }
+}
+
+/// Stored as text in its canonical form, which keeps the scale, e.g. `12.30`
+/// is read back as `12.30` and not `12.3`. The database compares and sorts the
+/// stored values as text, not as numbers. Reading a text that isn't a decimal
+/// number panics in `from_db_data`, and is a `DbError` when the record is read
+/// from a query.
+#[cfg(feature = "decimal")]
+impl TextField for Decimal {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        Decimal::try_from_db_data(data).unwrap_or_else(|e| panic!("{}", e))
+    }
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        let text = <String as DbData>::from_boxed_db_data(data);
+        // unlike `from_str`, it fails instead of rounding digits that don't fit
+        Decimal::from_str_exact(&text).map_err(|_|
+            DbError::Other(format!("The stored text {:?} is no decimal number", text)))
+    }
+    fn to_db_data(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Stored as text in the hyphenated lowercase form, e.g.
+/// `67e55044-10b1-426f-9247-bb680e5fe0c8`. Reading a text that isn't a UUID
+/// panics in `from_db_data`, and is a `DbError` when the record is read from a
+/// query.
+///
+/// As the primary key of a schema, the key is set by the caller, usually to
+/// `Uuid::new_v4()`, so it's known before the record is inserted.
+#[cfg(feature = "uuid")]
+impl TextField for Uuid {
+    fn from_db_data(data: &Box<dyn DbData>) -> Self {
+        Uuid::try_from_db_data(data).unwrap_or_else(|e| panic!("{}", e))
+    }
+    fn try_from_db_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        let text = <String as DbData>::from_boxed_db_data(data);
+        Uuid::parse_str(&text).map_err(|_|
+            DbError::Other(format!("The stored text {:?} is no UUID", text)))
+    }
+    fn to_db_data(&self) -> String {
+        self.hyphenated().to_string()
+    }
+}
+
+/// A type that can be read from a single column, without reading the whole
+/// record. It's implemented for the types of this crate that implement a field
+/// trait; a custom field type can implement it by forwarding to its field trait.
+#[allow(clippy::borrowed_box)]
+pub trait ColumnValue: Sized {
+    /// data type of the field it's read from
+    fn column_data_type() -> DbDataType;
+    /// restore the value from a boxed db data object
+    fn from_column_data(data: &Box<dyn DbData>) -> Self;
+    /// like `from_column_data`, but fail instead of panicking if the stored
+    /// value isn't valid for the type
+    fn try_from_column_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        Ok(Self::from_column_data(data))
+    }
+}
+
+macro_rules! impl_column_value {
+    ($field_trait: ident for $($t: ty),+) => {
+        $(
+            impl ColumnValue for $t {
+                fn column_data_type() -> DbDataType {
+                    <$t as $field_trait>::db_field_type()
+                }
+                fn from_column_data(data: &Box<dyn DbData>) -> Self {
+                    <$t as $field_trait>::from_db_data(data)
+                }
+                fn try_from_column_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+                    <$t as $field_trait>::try_from_db_data(data)
+                }
+            }
+        )+
+    };
+}
+
+impl_column_value!(TextField for String);
+impl_column_value!(NullableTextField for Option<String>);
+impl_column_value!(IntegerField for i64, i32, u32, u64, bool, Timestamp);
+impl_column_value!(NullableIntegerField for Option<i64>, Option<i32>, Option<u32>, Option<u64>, Option<bool>, Option<Timestamp>);
+impl_column_value!(FloatField for f64);
+impl_column_value!(NullableFloatField for Option<f64>);
+impl_column_value!(BlobField for Vec<u8>, Bytes);
+impl_column_value!(NullableBlobField for Option<Vec<u8>>, Option<Bytes>);
+#[cfg(feature = "decimal")]
+impl_column_value!(TextField for Decimal);
+#[cfg(feature = "decimal")]
+impl_column_value!(NullableTextField for Option<Decimal>);
+#[cfg(feature = "uuid")]
+impl_column_value!(TextField for Uuid);
+#[cfg(feature = "uuid")]
+impl_column_value!(NullableTextField for Option<Uuid>);
+
 /// Auto increment row ID field. It will be represented as an integer primary key.
 /// 
 /// A schema can has at most one RowID field.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RowID {
     /// A new created object so it doesn't have a row id yet.
     NEW,
@@ -136,18 +528,103 @@ pub enum RowID {
     ID(i64)
 }
 
+#[allow(clippy::borrowed_box)]
 impl RowID {
     pub fn from_db_data(data: &Box<dyn DbData>) -> RowID{
         <RowID as DbData>::from_boxed_db_data(data)
     }
+    pub fn try_from_db_data(data: &Box<dyn DbData>) -> Result<RowID, DbError> {
+        Ok(RowID::from_db_data(data))
+    }
     pub fn to_db_data(&self) -> RowID {
-        self.clone()
+        *self
+    }
+    pub fn try_to_db_data(&self) -> Result<RowID, DbError> {
+        Ok(*self)
     }
     pub fn db_field_type() -> DbDataType {
         DbDataType::RowID
     }
 }
 
+impl<E: IntegerEnum> ColumnValue for E {
+    fn column_data_type() -> DbDataType {
+        <E as IntegerField>::db_field_type()
+    }
+    fn from_column_data(data: &Box<dyn DbData>) -> Self {
+        <E as IntegerField>::from_db_data(data)
+    }
+    fn try_from_column_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        <E as IntegerField>::try_from_db_data(data)
+    }
+}
+
+impl<E: IntegerEnum> ColumnValue for Option<E> {
+    fn column_data_type() -> DbDataType {
+        <Option<E> as NullableIntegerField>::db_field_type()
+    }
+    fn from_column_data(data: &Box<dyn DbData>) -> Self {
+        <Option<E> as NullableIntegerField>::from_db_data(data)
+    }
+    fn try_from_column_data(data: &Box<dyn DbData>) -> Result<Self, DbError> {
+        <Option<E> as NullableIntegerField>::try_from_db_data(data)
+    }
+}
+
+impl ColumnValue for RowID {
+    fn column_data_type() -> DbDataType {
+        RowID::db_field_type()
+    }
+    fn from_column_data(data: &Box<dyn DbData>) -> Self {
+        RowID::from_db_data(data)
+    }
+}
+
+/// What happens to a record when the record it refers to is deleted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnDelete {
+    /// Deleting the referred record fails while it's referred to.
+    NoAction,
+    /// Like `NoAction`, but checked immediately even in a deferred transaction.
+    Restrict,
+    /// The referring field is set to null.
+    SetNull,
+    /// The referring record is deleted too.
+    Cascade
+}
+
+/// How the values of a text column are compared, including by unique
+/// constraints, indexes and sort orders on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Collation {
+    /// Byte by byte, the default.
+    Binary,
+    /// Like `Binary`, but ASCII letters match regardless of their case.
+    NoCase,
+    /// Like `Binary`, but trailing spaces are ignored.
+    RTrim
+}
+
+/// A text field, nullable or not, which can have a `Collation`. The derive
+/// requires it for a field marked with `collate`.
+#[diagnostic::on_unimplemented(message = "`{Self}` isn't a text field, so it can't have a collation")]
+pub trait CollatableField {}
+
+impl<T: TextField> CollatableField for T {}
+impl<T: TextField> CollatableField for Option<T> {}
+
+/// A field that refers to a field of another schema, usually its row id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForeignKey {
+    /// the column of the referring field
+    pub column: String,
+    /// the schema name of the referred schema
+    pub referred_schema: String,
+    /// the column of the referred field
+    pub referred_column: String,
+    pub on_delete: OnDelete
+}
+
 /// Make the type a data schema in the relational database.
 /// 
 /// In most cases, you should only use the derive macro to implement this trait.
@@ -158,14 +635,60 @@ pub trait Schema: 'static {
     fn get_fields() -> Vec<(String, DbDataType)>;
     /// the values of all fields in boxed DbData objects.
     fn get_values(&self) -> Vec<Box<dyn DbData>>;
+    /// like `get_values`, but fail instead of panicking if a value can't be
+    /// stored, e.g. a `u64` above `i64::MAX`
+    fn try_get_values(&self) -> Result<Vec<Box<dyn DbData>>, DbError> {
+        Ok(self.get_values())
+    }
     /// to create the struct with valeus of all fields in boxed DbData objects
     fn create_with_values(values: Vec<Box<dyn DbData>>) -> Self;
+    /// like `create_with_values`, but fail instead of panicking if a value
+    /// isn't valid for its field, e.g. an integer that is no variant of an enum
+    fn try_create_with_values(values: Vec<Box<dyn DbData>>) -> Result<Self, DbError> where Self: Sized {
+        Ok(Self::create_with_values(values))
+    }
+
+    /// the groups of fields whose values must be unique together. A group
+    /// of a single field makes that field unique.
+    fn get_unique_constraints() -> Vec<Vec<String>> {
+        Vec::new()
+    }
+
+    /// the fields that refer to fields of other schemas.
+    fn get_foreign_keys() -> Vec<ForeignKey> {
+        Vec::new()
+    }
+
+    /// the fields to create an index on each.
+    fn get_indexes() -> Vec<String> {
+        Vec::new()
+    }
+
+    /// the field that is the primary key, if it's not a RowID field.
+    fn get_primary_key() -> Option<String> {
+        None
+    }
+
+    /// the text fields whose values aren't compared with the default
+    /// `Collation::Binary`. Only SQLite supports collations on columns, and
+    /// only sets them when a column is created, so changing the collation of
+    /// a field doesn't change an existing column.
+    fn get_collations() -> Vec<(String, Collation)> {
+        Vec::new()
+    }
 
     /// get the name and value of the RowID field.
-    /// Return `None` if there is no such field. Panic if there are more than one RowID field.
+    /// Return `None` if there is no such field. Panic if there are more than
+    /// one RowID field, or `try_get_values` fails.
     fn get_row_id_field(&self) -> Option<(String, RowID)> {
+        self.try_get_row_id_field().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `get_row_id_field`, but return the error if a value of the record
+    /// can't be stored, e.g. an integer out of the range of `i64`.
+    fn try_get_row_id_field(&self) -> Result<Option<(String, RowID)>, DbError> {
         let fields = Self::get_fields();
-        let values = Self::get_values(&self);
+        let values = self.try_get_values()?;
         let mut answer = None;
         for i in 0..fields.len() {
             let (field_name, field_type) = &fields.get(i).unwrap();
@@ -180,6 +703,6 @@ pub trait Schema: 'static {
                 }
             }
         }
-        return answer;
+        Ok(answer)
     }
 }
\ No newline at end of file
@@ -5,23 +5,91 @@ extern crate proc_macro;
 use proc_macro::token_stream::IntoIter;
 use proc_macro::TokenTree;
 use proc_macro::TokenStream;
-use proc_macro::TokenTree::{Group, Ident, Punct}; 
+use proc_macro::TokenTree::{Group, Ident, Literal, Punct}; 
 
 /// Derive macro for implementing `yoshino_core::Schema` trait.
-#[proc_macro_derive(Schema)]
+///
+/// The table name is `y_` followed by the lowercase struct name. It can be
+/// overridden with `#[yoshino(table = "name")]` on the struct. Likewise, the
+/// column name of a field is the field name unless it's overridden with
+/// `#[yoshino(column = "name")]` on the field. Conditions and sort orders
+/// refer to fields by their column names.
+///
+/// The column type of a field comes from its field trait, so a field of
+/// `Option<T>` is nullable for any field type `T`, including custom ones.
+///
+/// A field marked with `#[yoshino(unique)]` must have a different value in
+/// every record. Fields that must be unique together are listed by their
+/// column names with `#[yoshino(unique(a, b))]` on the struct.
+///
+/// A field that refers to another schema is marked with
+/// `#[yoshino(references(User, id))]`, or `foreign_key(User, id)`, naming the
+/// referred schema and its column. `on_delete = "cascade"` next to it deletes the record with the
+/// referred one, and `"set_null"`, `"restrict"` and `"no_action"` (the
+/// default) are also accepted.
+///
+/// Fields marked with `#[yoshino(index)]` are indexed when the adaptor creates
+/// the indexes for the schema.
+///
+/// A field marked with `#[yoshino(primary_key)]` is the primary key of the
+/// table, e.g. a `Uuid` key in place of a `RowID` field. A schema has at most
+/// one of them, and none if it has a `RowID` field.
+///
+/// A text field marked with `#[yoshino(collate = "nocase")]` is compared
+/// regardless of the case of ASCII letters, and `"rtrim"` ignores trailing
+/// spaces. `"binary"` is the default. It's a compile error on a field that
+/// isn't a text field:
+///
+/// ```compile_fail,E0277
+/// use yoshino_core::IntegerField;
+///
+/// #[derive(yoshino_derive::Schema)]
+/// struct Badge {
+///     #[yoshino(collate = "nocase")]
+///     level: i64
+/// }
+/// ```
+///
+/// The collation is only set when the column is created:
+/// `verify_schema` doesn't compare it, and `migrate_schema` doesn't change it
+/// on an existing column. Only a rebuilt table gets the new collation, e.g.
+/// when `migrate_schema_with_rebuild` drops a column.
+#[proc_macro_derive(Schema, attributes(yoshino))]
 pub fn derive_schema_fn(src: TokenStream) -> TokenStream {
     let mut src_tokens = src.into_iter();
     // get struct name
-    let struct_name = get_next_struct_name(&mut src_tokens).unwrap().to_string();
+    let mut struct_attrs = Vec::new();
+    let struct_name = get_next_struct_name(&mut src_tokens, &mut struct_attrs).unwrap().to_string();
+    let mut schema_name_code = format!("\"y_{}\"", struct_name.to_lowercase());
+    let mut unique_constraints = Vec::new();
+    for (key, value) in struct_attrs {
+        match (key.as_str(), value) {
+            ("table", Some(table_name)) => {
+                schema_name_code = table_name;
+            }
+            ("unique", Some(columns)) => {
+                unique_constraints.push(columns.split(',').map(|column| column.trim().to_owned()).collect::<Vec<String>>());
+            }
+            _ => panic!("Unsupported yoshino attribute on struct: {}", key)
+        }
+    }
     let mut derived_code = String::new();
     for it in src_tokens {    
         match it {
             Group(g) => {
                 if g.delimiter() == proc_macro::Delimiter::Brace {
                     let fields = get_struct_fields_from_stream(g.stream());
+                    for columns in &unique_constraints {
+                        for column in columns {
+                            if !fields.iter().any(|field| &field.column == column) {
+                                panic!("Unknown column in unique constraint: {}", column);
+                            }
+                        }
+                    }
+                    unique_constraints.extend(fields.iter().filter(|field| field.unique).map(|field| vec![field.column.clone()]));
                     derived_code = format!("impl yoshino_core::Schema for {struct_name} {{
     fn get_schema_name() -> String {{
-        \"y_{}\".to_owned()
+        {}.to_owned()
     }}
     fn get_fields() -> Vec<(String, yoshino_core::db::DbDataType)> {{
         {}
@@ -29,14 +97,42 @@ pub fn derive_schema_fn(src: TokenStream) -> TokenStream {
     fn get_values(&self) -> Vec<Box<dyn yoshino_core::db::DbData>> {{
         {}
     }}
+    fn try_get_values(&self) -> Result<Vec<Box<dyn yoshino_core::db::DbData>>, yoshino_core::db::DbError> {{
+        Ok({})
+    }}
     fn create_with_values(values: Vec<Box<dyn yoshino_core::db::DbData>>) -> {struct_name} {{
         {}
     }}
+    fn try_create_with_values(values: Vec<Box<dyn yoshino_core::db::DbData>>) -> Result<{struct_name}, yoshino_core::db::DbError> {{
+        Ok({})
+    }}
+    fn get_unique_constraints() -> Vec<Vec<String>> {{
+        {}
+    }}
+    fn get_foreign_keys() -> Vec<yoshino_core::ForeignKey> {{
+        {}
+    }}
+    fn get_indexes() -> Vec<String> {{
+        {}
+    }}
+    fn get_collations() -> Vec<(String, yoshino_core::Collation)> {{
+        {}
+    }}
+    fn get_primary_key() -> Option<String> {{
+        {}
+    }}
 }}",
-        struct_name.to_lowercase(),
+        schema_name_code,
         get_fields_vec_code(&fields),
         get_values_vec_code(&fields),
-        get_create_with_values_code(&struct_name, &fields));
+        get_try_values_vec_code(&fields),
+        get_create_with_values_code(&struct_name, &fields),
+        get_try_create_with_values_code(&struct_name, &fields),
+        get_unique_constraints_code(&unique_constraints),
+        get_foreign_keys_code(&fields),
+        get_indexes_code(&fields),
+        get_collations_code(&fields),
+        get_primary_key_code(&fields));
                 } else {
                     panic!("Only StructStruct can be derived as schemas.")
                 }
@@ -49,15 +145,89 @@ pub fn derive_schema_fn(src: TokenStream) -> TokenStream {
     derived_code.parse().unwrap()
 }
 
-fn get_next_struct_name(src_iter: &mut IntoIter) -> Option<TokenTree> {
+/// Derive macro for implementing `yoshino_core::IntegerEnum` trait on a
+/// fieldless enum, so it can be a field of a schema.
+///
+/// Each variant is stored as its discriminant, which can be set explicitly
+/// like `Archived = 9`. Reading an integer that is no variant's discriminant
+/// is an error.
+#[proc_macro_derive(SchemaEnum)]
+pub fn derive_schema_enum_fn(src: TokenStream) -> TokenStream {
+    let mut src_tokens = src.into_iter();
+    let enum_name = loop {
+        match src_tokens.next() {
+            Some(Ident(ident)) if ident.to_string() == "enum" => {
+                break src_tokens.next().unwrap().to_string();
+            }
+            Some(Ident(ident)) if ident.to_string() == "struct" => {
+                panic!("Only enums can be derived as SchemaEnum.")
+            }
+            Some(_) => {}
+            None => panic!("Only enums can be derived as SchemaEnum.")
+        }
+    };
+    let variants = match src_tokens.next() {
+        Some(Group(g)) if g.delimiter() == proc_macro::Delimiter::Brace => get_enum_variants_from_stream(g.stream()),
+        _ => panic!("Generic enums can't be derived as SchemaEnum.")
+    };
+    let mut to_integer_arms = String::new();
+    let mut from_integer_arms = String::new();
+    for variant in &variants {
+        to_integer_arms += &format!("{enum_name}::{variant} => {enum_name}::{variant} as i64, ");
+        from_integer_arms += &format!("value if value == {enum_name}::{variant} as i64 => Some({enum_name}::{variant}), ");
+    }
+    format!("impl yoshino_core::IntegerEnum for {enum_name} {{
+    fn to_integer(&self) -> i64 {{
+        match self {{ {to_integer_arms} }}
+    }}
+    fn from_integer(value: i64) -> Option<{enum_name}> {{
+        match value {{ {from_integer_arms} _ => None }}
+    }}
+}}").parse().unwrap()
+}
+
+/// Get the variant names of a fieldless enum from the stream in its braces,
+/// skipping attributes and explicit discriminants.
+fn get_enum_variants_from_stream(src: TokenStream) -> Vec<String> {
+    let mut variants = Vec::new();
+    // whether the tokens up to the next ',' are a discriminant
+    let mut in_discriminant = false;
+    for it in src.into_iter() {
+        match &it {
+            Punct(punct) if punct.as_char() == ',' => {
+                in_discriminant = false;
+            }
+            Punct(punct) if punct.as_char() == '=' => {
+                in_discriminant = true;
+            }
+            _ if in_discriminant => {}
+            Ident(ident) => {
+                variants.push(ident.to_string());
+            }
+            Group(g) if g.delimiter() == proc_macro::Delimiter::Bracket => {}
+            Group(_) => {
+                panic!("Only fieldless enums can be derived as SchemaEnum, but {} has fields.", variants.last().unwrap())
+            }
+            _ => {}
+        }
+    }
+    if variants.is_empty() {
+        panic!("An enum without variants can't be derived as SchemaEnum.")
+    }
+    variants
+}
+
+/// Find the struct name, collecting the yoshino attributes of the struct on the way.
+fn get_next_struct_name(src_iter: &mut IntoIter, attrs: &mut Vec<(String, Option<String>)>) -> Option<TokenTree> {
     loop {
         let token = src_iter.next();
         match token {
             None => {break}
-            Some(Ident(ident)) => {
-                if ident.to_string() == "struct" {
-                    return src_iter.next();
-                }
+            Some(Ident(ident)) if ident.to_string() == "struct" => {
+                return src_iter.next();
+            }
+            Some(Group(g)) if g.delimiter() == proc_macro::Delimiter::Bracket => {
+                attrs.extend(get_yoshino_attr_items(&g));
             }
             _ => {}
         }
@@ -65,22 +235,138 @@ fn get_next_struct_name(src_iter: &mut IntoIter) -> Option<TokenTree> {
     None
 }
 
-fn get_struct_fields_from_stream(src: TokenStream) -> Vec<(String, String)> {
+/// Parse the items of an attribute like `#[yoshino(table = "name")]` from its
+/// bracket group, as keys with optional values. A value is either a literal
+/// after `=` or the tokens in parentheses after the key, like `unique(a, b)`.
+/// Other attributes have no items.
+fn get_yoshino_attr_items(attr: &proc_macro::Group) -> Vec<(String, Option<String>)> {
+    let mut tokens = attr.stream().into_iter();
+    match tokens.next() {
+        Some(Ident(ident)) if ident.to_string() == "yoshino" => {}
+        _ => return Vec::new()
+    }
+    let args = match tokens.next() {
+        Some(Group(g)) if g.delimiter() == proc_macro::Delimiter::Parenthesis => g.stream(),
+        _ => panic!("Expected yoshino attribute in the form of #[yoshino(...)]")
+    };
+    let mut items = Vec::new();
+    let mut tokens = args.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            Ident(key) => {
+                let mut value = None;
+                if matches!(tokens.peek(), Some(Punct(punct)) if punct.as_char() == '=') {
+                    tokens.next();
+                    match tokens.next() {
+                        Some(Literal(literal)) => {
+                            value = Some(literal.to_string());
+                        }
+                        _ => panic!("Expected a literal value for yoshino attribute {}", key)
+                    }
+                } else if let Some(Group(g)) = tokens.peek() {
+                    if g.delimiter() == proc_macro::Delimiter::Parenthesis {
+                        value = Some(g.stream().to_string());
+                        tokens.next();
+                    }
+                }
+                items.push((key.to_string(), value));
+            }
+            Punct(punct) if punct.as_char() == ',' => {}
+            other => panic!("Unexpected token in yoshino attribute: {}", other)
+        }
+    }
+    items
+}
+
+/// A field of the struct with its yoshino attributes applied.
+struct SchemaField {
+    name: String,
+    field_type: String,
+    column: String,
+    unique: bool,
+    index: bool,
+    primary_key: bool,
+    /// the referred schema type and column
+    references: Option<(String, String)>,
+    /// the `yoshino_core::OnDelete` variant of the reference
+    on_delete: String,
+    /// the `yoshino_core::Collation` variant of the column
+    collation: Option<String>
+}
+
+impl SchemaField {
+    fn new(name: String, field_type: String, attrs: Vec<(String, Option<String>)>) -> SchemaField {
+        let mut column = name.clone();
+        let mut unique = false;
+        let mut index = false;
+        let mut primary_key = false;
+        let mut references = None;
+        let mut on_delete = "NoAction".to_owned();
+        let mut collation = None;
+        for (key, value) in attrs {
+            match (key.as_str(), value) {
+                ("column", Some(column_literal)) => {
+                    column = column_literal.trim_matches('"').to_owned();
+                }
+                ("unique", None) => {
+                    unique = true;
+                }
+                ("index", None) => {
+                    index = true;
+                }
+                ("primary_key", None) => {
+                    primary_key = true;
+                }
+                ("references", Some(reference)) | ("foreign_key", Some(reference)) => {
+                    match reference.split_once(',') {
+                        Some((schema, referred_column)) => {
+                            references = Some((schema.replace(' ', ""), referred_column.trim().to_owned()));
+                        }
+                        None => panic!("Expected references(Schema, column) on field {}", name)
+                    }
+                }
+                ("on_delete", Some(action)) => {
+                    on_delete = match action.trim_matches('"') {
+                        "no_action" => "NoAction",
+                        "restrict" => "Restrict",
+                        "set_null" => "SetNull",
+                        "cascade" => "Cascade",
+                        other => panic!("Unsupported on_delete action on field {}: {}", name, other)
+                    }.to_owned();
+                }
+                ("collate", Some(collation_name)) => {
+                    collation = Some(match collation_name.trim_matches('"') {
+                        "binary" => "Binary",
+                        "nocase" => "NoCase",
+                        "rtrim" => "RTrim",
+                        other => panic!("Unsupported collation on field {}: {}", name, other)
+                    }.to_owned());
+                }
+                _ => panic!("Unsupported yoshino attribute on field {}: {}", name, key)
+            }
+        }
+        SchemaField { name, field_type, column, unique, index, primary_key, references, on_delete, collation }
+    }
+}
+
+fn get_struct_fields_from_stream(src: TokenStream) -> Vec<SchemaField> {
     let mut fields = Vec::new();
     let mut state = 0;
     let mut current_field_name = String::new();
     let mut current_field_type = String::new();
+    let mut current_field_attrs = Vec::new();
 
     for it in src.into_iter() {
         match state {
             0 => {
                 // wait for field name
                 match &it {
-                    Ident(ident) => {
-                        if ident.to_string() != "pub"{
-                            current_field_name = ident.to_string();
-                            state = 1;
-                        }
+                    Ident(ident) if ident.to_string() != "pub" => {
+                        current_field_name = ident.to_string();
+                        state = 1;
+                    }
+                    Group(g) if g.delimiter() == proc_macro::Delimiter::Bracket => {
+                        current_field_attrs.extend(get_yoshino_attr_items(g));
                     }
                     _ => {
                         //ignore
@@ -89,13 +375,10 @@ fn get_struct_fields_from_stream(src: TokenStream) -> Vec<(String, String)> {
             }
             1 => {
                 // wait for punct ':'
-                match &it {
-                    Punct(punct) => {
-                        if punct.as_char() == ':' {
-                            state = 2;
-                        }
-                    } 
-                    _ => {}
+                if let Punct(punct) = &it {
+                    if punct.as_char() == ':' {
+                        state = 2;
+                    }
                 }
             }
             2 => {
@@ -107,13 +390,14 @@ fn get_struct_fields_from_stream(src: TokenStream) -> Vec<(String, String)> {
                     Punct(punct) => {
                         match punct.as_char(){
                             ',' => {
-                                fields.push((current_field_name.to_owned(), current_field_type.to_owned()));
-                                current_field_name = String::new();
-                                current_field_type = String::new();
+                                fields.push(SchemaField::new(
+                                    std::mem::take(&mut current_field_name),
+                                    std::mem::take(&mut current_field_type),
+                                    std::mem::take(&mut current_field_attrs)));
                                 state = 0;
                             }
                             '<' => {
-                                current_field_type = current_field_type + "::<";
+                                current_field_type += "::<";
                             }
                             c => {
                                 current_field_type = current_field_type + &c.to_string();
@@ -129,46 +413,146 @@ fn get_struct_fields_from_stream(src: TokenStream) -> Vec<(String, String)> {
     
     // end with state 2 -> there is a last field without ',' in the end
     if state == 2 {
-        fields.push((current_field_name.to_owned(), current_field_type.to_owned()));
+        fields.push(SchemaField::new(current_field_name, current_field_type, current_field_attrs));
     }
     fields
 }
 
-fn get_fields_vec_code(fields: &Vec<(String, String)>) -> String {
+fn get_fields_vec_code(fields: &[SchemaField]) -> String {
     let mut s = "vec![".to_owned();
     for i in 0..fields.len() {
         if i != 0 {
-            s = s + ", ";
+            s += ", ";
         }
-        let (field_name, field_type) = fields.get(i).unwrap();
-        s = s + format!("(\"{}\".to_string(), {}::db_field_type())", field_name, field_type).as_ref(); 
+        let field = fields.get(i).unwrap();
+        s += format!("({:?}.to_string(), {}::db_field_type())", field.column, field.field_type).as_ref(); 
     }
-    s = s + "]";
-    return s
+    s += "]";
+    s
 }
 
-fn get_values_vec_code(fields: &Vec<(String, String)>) -> String {
+fn get_values_vec_code(fields: &[SchemaField]) -> String {
     let mut s = "vec![".to_string();
     for i in 0..fields.len() {
         if i != 0 {
-            s = s + ", ";
+            s += ", ";
+        }
+        let field = fields.get(i).unwrap();
+        s += format!("Box::new(self.{}.to_db_data())", field.name).as_ref();
+    }
+    s += "]";
+    s
+}
+
+fn get_try_values_vec_code(fields: &[SchemaField]) -> String {
+    let mut s = "vec![".to_string();
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            s += ", ";
         }
-        let (field_name, _) = fields.get(i).unwrap();
-        s = s + format!("Box::new(self.{}.to_db_data())", field_name).as_ref();
+        s += format!("Box::new(self.{}.try_to_db_data()?)", field.name).as_ref();
     }
-    s = s + "]";
+    s += "]";
     s
 }
 
-fn get_create_with_values_code(struct_name: &str, fields: &Vec<(String, String)>) -> String {
+fn get_create_with_values_code(struct_name: &str, fields: &[SchemaField]) -> String {
     let mut s = struct_name.to_owned() + "{";
     for i in 0..fields.len() {
         if i != 0 {
-            s = s + ", ";
+            s += ", ";
+        }
+        let field = fields.get(i).unwrap();
+        s += format!("{}: {}::from_db_data(&values[{}])", field.name, field.field_type, i).as_ref();
+    }
+    s += "}";
+    s
+}
+
+fn get_try_create_with_values_code(struct_name: &str, fields: &[SchemaField]) -> String {
+    let mut s = struct_name.to_owned() + "{";
+    for (i, field) in fields.iter().enumerate() {
+        if i != 0 {
+            s += ", ";
         }
-        let (field_name, field_type) = fields.get(i).unwrap();
-        s = s + format!("{}: {}::from_db_data(&values[{}])", field_name, field_type, i).as_ref();
+        s += format!("{}: {}::try_from_db_data(&values[{}])?", field.name, field.field_type, i).as_ref();
     }
-    s = s + "}";
+    s += "}";
     s
-}
\ No newline at end of file
+}
+
+fn get_unique_constraints_code(unique_constraints: &[Vec<String>]) -> String {
+    let mut s = "vec![".to_owned();
+    for (i, columns) in unique_constraints.iter().enumerate() {
+        if i != 0 {
+            s += ", ";
+        }
+        s += "vec![";
+        for (j, column) in columns.iter().enumerate() {
+            if j != 0 {
+                s += ", ";
+            }
+            s += format!("{:?}.to_string()", column).as_ref();
+        }
+        s += "]";
+    }
+    s += "]";
+    s
+}
+
+fn get_foreign_keys_code(fields: &[SchemaField]) -> String {
+    let mut s = "vec![".to_owned();
+    for (i, field) in fields.iter().filter(|field| field.references.is_some()).enumerate() {
+        if i != 0 {
+            s += ", ";
+        }
+        let (schema, referred_column) = field.references.as_ref().unwrap();
+        s += format!("yoshino_core::ForeignKey {{ column: {:?}.to_string(), referred_schema: <{} as yoshino_core::Schema>::get_schema_name(), referred_column: {:?}.to_string(), on_delete: yoshino_core::OnDelete::{} }}",
+            field.column, schema, referred_column, field.on_delete).as_ref();
+    }
+    s += "]";
+    s
+}
+
+fn get_indexes_code(fields: &[SchemaField]) -> String {
+    let mut s = "vec![".to_owned();
+    for (i, field) in fields.iter().filter(|field| field.index).enumerate() {
+        if i != 0 {
+            s += ", ";
+        }
+        s += format!("{:?}.to_string()", field.column).as_ref();
+    }
+    s += "]";
+    s
+}
+
+fn get_collations_code(fields: &[SchemaField]) -> String {
+    // a collation on a field that isn't a text fails to compile
+    let mut s = "fn assert_collatable<T: yoshino_core::CollatableField>() {}\n".to_owned();
+    for field in fields.iter().filter(|field| field.collation.is_some()) {
+        s += format!("assert_collatable::<{}>();\n", field.field_type).as_ref();
+    }
+    s += "vec![";
+    for (i, field) in fields.iter().filter(|field| field.collation.is_some()).enumerate() {
+        if i != 0 {
+            s += ", ";
+        }
+        s += format!("({:?}.to_string(), yoshino_core::Collation::{})", field.column, field.collation.as_ref().unwrap()).as_ref();
+    }
+    s += "]";
+    s
+}
+
+fn get_primary_key_code(fields: &[SchemaField]) -> String {
+    let primary_keys: Vec<&SchemaField> = fields.iter().filter(|field| field.primary_key).collect();
+    match primary_keys.as_slice() {
+        [] => "None".to_owned(),
+        [field] => {
+            if fields.iter().any(|field| field.field_type == "RowID" || field.field_type.ends_with("::RowID")) {
+                panic!("A schema with a RowID field can't have another primary key: {}", field.name);
+            }
+            format!("Some({:?}.to_string())", field.column)
+        }
+        _ => panic!("Only one field can be the primary key")
+    }
+}
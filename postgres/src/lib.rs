@@ -0,0 +1,636 @@
+//! PostgreSQL adaptor for Yoshino.
+//!
+//! It stores the same schemas as the SQLite adaptor, so an application can be
+//! developed against SQLite and deployed on PostgreSQL.
+use postgres::{Client, GenericClient, NoTls, Row};
+use postgres::types::ToSql;
+use yoshino_core::{Aggregate, ColumnValue, FieldUpdates, ForeignKey, OnDelete, RowID, Schema, SortDir};
+use yoshino_core::db::{quote_identifier, quote_identifiers, ConstraintKind, DbAdaptor, DbData, DbDataType, DbError, DbFailure, DbQueryResult, ViolatedConstraint};
+
+/// A parameter to bind to a PostgreSQL statement.
+type Param = Box<dyn ToSql + Sync>;
+
+/// Build a `DbError` from an error of the PostgreSQL client, classified by
+/// the SQLSTATE if the server reported it.
+fn postgres_error(e: postgres::Error) -> DbError {
+    match e.as_db_error() {
+        Some(db_error) => {
+            let failure = DbFailure {
+                code: 0,
+                extended_code: 0,
+                sqlstate: Some(db_error.code().code().to_string()),
+                message: db_error.message().to_string(),
+                sql: None
+            };
+            let constraint = ViolatedConstraint {
+                kind: ConstraintKind::Other,
+                table: db_error.table().map(str::to_string),
+                columns: db_error.column().map(|column| vec![column.to_string()]).unwrap_or_default(),
+                name: db_error.constraint().map(str::to_string)
+            };
+            classify_failure(failure, constraint, db_error.detail())
+        }
+        None => DbError::Other(format!("PostgreSQL error: {}", e))
+    }
+}
+
+/// Pick the kind of error from the SQLSTATE of the failure. `constraint` has
+/// the fields the server reported, which name the violated constraint.
+fn classify_failure(failure: DbFailure, mut constraint: ViolatedConstraint, detail: Option<&str>) -> DbError {
+    let sqlstate = failure.sqlstate.clone().unwrap_or_default();
+    match sqlstate.as_str() {
+        // integrity constraint violations
+        code if code.starts_with("23") => {
+            constraint.kind = match code {
+                // the primary key constraint is named `<table>_pkey` unless it's named explicitly
+                "23505" if constraint.name.as_deref().is_some_and(|name| name.ends_with("_pkey")) => ConstraintKind::PrimaryKey,
+                "23505" => ConstraintKind::Unique,
+                "23502" => ConstraintKind::NotNull,
+                "23503" => ConstraintKind::ForeignKey,
+                "23514" => ConstraintKind::Check,
+                _ => ConstraintKind::Other
+            };
+            match constraint.kind {
+                // the columns are only in the detail, like `Key (user_name)=(alice) already exists.`
+                ConstraintKind::Unique | ConstraintKind::PrimaryKey => {
+                    if let Some(columns) = detail.and_then(|detail| detail.strip_prefix("Key (")).and_then(|rest| rest.split_once(")=(")) {
+                        constraint.columns = columns.0.split(", ").map(str::to_string).collect();
+                    }
+                    constraint.name = None;
+                }
+                ConstraintKind::NotNull => constraint.name = None,
+                ConstraintKind::ForeignKey | ConstraintKind::Other => constraint.columns.clear(),
+                ConstraintKind::Check => {}
+            }
+            DbError::ConstraintViolation(failure, Box::new(constraint))
+        }
+        // serialization failure, deadlock and lock not available
+        "40001" | "40P01" | "55P03" => DbError::Busy(failure),
+        // query canceled, e.g. by a statement timeout or a cancel request
+        "57014" => DbError::Interrupted,
+        "25006" => DbError::ReadOnly(failure),
+        // system errors, e.g. an I/O error
+        code if code.starts_with("58") => DbError::Io(failure),
+        _ => DbError::DatabaseFailure(failure)
+    }
+}
+
+/// The SQL expression of `expr` with its ASCII letters in lower case.
+fn fold_ascii_case(expr: &str) -> String {
+    format!("translate({}, 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz')", expr)
+}
+
+/// Add the SQL of the statement that failed to the failure, if the server reported one.
+fn with_sql(mut error: DbError, stmt: &str) -> DbError {
+    if let Some(failure) = error.failure_mut() {
+        failure.sql = Some(stmt.to_string());
+    }
+    error
+}
+
+/// The nullable version of a field type, to read a value that may be NULL.
+fn get_nullable_type(field_type: &DbDataType) -> DbDataType {
+    match field_type {
+        DbDataType::Int | DbDataType::NullableInt | DbDataType::RowID => DbDataType::NullableInt,
+        DbDataType::Text | DbDataType::NullableText => DbDataType::NullableText,
+        DbDataType::Float | DbDataType::NullableFloat => DbDataType::NullableFloat,
+        DbDataType::Blob | DbDataType::NullableBlob => DbDataType::NullableBlob
+    }
+}
+
+pub struct PostgresAdaptor {
+    client: Client
+}
+
+impl PostgresAdaptor {
+    /// Connect to the PostgreSQL server without TLS. `params` is a connection
+    /// string like `host=localhost user=postgres dbname=app`.
+    pub fn connect(params: &str) -> Result<PostgresAdaptor, DbError> {
+        let client = Client::connect(params, NoTls).map_err(postgres_error)?;
+        Ok(PostgresAdaptor::from_client(client))
+    }
+
+    /// Use a connected client, e.g. one connected with TLS.
+    pub fn from_client(client: Client) -> PostgresAdaptor {
+        PostgresAdaptor { client }
+    }
+
+    /// Drop the table of the schema. If `if_exists` is false, dropping a table
+    /// that doesn't exist is an error.
+    pub fn drop_table_for_schema<T: Schema>(&mut self, if_exists: bool) -> Result<(), DbError> {
+        let drop_table_stmt = format!(
+            "DROP TABLE {}{};",
+            if if_exists { "IF EXISTS " } else { "" },
+            quote_identifier(&T::get_schema_name())
+        );
+        self.client.batch_execute(&drop_table_stmt).map_err(postgres_error)
+    }
+
+    fn get_create_table_stmt_code(schema_name: &str, fields: &[(String, DbDataType)], unique_constraints: &[Vec<String>], foreign_keys: &[ForeignKey], primary_key: Option<&str>) -> String {
+        let mut s = format!("CREATE TABLE IF NOT EXISTS {} (", quote_identifier(schema_name));
+        for i in 0..fields.len() {
+            if i != 0 {
+                s += ", ";
+            }
+            let (field_name, field_type) = fields.get(i).unwrap();
+            s = s + &quote_identifier(field_name) + " ";
+            s += PostgresAdaptor::get_column_type_code(field_type);
+            if primary_key == Some(field_name.as_str()) {
+                s += " PRIMARY KEY";
+            }
+            if unique_constraints.iter().any(|columns| columns.len() == 1 && &columns[0] == field_name) {
+                s += " UNIQUE";
+            }
+            if let Some(foreign_key) = foreign_keys.iter().find(|foreign_key| &foreign_key.column == field_name) {
+                s += &format!(" REFERENCES {}({})", quote_identifier(&foreign_key.referred_schema), quote_identifier(&foreign_key.referred_column));
+                s += match foreign_key.on_delete {
+                    OnDelete::NoAction => "",
+                    OnDelete::Restrict => " ON DELETE RESTRICT",
+                    OnDelete::SetNull => " ON DELETE SET NULL",
+                    OnDelete::Cascade => " ON DELETE CASCADE"
+                };
+            }
+        }
+        for columns in unique_constraints.iter().filter(|columns| columns.len() > 1) {
+            s += &format!(", UNIQUE({})", quote_identifiers(columns));
+        }
+        s += ");";
+        s
+    }
+
+    fn get_column_type_code(field_type: &DbDataType) -> &'static str {
+        match field_type {
+            DbDataType::Int => "BIGINT NOT NULL",
+            DbDataType::NullableInt => "BIGINT",
+            DbDataType::Text => "TEXT NOT NULL",
+            DbDataType::NullableText => "TEXT",
+            DbDataType::Float => "DOUBLE PRECISION NOT NULL",
+            DbDataType::NullableFloat => "DOUBLE PRECISION",
+            DbDataType::Blob => "BYTEA NOT NULL",
+            DbDataType::NullableBlob => "BYTEA",
+            DbDataType::RowID => "BIGSERIAL PRIMARY KEY"
+        }
+    }
+
+    /// Build the insert statement and its parameters. A new row id is left out,
+    /// so the database assigns one, and returned by the statement.
+    fn get_insert_stmt_and_params(schema_name: &str, fields: &[(String, DbDataType)], values: Vec<Box<dyn DbData>>) -> (String, Vec<Box<dyn DbData>>) {
+        let mut columns = Vec::new();
+        let mut params = Vec::new();
+        let mut row_id_field = None;
+        for ((field_name, field_type), value) in fields.iter().zip(values) {
+            if let DbDataType::RowID = field_type {
+                row_id_field = Some(field_name);
+                if let RowID::NEW = RowID::from_db_data(&value) {
+                    continue;
+                }
+            }
+            columns.push(field_name.as_str());
+            params.push(value);
+        }
+        let mut s = format!("INSERT INTO {}", quote_identifier(schema_name));
+        if columns.is_empty() {
+            s += " DEFAULT VALUES";
+        } else {
+            let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("${}", i)).collect();
+            s += &format!(" ({}) VALUES ({})", quote_identifiers(&columns), placeholders.join(", "));
+        }
+        if let Some(field_name) = row_id_field {
+            s += &format!(" RETURNING {}", quote_identifier(field_name));
+        }
+        s += ";";
+        (s, params)
+    }
+
+    /// Build the statement moving the sequence of a row id column up to `id`,
+    /// so the next new row id is after it. The sequence is never moved back.
+    fn get_sync_sequence_stmt_and_params(schema_name: &str, field_name: &str, id: i64) -> (String, Vec<Box<dyn DbData>>) {
+        let stmt = "SELECT setval(seq, $3) FROM (SELECT pg_get_serial_sequence($1, $2)::regclass AS seq) AS s \
+            WHERE COALESCE(pg_sequence_last_value(seq), 0) < $3;";
+        // the table name is parsed as an identifier, but the column name is taken as it is
+        (stmt.to_string(), vec![Box::new(quote_identifier(schema_name)), Box::new(field_name.to_string()), Box::new(id)])
+    }
+
+    fn get_query_clause(schema_name: &str, fields: &[(String, DbDataType)]) -> String {
+        let field_names: Vec<&str> = fields.iter().map(|(field_name, _)| field_name.as_str()).collect();
+        format!("SELECT {} FROM {}", quote_identifiers(&field_names), quote_identifier(schema_name))
+    }
+
+    /// Build the UPDATE clause setting all fields except the row id, and add
+    /// the values of the record to `params`.
+    fn get_update_clause(schema_name: &str, fields: &[(String, DbDataType)], values: Vec<Box<dyn DbData>>, params: &mut Vec<Box<dyn DbData>>) -> String {
+        let mut assignments = Vec::new();
+        for ((field_name, field_type), value) in fields.iter().zip(values) {
+            // the row id identifies the record, so it's never updated
+            if let DbDataType::RowID = field_type {
+                continue;
+            }
+            params.push(value);
+            assignments.push(format!("{} = ${}", quote_identifier(field_name), params.len()));
+        }
+        format!("UPDATE {} SET {}", quote_identifier(schema_name), assignments.join(", "))
+    }
+
+    /// Render the condition with `$n` placeholders numbered after the ones
+    /// already in `params`, and add its parameters to `params`.
+    fn get_condition_stmt(cond: yoshino_core::Cond, params: &mut Vec<Box<dyn DbData>>) -> String {
+        use yoshino_core::Cond::*;
+        match cond {
+            IsNull { field_name } => format!("{} IS NULL", quote_identifier(&field_name)),
+            IsNotNull { field_name } => format!("{} IS NOT NULL", quote_identifier(&field_name)),
+            TextEqualTo { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, "=", Box::new(value), params),
+            TextNotEqualTo { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, "<>", Box::new(value), params),
+            // folds ASCII letters only, like SQLite's NOCASE, where LOWER would fold all letters
+            TextEqualToIgnoreCase { field_name, value } => {
+                params.push(Box::new(value));
+                format!("{}={}", fold_ascii_case(&quote_identifier(&field_name)), fold_ascii_case(&format!("${}", params.len())))
+            }
+            // ILIKE keeps the matching case-insensitive, as `Cond::text_like` promises
+            TextLike { field_name, pattern } => {
+                params.push(Box::new(pattern));
+                format!("{} ILIKE ${} ESCAPE '\\'", quote_identifier(&field_name), params.len())
+            }
+            TextIn { field_name, values } => {
+                let values = values.into_iter().map(|value| Box::new(value) as Box<dyn DbData>).collect();
+                PostgresAdaptor::get_in_stmt(&field_name, values, params)
+            }
+            IntegerEqualTo { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, "=", Box::new(value), params),
+            IntegerNotEqualTo { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, "<>", Box::new(value), params),
+            IntegerGreaterThan { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, ">", Box::new(value), params),
+            IntegerLessThan { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, "<", Box::new(value), params),
+            IntegerGreaterThanOrEqualTo { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, ">=", Box::new(value), params),
+            IntegerLessThanOrEqualTo { field_name, value } => PostgresAdaptor::get_comparison_stmt(&field_name, "<=", Box::new(value), params),
+            IntegerIn { field_name, values } => {
+                let values = values.into_iter().map(|value| Box::new(value) as Box<dyn DbData>).collect();
+                PostgresAdaptor::get_in_stmt(&field_name, values, params)
+            }
+            IntegerBetween { field_name, low, high } => {
+                params.push(Box::new(low));
+                params.push(Box::new(high));
+                format!("{} BETWEEN ${} AND ${}", quote_identifier(&field_name), params.len() - 1, params.len())
+            }
+            And { left, right } => {
+                let left_stmt = PostgresAdaptor::get_condition_stmt(*left, params);
+                let right_stmt = PostgresAdaptor::get_condition_stmt(*right, params);
+                format!("({}) AND ({})", left_stmt, right_stmt)
+            }
+            Or { left, right } => {
+                let left_stmt = PostgresAdaptor::get_condition_stmt(*left, params);
+                let right_stmt = PostgresAdaptor::get_condition_stmt(*right, params);
+                format!("({}) OR ({})", left_stmt, right_stmt)
+            }
+            Not { cond } => format!("NOT ({})", PostgresAdaptor::get_condition_stmt(*cond, params))
+        }
+    }
+
+    fn get_comparison_stmt(field_name: &str, operator: &str, value: Box<dyn DbData>, params: &mut Vec<Box<dyn DbData>>) -> String {
+        params.push(value);
+        format!("{}{}${}", quote_identifier(field_name), operator, params.len())
+    }
+
+    /// `field IN ($n, ...)` with a placeholder for each value. An empty `IN ()`
+    /// isn't valid SQL, so it becomes a condition that is always false.
+    fn get_in_stmt(field_name: &str, values: Vec<Box<dyn DbData>>, params: &mut Vec<Box<dyn DbData>>) -> String {
+        if values.is_empty() {
+            return "FALSE".to_string();
+        }
+        let mut placeholders = Vec::new();
+        for value in values {
+            params.push(value);
+            placeholders.push(format!("${}", params.len()));
+        }
+        format!("{} IN ({})", quote_identifier(field_name), placeholders.join(", "))
+    }
+
+    /// Build the ORDER BY clause with a leading space, or an empty string if there is no ordering.
+    /// The column names are checked against the fields, as they can't be bound as parameters.
+    fn get_order_by_clause(fields: &[(String, DbDataType)], order: &[(String, SortDir)]) -> Result<String, DbError> {
+        if order.is_empty() {
+            return Ok(String::new());
+        }
+        let mut s = " ORDER BY ".to_string();
+        for (i, (field_name, sort_dir)) in order.iter().enumerate() {
+            if !fields.iter().any(|(name, _)| name == field_name) {
+                return Err(DbError::Other(format!("Unknown field to sort by: {}", field_name)));
+            }
+            if i != 0 {
+                s += ", ";
+            }
+            s += &quote_identifier(field_name);
+            s += match sort_dir {
+                SortDir::Asc => " ASC",
+                SortDir::Desc => " DESC"
+            };
+        }
+        Ok(s)
+    }
+
+    /// `LIMIT $n OFFSET $m` with the values added to `params`. PostgreSQL takes
+    /// signed integers, so larger values are clamped, which is the same as no limit.
+    fn get_limit_clause(limit: u64, offset: u64, params: &mut Vec<Box<dyn DbData>>) -> String {
+        params.push(Box::new(limit.min(i64::MAX as u64) as i64));
+        params.push(Box::new(offset.min(i64::MAX as u64) as i64));
+        format!(" LIMIT ${} OFFSET ${}", params.len() - 1, params.len())
+    }
+
+    /// Convert the parameters to values the PostgreSQL client can bind.
+    fn get_sql_params(params: &[Box<dyn DbData>]) -> Result<Vec<Param>, DbError> {
+        params.iter().enumerate().map(|(i, param)| {
+            let sql_param: Param = match param.db_data_type() {
+                DbDataType::Int => Box::new(<i64 as DbData>::from_boxed_db_data(param)),
+                DbDataType::NullableInt => Box::new(<Option<i64> as DbData>::from_boxed_db_data(param)),
+                DbDataType::RowID => match RowID::from_db_data(param) {
+                    RowID::ID(id) => Box::new(id),
+                    RowID::NEW => Box::new(None::<i64>)
+                },
+                DbDataType::Text => Box::new(<String as DbData>::from_boxed_db_data(param)),
+                DbDataType::NullableText => Box::new(<Option<String> as DbData>::from_boxed_db_data(param)),
+                DbDataType::Float => {
+                    let value = <f64 as DbData>::from_boxed_db_data(param);
+                    PostgresAdaptor::check_not_nan(value, i)?;
+                    Box::new(value)
+                }
+                DbDataType::NullableFloat => {
+                    let value = <Option<f64> as DbData>::from_boxed_db_data(param);
+                    if let Some(value) = value {
+                        PostgresAdaptor::check_not_nan(value, i)?;
+                    }
+                    Box::new(value)
+                }
+                DbDataType::Blob => Box::new(<Vec<u8> as DbData>::from_boxed_db_data(param)),
+                DbDataType::NullableBlob => Box::new(<Option<Vec<u8>> as DbData>::from_boxed_db_data(param))
+            };
+            Ok(sql_param)
+        }).collect()
+    }
+
+    /// NaN is rejected like the SQLite adaptor does, so both store the same values.
+    fn check_not_nan(value: f64, index: usize) -> Result<(), DbError> {
+        if value.is_nan() {
+            return Err(DbError::Other(format!("Cannot bind NaN to parameter {}", index + 1)));
+        }
+        Ok(())
+    }
+
+    fn query_rows(&mut self, stmt: &str, params: &[Box<dyn DbData>]) -> Result<Vec<Row>, DbError> {
+        PostgresAdaptor::query_rows_on(&mut self.client, stmt, params)
+    }
+
+    /// Run the query on the client or on a transaction of it.
+    fn query_rows_on(client: &mut impl GenericClient, stmt: &str, params: &[Box<dyn DbData>]) -> Result<Vec<Row>, DbError> {
+        let sql_params = PostgresAdaptor::get_sql_params(params)?;
+        let param_refs: Vec<&(dyn ToSql + Sync)> = sql_params.iter().map(|param| param.as_ref()).collect();
+        client.query(stmt, &param_refs).map_err(|e| with_sql(postgres_error(e), stmt))
+    }
+
+    /// Execute the statement and return the number of rows it changed.
+    fn execute_stmt(&mut self, stmt: &str, params: &[Box<dyn DbData>]) -> Result<u64, DbError> {
+        let sql_params = PostgresAdaptor::get_sql_params(params)?;
+        let param_refs: Vec<&(dyn ToSql + Sync)> = sql_params.iter().map(|param| param.as_ref()).collect();
+        self.client.execute(stmt, &param_refs).map_err(|e| with_sql(postgres_error(e), stmt))
+    }
+
+    fn query_with_stmt<T: Schema>(&mut self, query_stmt: &str, params: &[Box<dyn DbData>]) -> Result<DbQueryResult<T>, DbError> {
+        let rows = self.query_rows(query_stmt, params)?;
+        Ok(DbQueryResult::new(Box::new(rows.into_iter().map(|row| PostgresAdaptor::read_row::<T>(&row)))))
+    }
+
+    fn read_row<T: Schema>(row: &Row) -> Result<T, DbError> {
+        let mut values: Vec<Box<dyn DbData>> = Vec::new();
+        for (i, (_, field_type)) in T::get_fields().iter().enumerate() {
+            values.push(PostgresAdaptor::read_column(row, i, field_type)?);
+        }
+        T::try_create_with_values(values)
+    }
+
+    /// Read column `i` of the row as a value of the field type.
+    fn read_column(row: &Row, i: usize, field_type: &DbDataType) -> Result<Box<dyn DbData>, DbError> {
+        Ok(match field_type {
+            DbDataType::Int => Box::new(row.try_get::<_, i64>(i).map_err(postgres_error)?),
+            DbDataType::NullableInt => Box::new(row.try_get::<_, Option<i64>>(i).map_err(postgres_error)?),
+            DbDataType::RowID => Box::new(RowID::ID(row.try_get::<_, i64>(i).map_err(postgres_error)?)),
+            DbDataType::Text => Box::new(row.try_get::<_, String>(i).map_err(postgres_error)?),
+            DbDataType::NullableText => Box::new(row.try_get::<_, Option<String>>(i).map_err(postgres_error)?),
+            DbDataType::Float => Box::new(row.try_get::<_, f64>(i).map_err(postgres_error)?),
+            DbDataType::NullableFloat => Box::new(row.try_get::<_, Option<f64>>(i).map_err(postgres_error)?),
+            DbDataType::Blob => Box::new(row.try_get::<_, Vec<u8>>(i).map_err(postgres_error)?),
+            DbDataType::NullableBlob => Box::new(row.try_get::<_, Option<Vec<u8>>>(i).map_err(postgres_error)?)
+        })
+    }
+
+    /// Get the expression of the aggregate over a field of `field_type`. Sums of
+    /// integers and averages are NUMERIC in PostgreSQL, so they are cast back to
+    /// the types they are read as.
+    fn get_aggregate_expr(aggregate: &Aggregate, field_type: &DbDataType) -> String {
+        let expr = format!("{}({})", aggregate.function_name(), quote_identifier(aggregate.field_name()));
+        match (aggregate, field_type) {
+            (Aggregate::Avg(_), _) => format!("{}::DOUBLE PRECISION", expr),
+            (Aggregate::Sum(_), DbDataType::Int | DbDataType::NullableInt | DbDataType::RowID) => format!("{}::BIGINT", expr),
+            _ => expr
+        }
+    }
+
+    fn query_count(&mut self, count_stmt: &str, params: &[Box<dyn DbData>]) -> Result<u64, DbError> {
+        let rows = self.query_rows(count_stmt, params)?;
+        let count: i64 = rows[0].try_get(0).map_err(postgres_error)?;
+        // COUNT(*) is never negative
+        Ok(count as u64)
+    }
+
+    /// Get the condition matching a stored record by its row id field, or else
+    /// by its primary key, for the `action` on it. It comes with the key to
+    /// name in errors.
+    fn get_stored_record_cond<T: Schema>(record: &T, action: &str) -> Result<(yoshino_core::Cond, String), DbError> {
+        let schema_name = T::get_schema_name();
+        match record.try_get_row_id_field()? {
+            Some((field_name, RowID::ID(id))) => Ok((yoshino_core::Cond::integer_equal_to(&field_name, id), format!("{} = {}", field_name, id))),
+            Some((_, RowID::NEW)) => Err(DbError::Other(format!("Cannot {} a new {} record", action, schema_name))),
+            None => {
+                let primary_key = T::get_primary_key().ok_or_else(||
+                    DbError::Other(format!("{} has no row id field or primary key", schema_name)))?;
+                let cond = yoshino_core::Cond::is_primary_key_equal_to(record)?.ok_or_else(||
+                    DbError::Other(format!("Cannot {} a {} record by {}, which is neither text nor an integer", action, schema_name, primary_key)))?;
+                Ok((cond, format!("the same {}", primary_key)))
+            }
+        }
+    }
+
+    /// Delete records matching the condition and return the number of rows deleted.
+    fn delete_rows<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<u64, DbError> {
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let delete_stmt = format!("DELETE FROM {} WHERE {};", quote_identifier(&T::get_schema_name()), cond_stmt);
+        self.execute_stmt(&delete_stmt, &params)
+    }
+
+    /// Update records matching the condition and return the number of rows changed.
+    fn update_rows<T: Schema>(&mut self, cond: yoshino_core::Cond, record: &T) -> Result<u64, DbError> {
+        let mut params = Vec::new();
+        let update_clause = PostgresAdaptor::get_update_clause(&T::get_schema_name(), &T::get_fields(), record.try_get_values()?, &mut params);
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let update_stmt = format!("{} WHERE {};", update_clause, cond_stmt);
+        self.execute_stmt(&update_stmt, &params)
+    }
+}
+
+impl DbAdaptor for PostgresAdaptor {
+    fn create_table_for_schema<T: Schema>(&mut self) -> Result<(), DbError> {
+        let create_table_stmt = PostgresAdaptor::get_create_table_stmt_code(
+            &T::get_schema_name(), &T::get_fields(), &T::get_unique_constraints(), &T::get_foreign_keys(), T::get_primary_key().as_deref());
+        self.client.batch_execute(&create_table_stmt).map_err(postgres_error)
+    }
+
+    /// Insert a record to the database. Return the row id assigned to the new
+    /// record, or 0 if the schema has no row id field.
+    fn insert_record<T: Schema>(&mut self, record: T) -> Result<i64, DbError> {
+        let schema_name = T::get_schema_name();
+        let (insert_stmt, params) = PostgresAdaptor::get_insert_stmt_and_params(&schema_name, &T::get_fields(), record.try_get_values()?);
+        let row_id_field = record.try_get_row_id_field()?;
+        // the sequence is only advanced if the record is inserted
+        let mut transaction = self.client.transaction().map_err(postgres_error)?;
+        let rows = PostgresAdaptor::query_rows_on(&mut transaction, &insert_stmt, &params)?;
+        // an explicit row id doesn't advance the sequence, which would assign it to a new record again
+        if let Some((field_name, RowID::ID(id))) = row_id_field {
+            let (sync_stmt, sync_params) = PostgresAdaptor::get_sync_sequence_stmt_and_params(&schema_name, &field_name, id);
+            PostgresAdaptor::query_rows_on(&mut transaction, &sync_stmt, &sync_params)?;
+        }
+        transaction.commit().map_err(postgres_error)?;
+        match rows.first() {
+            Some(row) => row.try_get(0).map_err(postgres_error),
+            None => Ok(0)
+        }
+    }
+
+    fn query_all<T: Schema>(&mut self) -> Result<DbQueryResult<T>, DbError> {
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &T::get_fields()) + ";";
+        self.query_with_stmt(&query_stmt, &[])
+    }
+
+    fn query_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<DbQueryResult<T>, DbError> {
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &T::get_fields());
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let query_where_cond_stmt = format!("{} WHERE {};", query_stmt, cond_stmt);
+        self.query_with_stmt(&query_where_cond_stmt, &params)
+    }
+
+    fn query_sorted<T: Schema>(&mut self, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError> {
+        let fields = T::get_fields();
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &fields);
+        let order_by_clause = PostgresAdaptor::get_order_by_clause(&fields, &order)?;
+        let query_sorted_stmt = format!("{}{};", query_stmt, order_by_clause);
+        self.query_with_stmt(&query_sorted_stmt, &[])
+    }
+
+    fn query_first_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<Option<T>, DbError> {
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &T::get_fields());
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let query_where_cond_first_stmt = format!("{} WHERE {} LIMIT 1;", query_stmt, cond_stmt);
+        let rows = self.query_rows(&query_where_cond_first_stmt, &params)?;
+        rows.first().map(PostgresAdaptor::read_row).transpose()
+    }
+
+    fn query_with_cond_sorted<T: Schema>(&mut self, cond: yoshino_core::Cond, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError> {
+        let fields = T::get_fields();
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &fields);
+        let order_by_clause = PostgresAdaptor::get_order_by_clause(&fields, &order)?;
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let query_where_cond_sorted_stmt = format!("{} WHERE {}{};", query_stmt, cond_stmt, order_by_clause);
+        self.query_with_stmt(&query_where_cond_sorted_stmt, &params)
+    }
+
+    fn query_limit<T: Schema>(&mut self, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError> {
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &T::get_fields());
+        let mut params = Vec::new();
+        let limit_clause = PostgresAdaptor::get_limit_clause(limit, offset, &mut params);
+        let query_limit_stmt = format!("{}{};", query_stmt, limit_clause);
+        self.query_with_stmt(&query_limit_stmt, &params)
+    }
+
+    fn query_with_cond_limit<T: Schema>(&mut self, cond: yoshino_core::Cond, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError> {
+        let query_stmt = PostgresAdaptor::get_query_clause(&T::get_schema_name(), &T::get_fields());
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let limit_clause = PostgresAdaptor::get_limit_clause(limit, offset, &mut params);
+        let query_where_cond_limit_stmt = format!("{} WHERE {}{};", query_stmt, cond_stmt, limit_clause);
+        self.query_with_stmt(&query_where_cond_limit_stmt, &params)
+    }
+
+    fn count_all<T: Schema>(&mut self) -> Result<u64, DbError> {
+        let count_stmt = format!("SELECT COUNT(*) FROM {};", quote_identifier(&T::get_schema_name()));
+        self.query_count(&count_stmt, &[])
+    }
+
+    fn count_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<u64, DbError> {
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let count_where_cond_stmt = format!("SELECT COUNT(*) FROM {} WHERE {};", quote_identifier(&T::get_schema_name()), cond_stmt);
+        self.query_count(&count_where_cond_stmt, &params)
+    }
+
+    fn aggregate_with_cond<T: Schema, V: ColumnValue>(&mut self, aggregate: Aggregate, cond: yoshino_core::Cond) -> Result<Option<V>, DbError> {
+        let schema_name = T::get_schema_name();
+        let fields = T::get_fields();
+        let (_, field_type) = fields.iter().find(|(name, _)| name == aggregate.field_name())
+            .ok_or_else(|| DbError::Other(format!("Unknown field in {}: {}", schema_name, aggregate.field_name())))?;
+        aggregate.check_types(field_type, &V::column_data_type())?;
+        let mut params = Vec::new();
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let aggregate_stmt = format!("SELECT {} FROM {} WHERE {};", PostgresAdaptor::get_aggregate_expr(&aggregate, field_type), quote_identifier(&schema_name), cond_stmt);
+        let rows = self.query_rows(&aggregate_stmt, &params)?;
+        let value = PostgresAdaptor::read_column(&rows[0], 0, &get_nullable_type(&V::column_data_type()))?;
+        // the aggregate of no records is NULL
+        if value.db_data_ptr().is_null() {
+            Ok(None)
+        } else {
+            V::try_from_column_data(&value).map(Some)
+        }
+    }
+
+    fn delete_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<(), DbError> {
+        self.delete_rows::<T>(cond)?;
+        Ok(())
+    }
+
+    fn delete_all<T: Schema>(&mut self) -> Result<u64, DbError> {
+        let delete_stmt = format!("DELETE FROM {};", quote_identifier(&T::get_schema_name()));
+        self.execute_stmt(&delete_stmt, &[])
+    }
+
+    fn delete_record<T: Schema>(&mut self, record: &T) -> Result<bool, DbError> {
+        let (cond, _) = PostgresAdaptor::get_stored_record_cond(record, "delete")?;
+        let changes = self.delete_rows::<T>(cond)?;
+        Ok(changes > 0)
+    }
+
+    fn update_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond, record: T) -> Result<(), DbError> {
+        self.update_rows(cond, &record)?;
+        Ok(())
+    }
+
+    fn update_fields_with_cond<T: Schema>(&mut self, updates: FieldUpdates, cond: yoshino_core::Cond) -> Result<u64, DbError> {
+        let mut params = Vec::new();
+        let mut assignments = Vec::new();
+        for (field_name, value) in updates.into_field_values::<T>()? {
+            params.push(value);
+            assignments.push(format!("{} = ${}", quote_identifier(&field_name), params.len()));
+        }
+        let cond_stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+        let update_stmt = format!("UPDATE {} SET {} WHERE {};", quote_identifier(&T::get_schema_name()), assignments.join(", "), cond_stmt);
+        self.execute_stmt(&update_stmt, &params)
+    }
+
+    fn update_record<T: Schema>(&mut self, record: &T) -> Result<(), DbError> {
+        let (cond, key) = PostgresAdaptor::get_stored_record_cond(record, "update")?;
+        let changes = self.update_rows(cond, record)?;
+        if changes == 0 {
+            return Err(DbError::Other(format!("No {} record with {}", T::get_schema_name(), key)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test;
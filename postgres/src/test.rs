@@ -0,0 +1,288 @@
+use yoshino_core::{Aggregate, Cond, ForeignKey, OnDelete, RowID, SortDir};
+use yoshino_core::db::{ConstraintKind, DbData, DbDataType, DbError, DbFailure, ViolatedConstraint};
+
+use crate::{classify_failure, PostgresAdaptor};
+
+fn get_test_fields() -> Vec<(String, DbDataType)> {
+    vec![
+        ("row_id".to_string(), DbDataType::RowID),
+        ("name".to_string(), DbDataType::Text),
+        ("desc".to_string(), DbDataType::NullableText),
+        ("counter".to_string(), DbDataType::Int)
+    ]
+}
+const TEST_TABLE_NAME: &str = "test_table_name";
+
+fn get_test_values(row_id: RowID) -> Vec<Box<dyn DbData>> {
+    vec![
+        Box::new(row_id),
+        Box::new("name".to_string()),
+        Box::new(None::<String>),
+        Box::new(3i64)
+    ]
+}
+
+#[test]
+fn test_create_table_stmt_creation() {
+    let stmt = PostgresAdaptor::get_create_table_stmt_code(TEST_TABLE_NAME, &get_test_fields(), &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("row_id" BIGSERIAL PRIMARY KEY, "name" TEXT NOT NULL, "desc" TEXT, "counter" BIGINT NOT NULL);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_other_types() {
+    let fields = vec![
+        ("price".to_string(), DbDataType::Float),
+        ("discount".to_string(), DbDataType::NullableFloat),
+        ("thumbnail".to_string(), DbDataType::Blob),
+        ("original".to_string(), DbDataType::NullableBlob)
+    ];
+    let stmt = PostgresAdaptor::get_create_table_stmt_code(TEST_TABLE_NAME, &fields, &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("price" DOUBLE PRECISION NOT NULL, "discount" DOUBLE PRECISION, "thumbnail" BYTEA NOT NULL, "original" BYTEA);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_constraints() {
+    let unique_constraints = vec![vec!["name".to_string(), "desc".to_string()]];
+    let foreign_keys = vec![ForeignKey {
+        column: "counter".to_string(),
+        referred_schema: "counters".to_string(),
+        referred_column: "id".to_string(),
+        on_delete: OnDelete::SetNull
+    }];
+    let stmt = PostgresAdaptor::get_create_table_stmt_code(TEST_TABLE_NAME, &get_test_fields(), &unique_constraints, &foreign_keys, None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("row_id" BIGSERIAL PRIMARY KEY, "name" TEXT NOT NULL, "desc" TEXT, "counter" BIGINT NOT NULL REFERENCES "counters"("id") ON DELETE SET NULL, UNIQUE("name", "desc"));"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_primary_key() {
+    let fields = vec![
+        ("id".to_string(), DbDataType::Text),
+        ("name".to_string(), DbDataType::Text)
+    ];
+    let stmt = PostgresAdaptor::get_create_table_stmt_code(TEST_TABLE_NAME, &fields, &[], &[], Some("id"));
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("id" TEXT NOT NULL PRIMARY KEY, "name" TEXT NOT NULL);"#);
+}
+
+#[test]
+fn test_insert_stmt_creation() {
+    let (stmt, params) = PostgresAdaptor::get_insert_stmt_and_params(TEST_TABLE_NAME, &get_test_fields(), get_test_values(RowID::NEW));
+    assert_eq!(stmt, r#"INSERT INTO "test_table_name" ("name", "desc", "counter") VALUES ($1, $2, $3) RETURNING "row_id";"#);
+    assert_eq!(params.len(), 3);
+    let (stmt, params) = PostgresAdaptor::get_insert_stmt_and_params(TEST_TABLE_NAME, &get_test_fields(), get_test_values(RowID::ID(7)));
+    assert_eq!(stmt, r#"INSERT INTO "test_table_name" ("row_id", "name", "desc", "counter") VALUES ($1, $2, $3, $4) RETURNING "row_id";"#);
+    assert_eq!(params.len(), 4);
+    let fields = vec![("row_id".to_string(), DbDataType::RowID)];
+    let (stmt, _) = PostgresAdaptor::get_insert_stmt_and_params(TEST_TABLE_NAME, &fields, vec![Box::new(RowID::NEW)]);
+    assert_eq!(stmt, r#"INSERT INTO "test_table_name" DEFAULT VALUES RETURNING "row_id";"#);
+}
+
+#[test]
+fn test_update_clause() {
+    let mut params = Vec::new();
+    let clause = PostgresAdaptor::get_update_clause(TEST_TABLE_NAME, &get_test_fields(), get_test_values(RowID::ID(1)), &mut params);
+    assert_eq!(clause, r#"UPDATE "test_table_name" SET "name" = $1, "desc" = $2, "counter" = $3"#);
+    let cond_stmt = PostgresAdaptor::get_condition_stmt(Cond::integer_equal_to("row_id", 1), &mut params);
+    assert_eq!(cond_stmt, r#""row_id"=$4"#);
+    assert_eq!(params.len(), 4);
+}
+
+#[test]
+fn test_condition_placeholders() {
+    let cond = Cond::or(
+        Cond::and(
+            Cond::text_starts_with("name", "a_"),
+            Cond::not(Cond::integer_in("counter", vec![1, 2]))
+        ),
+        Cond::and(Cond::is_null("desc"), Cond::text_in("name", vec![]))
+    );
+    let mut params = Vec::new();
+    let stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+    assert_eq!(stmt, r#"(("name" ILIKE $1 ESCAPE '\') AND (NOT ("counter" IN ($2, $3)))) OR (("desc" IS NULL) AND (FALSE))"#);
+    assert_eq!(String::from_boxed_db_data(&params[0]), "a\\_%");
+    assert_eq!(i64::from_boxed_db_data(&params[2]), 2);
+}
+
+#[test]
+fn test_text_comparison_conditions() {
+    let mut params = Vec::new();
+    let cond = Cond::or(Cond::text_not_equal_to("name", "a"), Cond::text_equal_ci("name", "Admin"));
+    let stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+    assert_eq!(stmt, r#"("name"<>$1) OR (translate("name", 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz')=translate($2, 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz'))"#);
+    assert_eq!(String::from_boxed_db_data(&params[1]), "Admin");
+}
+
+#[test]
+fn test_between_condition() {
+    let mut params = Vec::new();
+    let cond = Cond::and(Cond::integer_between("counter", 1, 5), Cond::integer_less_than("row_id", 9));
+    let stmt = PostgresAdaptor::get_condition_stmt(cond, &mut params);
+    assert_eq!(stmt, r#"("counter" BETWEEN $1 AND $2) AND ("row_id"<$3)"#);
+    assert_eq!(params.iter().map(i64::from_boxed_db_data).collect::<Vec<_>>(), vec![1, 5, 9]);
+}
+
+#[test]
+fn test_limit_and_order_by_clause() {
+    let mut params = Vec::new();
+    PostgresAdaptor::get_condition_stmt(Cond::integer_greater_than("counter", 0), &mut params);
+    assert_eq!(PostgresAdaptor::get_limit_clause(10, u64::MAX, &mut params), " LIMIT $2 OFFSET $3");
+    assert_eq!(i64::from_boxed_db_data(&params[2]), i64::MAX);
+    let order = vec![("counter".to_string(), SortDir::Desc)];
+    assert_eq!(PostgresAdaptor::get_order_by_clause(&get_test_fields(), &order).unwrap(), r#" ORDER BY "counter" DESC"#);
+    let unknown = vec![("counter; DROP TABLE x".to_string(), SortDir::Asc)];
+    assert!(PostgresAdaptor::get_order_by_clause(&get_test_fields(), &unknown).is_err());
+}
+
+#[test]
+fn test_nan_is_rejected() {
+    let params: Vec<Box<dyn DbData>> = vec![Box::new(1.5f64), Box::new(Some(f64::NAN))];
+    assert!(PostgresAdaptor::get_sql_params(&params[..1]).is_ok());
+    assert!(PostgresAdaptor::get_sql_params(&params).is_err());
+}
+
+#[test]
+fn test_aggregate_expr() {
+    assert_eq!(PostgresAdaptor::get_aggregate_expr(&Aggregate::Sum("counter"), &DbDataType::Int), r#"SUM("counter")::BIGINT"#);
+    assert_eq!(PostgresAdaptor::get_aggregate_expr(&Aggregate::Sum("price"), &DbDataType::Float), r#"SUM("price")"#);
+    assert_eq!(PostgresAdaptor::get_aggregate_expr(&Aggregate::Avg("counter"), &DbDataType::Int), r#"AVG("counter")::DOUBLE PRECISION"#);
+    assert_eq!(PostgresAdaptor::get_aggregate_expr(&Aggregate::Max("name"), &DbDataType::Text), r#"MAX("name")"#);
+}
+
+#[test]
+fn test_keyword_identifiers() {
+    let fields = vec![("id".to_string(), DbDataType::RowID), ("select".to_string(), DbDataType::Text)];
+    let stmt = PostgresAdaptor::get_create_table_stmt_code("order", &fields, &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "order" ("id" BIGSERIAL PRIMARY KEY, "select" TEXT NOT NULL);"#);
+    assert_eq!(PostgresAdaptor::get_query_clause("order", &fields), r#"SELECT "id", "select" FROM "order""#);
+    let mut params = Vec::new();
+    assert_eq!(PostgresAdaptor::get_condition_stmt(Cond::text_equal_to("say \"hi\"", "x"), &mut params), r#""say ""hi"""=$1"#);
+}
+
+fn failure(sqlstate: &str) -> DbFailure {
+    DbFailure { code: 0, extended_code: 0, sqlstate: Some(sqlstate.to_string()), message: "failed".to_string(), sql: None }
+}
+
+fn constraint(table: &str, column: Option<&str>, name: &str) -> ViolatedConstraint {
+    ViolatedConstraint {
+        kind: ConstraintKind::Other,
+        table: Some(table.to_string()),
+        columns: column.map(|column| vec![column.to_string()]).unwrap_or_default(),
+        name: Some(name.to_string())
+    }
+}
+
+#[test]
+fn test_classify_failure() {
+    let detail = Some("Key (first_name, last_name)=(Alice, Smith) already exists.");
+    match classify_failure(failure("23505"), constraint("y_member", None, "y_member_first_name_last_name_key"), detail) {
+        DbError::ConstraintViolation(_, constraint) => assert_eq!(*constraint, ViolatedConstraint {
+            kind: ConstraintKind::Unique,
+            table: Some("y_member".to_string()),
+            columns: vec!["first_name".to_string(), "last_name".to_string()],
+            name: None
+        }),
+        other => panic!("a unique violation should be a constraint violation: {:?}", other)
+    }
+    let detail = Some("Key (user_name)=(alice) already exists.");
+    match classify_failure(failure("23505"), constraint("y_member", None, "y_member_pkey"), detail) {
+        DbError::ConstraintViolation(_, constraint) => {
+            assert_eq!(constraint.kind, ConstraintKind::PrimaryKey);
+            assert_eq!(constraint.columns, vec!["user_name".to_string()]);
+        }
+        other => panic!("a primary key violation should be a constraint violation: {:?}", other)
+    }
+    match classify_failure(failure("23502"), constraint("y_member", Some("first_name"), "unused"), None) {
+        DbError::ConstraintViolation(_, constraint) => {
+            assert_eq!(constraint.kind, ConstraintKind::NotNull);
+            assert_eq!((constraint.columns, constraint.name), (vec!["first_name".to_string()], None));
+        }
+        other => panic!("a not null violation should be a constraint violation: {:?}", other)
+    }
+    match classify_failure(failure("23514"), constraint("y_item", None, "positive_price"), None) {
+        DbError::ConstraintViolation(_, constraint) => {
+            assert_eq!(constraint.kind, ConstraintKind::Check);
+            assert_eq!(constraint.name.as_deref(), Some("positive_price"));
+        }
+        other => panic!("a check violation should be a constraint violation: {:?}", other)
+    }
+    assert!(matches!(classify_failure(failure("23503"), constraint("y_item", None, "fk"), None),
+        DbError::ConstraintViolation(_, constraint) if constraint.kind == ConstraintKind::ForeignKey));
+    assert!(matches!(classify_failure(failure("40001"), constraint("", None, ""), None), DbError::Busy(_)));
+    assert!(matches!(classify_failure(failure("40P01"), constraint("", None, ""), None), DbError::Busy(_)));
+    assert!(matches!(classify_failure(failure("57014"), constraint("", None, ""), None), DbError::Interrupted));
+    assert!(matches!(classify_failure(failure("25006"), constraint("", None, ""), None), DbError::ReadOnly(_)));
+    assert!(matches!(classify_failure(failure("58030"), constraint("", None, ""), None), DbError::Io(_)));
+    let error = classify_failure(failure("42P01"), constraint("", None, ""), None);
+    assert!(matches!(error, DbError::DatabaseFailure(_)));
+    assert_eq!(error.to_string(), "Database error 42P01: failed");
+}
+
+#[test]
+fn test_sync_sequence_stmt() {
+    let (stmt, params) = PostgresAdaptor::get_sync_sequence_stmt_and_params("order", "Row ID", 7);
+    assert_eq!(stmt, "SELECT setval(seq, $3) FROM (SELECT pg_get_serial_sequence($1, $2)::regclass AS seq) AS s \
+        WHERE COALESCE(pg_sequence_last_value(seq), 0) < $3;");
+    assert_eq!(String::from_boxed_db_data(&params[0]), r#""order""#);
+    assert_eq!(String::from_boxed_db_data(&params[1]), "Row ID");
+    assert_eq!(i64::from_boxed_db_data(&params[2]), 7);
+}
+
+mod live_test {
+    use yoshino_core::db::{ConstraintKind, DbAdaptor, DbError};
+    use yoshino_core::{Cond, IntegerField, RowID, TextField};
+    use yoshino_derive::Schema;
+    use crate::PostgresAdaptor;
+
+    #[derive(Schema)]
+    #[yoshino(table = "yoshino_live_member")]
+    struct Member {
+        pub id: RowID,
+        #[yoshino(unique)]
+        pub name: String,
+        pub age: i64
+    }
+
+    /// Connect to the server at `YOSHINO_PG_URL`, or `None` to skip the test
+    /// if it's not set.
+    fn connect() -> Option<PostgresAdaptor> {
+        let url = std::env::var("YOSHINO_PG_URL").ok()?;
+        Some(PostgresAdaptor::connect(&url).unwrap())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut adaptor = match connect() {
+            Some(adaptor) => adaptor,
+            None => return
+        };
+        adaptor.drop_table_for_schema::<Member>(true).unwrap();
+        adaptor.create_table_for_schema::<Member>().unwrap();
+        let alice = adaptor.insert_record(Member { id: RowID::NEW, name: "Alice".to_string(), age: 30 }).unwrap();
+        // a new record after an explicit row id gets the next one
+        assert_eq!(adaptor.insert_record(Member { id: RowID::ID(100), name: "Bob".to_string(), age: 40 }).unwrap(), 100);
+        assert_eq!(adaptor.insert_record(Member { id: RowID::NEW, name: "Carol".to_string(), age: 50 }).unwrap(), 101);
+        // an explicit row id below the sequence doesn't move it back
+        adaptor.insert_record(Member { id: RowID::ID(alice + 1), name: "Dave".to_string(), age: 60 }).unwrap();
+        assert_eq!(adaptor.insert_record(Member { id: RowID::NEW, name: "Erin".to_string(), age: 70 }).unwrap(), 102);
+
+        let mut bob = adaptor.query_first_with_cond::<Member>(Cond::text_equal_ci("name", "BOB")).unwrap().unwrap();
+        assert_eq!((bob.id, bob.age), (RowID::ID(100), 40));
+        assert!(adaptor.query_first_with_cond::<Member>(Cond::text_equal_ci("name", "bOB ")).unwrap().is_none());
+        bob.age = 41;
+        adaptor.update_record(&bob).unwrap();
+        assert_eq!(adaptor.count_with_cond::<Member>(Cond::integer_equal_to("age", 41)).unwrap(), 1);
+        assert!(adaptor.delete_record(&bob).unwrap());
+        assert_eq!(adaptor.count_all::<Member>().unwrap(), 4);
+
+        match adaptor.insert_record(Member { id: RowID::NEW, name: "Alice".to_string(), age: 31 }) {
+            Err(DbError::ConstraintViolation(failure, constraint)) => {
+                assert_eq!(failure.sqlstate.as_deref(), Some("23505"));
+                assert_eq!(constraint.kind, ConstraintKind::Unique);
+                assert_eq!(constraint.columns, vec!["name".to_string()]);
+            }
+            other => panic!("a duplicate name should violate the unique constraint: {:?}", other)
+        }
+        // the failed insert rolled its transaction back, so the connection can go on
+        assert_eq!(adaptor.insert_record(Member { id: RowID::ID(200), name: "Frank".to_string(), age: 80 }).unwrap(), 200);
+        adaptor.drop_table_for_schema::<Member>(false).unwrap();
+    }
+}
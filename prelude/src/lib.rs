@@ -32,8 +32,12 @@
 //! For more usages, please refer to this document and the examples.
 
 pub use yoshino_core;
-pub use yoshino_core::Cond;
-pub use yoshino_core::Schema;
-pub use yoshino_core::{RowID, NullableIntegerField, NullableTextField, IntegerField, TextField, FloatField};
-pub use yoshino_core::db::{DbAdaptor, DbData, DbError, DbQueryResult, DbDataType};
-pub use yoshino_derive::Schema;
\ No newline at end of file
+pub use yoshino_core::{Aggregate, Cond, FieldUpdates, SortDir};
+pub use yoshino_core::{Schema, ForeignKey, OnDelete, Collation};
+pub use yoshino_core::{RowID, Timestamp, NullableIntegerField, NullableTextField, IntegerField, TextField, FloatField, NullableFloatField, BlobField, NullableBlobField, ColumnValue, IntegerEnum};
+pub use yoshino_core::db::{ConstraintKind, DbAdaptor, DbData, DbError, DbFailure, DbQueryResult, DbDataType, ViolatedConstraint};
+pub use yoshino_derive::{Schema, SchemaEnum};
+#[cfg(feature = "decimal")]
+pub use yoshino_core::Decimal;
+#[cfg(feature = "uuid")]
+pub use yoshino_core::Uuid;
\ No newline at end of file
@@ -0,0 +1,110 @@
+//! An adaptor for async code, which runs the SQLite calls on blocking threads.
+use std::sync::{Arc, Mutex};
+use yoshino_core::{Cond, Schema};
+use yoshino_core::db::{DbAdaptor, DbError};
+use crate::SQLiteAdaptor;
+
+/// A `SQLiteAdaptor` for async code. Each operation runs on a blocking thread
+/// of the tokio runtime with `spawn_blocking`, so it doesn't block the async
+/// tasks. Query results are collected into a `Vec`, as the rows are read on
+/// that thread.
+///
+/// Cloning the adaptor shares its connection, and operations on it run one at
+/// a time. Use one adaptor per connection to run them concurrently.
+///
+/// It has async methods for the record operations of `DbAdaptor`. Anything
+/// else, e.g. a transaction, a savepoint or a cached statement, runs in a
+/// closure passed to `run`, which gets the `SQLiteAdaptor` itself.
+#[derive(Clone)]
+pub struct AsyncSQLiteAdaptor {
+    adaptor: Arc<Mutex<SQLiteAdaptor>>
+}
+
+impl AsyncSQLiteAdaptor {
+    /// Open the SQLite database file like `SQLiteAdaptor::open`.
+    pub async fn open(filename: &str) -> Result<AsyncSQLiteAdaptor, DbError> {
+        let filename = filename.to_string();
+        let adaptor = spawn_blocking(move || SQLiteAdaptor::open(&filename)).await?;
+        Ok(AsyncSQLiteAdaptor::from_adaptor(adaptor))
+    }
+
+    /// Use an opened adaptor from async code.
+    pub fn from_adaptor(adaptor: SQLiteAdaptor) -> AsyncSQLiteAdaptor {
+        AsyncSQLiteAdaptor { adaptor: Arc::new(Mutex::new(adaptor)) }
+    }
+
+    /// Run `f` with the adaptor on a blocking thread, e.g. to run several
+    /// operations in a transaction. The connection stays locked until `f`
+    /// returns, so other operations on it wait for that.
+    pub async fn run<R, F>(&self, f: F) -> Result<R, DbError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut SQLiteAdaptor) -> Result<R, DbError> + Send + 'static
+    {
+        let adaptor = self.adaptor.clone();
+        spawn_blocking(move || {
+            // a poisoned lock only means an operation panicked, the connection is still valid
+            let mut adaptor = adaptor.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut adaptor)
+        }).await
+    }
+
+    /// Create the table of the schema, like `DbAdaptor::create_table_for_schema`,
+    /// through `run`.
+    pub async fn create_table_for_schema<T: Schema>(&self) -> Result<(), DbError> {
+        self.run(|adaptor| adaptor.create_table_for_schema::<T>()).await
+    }
+
+    /// Insert a record and return its row id, like `DbAdaptor::insert_record`,
+    /// through `run`.
+    pub async fn insert_record<T: Schema + Send + 'static>(&self, record: T) -> Result<i64, DbError> {
+        self.run(move |adaptor| adaptor.insert_record(record)).await
+    }
+
+    /// Read all records of the schema through `run`.
+    pub async fn query_all<T: Schema + Send + 'static>(&self) -> Result<Vec<T>, DbError> {
+        self.run(|adaptor| adaptor.query_all::<T>()?.collect_all()).await
+    }
+
+    /// Read the records matching `cond` through `run`.
+    pub async fn query_with_cond<T: Schema + Send + 'static>(&self, cond: Cond) -> Result<Vec<T>, DbError> {
+        self.run(move |adaptor| adaptor.query_with_cond::<T>(cond)?.collect_all()).await
+    }
+
+    /// Read the first record matching `cond`, if any, through `run`.
+    pub async fn query_first_with_cond<T: Schema + Send + 'static>(&self, cond: Cond) -> Result<Option<T>, DbError> {
+        self.run(move |adaptor| adaptor.query_first_with_cond::<T>(cond)).await
+    }
+
+    /// Count the records matching `cond` through `run`.
+    pub async fn count_with_cond<T: Schema>(&self, cond: Cond) -> Result<u64, DbError> {
+        self.run(move |adaptor| adaptor.count_with_cond::<T>(cond)).await
+    }
+
+    /// Delete the records matching `cond` through `run`.
+    pub async fn delete_with_cond<T: Schema>(&self, cond: Cond) -> Result<(), DbError> {
+        self.run(move |adaptor| adaptor.delete_with_cond::<T>(cond)).await
+    }
+
+    /// Delete the stored record with the key of `record`, like
+    /// `DbAdaptor::delete_record`, through `run`. Returns whether it existed.
+    pub async fn delete_record<T: Schema + Send + 'static>(&self, record: T) -> Result<bool, DbError> {
+        self.run(move |adaptor| adaptor.delete_record(&record)).await
+    }
+
+    /// Set the fields but the row id of the records matching `cond` to those of
+    /// `record`, like `DbAdaptor::update_with_cond`, through `run`.
+    pub async fn update_with_cond<T: Schema + Send + 'static>(&self, cond: Cond, record: T) -> Result<(), DbError> {
+        self.run(move |adaptor| adaptor.update_with_cond(cond, record)).await
+    }
+
+    /// Update the stored record with the key of `record` through `run`.
+    pub async fn update_record<T: Schema + Send + 'static>(&self, record: T) -> Result<(), DbError> {
+        self.run(move |adaptor| adaptor.update_record(&record)).await
+    }
+}
+
+async fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> Result<R, DbError> + Send + 'static) -> Result<R, DbError> {
+    tokio::task::spawn_blocking(f).await
+        .map_err(|e| DbError::Other(format!("Database operation failed to complete: {}", e)))?
+}
@@ -0,0 +1,89 @@
+//! Attaching other database files to a connection, and accessing the tables
+//! of a schema in them.
+use yoshino_core::{Cond, Schema};
+use yoshino_core::db::{DbError, DbQueryResult};
+use crate::{quote_identifier, SQLiteAdaptor};
+
+impl SQLiteAdaptor {
+    /// Attach the database file at `path` to the connection as `alias`,
+    /// creating the file if it doesn't exist. Its tables are accessed with the
+    /// `_in` methods, e.g. `query_with_cond_in`.
+    ///
+    /// The alias must be an identifier: ASCII letters, digits and `_`, not
+    /// starting with a digit.
+    pub fn attach(&mut self, path: &str, alias: &str) -> Result<(), DbError> {
+        check_alias(alias)?;
+        self.execute_raw("ATTACH DATABASE ?1 AS ?2;", &[&path.to_string(), &alias.to_string()])?;
+        Ok(())
+    }
+
+    /// Detach the database attached as `alias`. It fails while a query on it
+    /// is still being read.
+    pub fn detach(&mut self, alias: &str) -> Result<(), DbError> {
+        check_alias(alias)?;
+        self.execute_raw("DETACH DATABASE ?1;", &[&alias.to_string()])?;
+        Ok(())
+    }
+
+    /// Create the table for the schema in the attached `database`, like `create_table_for_schema`.
+    pub fn create_table_for_schema_in<T: Schema>(&mut self, database: &str) -> Result<(), DbError> {
+        let create_table_stmt = SQLiteAdaptor::get_create_table_stmt_code(
+            &get_qualified_table::<T>(database)?, &T::get_fields(), &T::get_unique_constraints(), &T::get_foreign_keys(), &T::get_collations(), T::get_primary_key().as_deref());
+        let stmt = self.prepare_stmt(&create_table_stmt)?;
+        self.run_to_completion(stmt)
+    }
+
+    /// Insert a record into the table of the schema in the attached `database`,
+    /// like `insert_record`, and return its row id.
+    pub fn insert_record_in<T: Schema>(&mut self, database: &str, record: T) -> Result<i64, DbError> {
+        let insert_record_stmt = SQLiteAdaptor::get_insert_value_stmt_code(&get_qualified_table::<T>(database)?, &T::get_fields());
+        self.insert_with_stmt(&insert_record_stmt, record)
+    }
+
+    /// Query all records of the schema in the attached `database`.
+    pub fn query_all_in<T: Schema>(&mut self, database: &str) -> Result<DbQueryResult<T>, DbError> {
+        let query_stmt = SQLiteAdaptor::get_query_clause(&get_qualified_table::<T>(database)?, &T::get_fields()) + ";";
+        self.query_with_stmt(&query_stmt, &[])
+    }
+
+    /// Query the records of the schema in the attached `database` that match the condition.
+    pub fn query_with_cond_in<T: Schema>(&mut self, database: &str, cond: Cond) -> Result<DbQueryResult<T>, DbError> {
+        let query_stmt = SQLiteAdaptor::get_query_clause(&get_qualified_table::<T>(database)?, &T::get_fields());
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        self.query_with_stmt(&format!("{} WHERE {};", query_stmt, cond_stmt), &cond_params)
+    }
+
+    /// Count the records of the schema in the attached `database` that match the condition.
+    pub fn count_with_cond_in<T: Schema>(&mut self, database: &str, cond: Cond) -> Result<u64, DbError> {
+        let count_clause = SQLiteAdaptor::get_count_clause(&get_qualified_table::<T>(database)?);
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        self.query_count(&format!("{} WHERE {};", count_clause, cond_stmt), &cond_params)
+    }
+
+    /// Delete the records of the schema in the attached `database` that match
+    /// the condition, and return the number of records deleted.
+    pub fn delete_with_cond_in<T: Schema>(&mut self, database: &str, cond: Cond) -> Result<u64, DbError> {
+        let delete_clause = SQLiteAdaptor::get_delete_clause(&get_qualified_table::<T>(database)?);
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        let db_handler = self.db_handler;
+        self.execute_cached(&format!("{} WHERE {};", delete_clause, cond_stmt), &cond_params, |_| unsafe {
+            libsqlite3_sys::sqlite3_changes(db_handler) as u64
+        })
+    }
+}
+
+/// The table of the schema in `database`, as `"database"."table"`.
+fn get_qualified_table<T: Schema>(database: &str) -> Result<String, DbError> {
+    check_alias(database)?;
+    Ok(format!("{}.{}", quote_identifier(database), quote_identifier(&T::get_schema_name())))
+}
+
+fn check_alias(alias: &str) -> Result<(), DbError> {
+    let is_identifier = alias.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_identifier {
+        Ok(())
+    } else {
+        Err(DbError::Other(format!("Invalid database alias: {}", alias)))
+    }
+}
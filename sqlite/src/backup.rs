@@ -0,0 +1,66 @@
+//! Online backup of a database with the SQLite backup API.
+use std::os::raw::c_int;
+use yoshino_core::db::DbError;
+use crate::{sqlite_error, OpenFlags, SQLiteAdaptor};
+
+/// Number of pages copied in each step of a backup.
+const BACKUP_PAGES_PER_STEP: c_int = 64;
+
+/// Progress of a backup, reported after each step of copying pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BackupProgress {
+    /// pages still to be copied
+    pub remaining_pages: u32,
+    /// pages in the source database
+    pub total_pages: u32
+}
+
+impl SQLiteAdaptor {
+    /// Copy the database to the file at `path` while it's still in use,
+    /// creating the file if it doesn't exist and replacing its content otherwise.
+    /// `progress` is called after each chunk of pages is copied.
+    ///
+    /// An in-memory database can be backed up to a file too.
+    pub fn backup_to(&self, path: &str, progress: Option<impl FnMut(BackupProgress)>) -> Result<(), DbError> {
+        let destination = SQLiteAdaptor::open(path)?;
+        run_backup(&destination, self, progress)
+    }
+
+    /// Replace the content of the database with the database file at `path`,
+    /// e.g. to load a backup made with `backup_to` into an in-memory database.
+    /// `progress` is called after each chunk of pages is copied.
+    pub fn restore_from(&mut self, path: &str, progress: Option<impl FnMut(BackupProgress)>) -> Result<(), DbError> {
+        let source = SQLiteAdaptor::open_with_flags(path, OpenFlags::READONLY)?;
+        run_backup(self, &source, progress)
+    }
+}
+
+fn run_backup(destination: &SQLiteAdaptor, source: &SQLiteAdaptor, mut progress: Option<impl FnMut(BackupProgress)>) -> Result<(), DbError> {
+    let main = c"main";
+    unsafe {
+        let backup = libsqlite3_sys::sqlite3_backup_init(destination.db_handler, main.as_ptr(), source.db_handler, main.as_ptr());
+        if backup.is_null() {
+            // the error is recorded on the destination connection
+            return Err(sqlite_error(destination.db_handler, libsqlite3_sys::sqlite3_errcode(destination.db_handler)));
+        }
+        loop {
+            let return_value = libsqlite3_sys::sqlite3_backup_step(backup, BACKUP_PAGES_PER_STEP);
+            if let Some(progress) = progress.as_mut() {
+                progress(BackupProgress {
+                    remaining_pages: libsqlite3_sys::sqlite3_backup_remaining(backup) as u32,
+                    total_pages: libsqlite3_sys::sqlite3_backup_pagecount(backup) as u32
+                });
+            }
+            match return_value {
+                libsqlite3_sys::SQLITE_OK => {}
+                libsqlite3_sys::SQLITE_DONE => break,
+                error_code => {
+                    libsqlite3_sys::sqlite3_backup_finish(backup);
+                    return Err(sqlite_error(destination.db_handler, error_code));
+                }
+            }
+        }
+        db_try!(destination.db_handler, libsqlite3_sys::sqlite3_backup_finish(backup));
+    }
+    Ok(())
+}
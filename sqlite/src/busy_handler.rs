@@ -0,0 +1,54 @@
+//! Callback to decide whether to retry when the database is locked.
+use std::os::raw::{c_int, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use yoshino_core::db::DbError;
+use crate::SQLiteAdaptor;
+
+/// Callback that is given the number of times it has been called for the same
+/// lock, and returns whether to wait and try again.
+pub type BusyHandler = Box<dyn FnMut(i32) -> bool + Send>;
+
+impl SQLiteAdaptor {
+    /// Call `handler` when the database is locked by another connection, with
+    /// the number of times it has been called for the same lock, starting at 0.
+    /// If it returns `true`, the lock is tried again, so it should sleep before
+    /// returning, e.g. with a backoff. If it returns `false`, the operation
+    /// fails with `DbError::Busy`. `None` removes the handler, so a locked
+    /// database fails immediately.
+    ///
+    /// It replaces the busy timeout, which `set_busy_timeout` sets instead of
+    /// the handler. A panic in the handler is caught and gives up.
+    #[doc(alias = "sqlite3_busy_handler")]
+    pub fn set_busy_handler(&mut self, handler: Option<BusyHandler>) -> Result<(), DbError> {
+        // boxed again so SQLite gets a thin pointer, which stays valid when the adaptor moves
+        let mut handler = handler.map(Box::new);
+        unsafe {
+            match handler.as_mut() {
+                Some(handler) => {
+                    let context = &mut **handler as *mut BusyHandler as *mut c_void;
+                    db_try!(self.db_handler, libsqlite3_sys::sqlite3_busy_handler(self.db_handler, Some(call_busy_handler), context));
+                }
+                None => self.clear_busy_handler()
+            }
+        }
+        // the old handler is only dropped once SQLite no longer refers to it
+        self.busy_handler = handler;
+        Ok(())
+    }
+
+    /// Stop calling the busy handler, so it can be dropped.
+    pub(crate) fn clear_busy_handler(&self) {
+        unsafe {
+            libsqlite3_sys::sqlite3_busy_handler(self.db_handler, None, ptr::null_mut());
+        }
+    }
+}
+
+unsafe extern "C" fn call_busy_handler(context: *mut c_void, count: c_int) -> c_int {
+    let handler = &mut *(context as *mut BusyHandler);
+    match catch_unwind(AssertUnwindSafe(|| handler(count))) {
+        Ok(true) => 1,
+        _ => 0
+    }
+}
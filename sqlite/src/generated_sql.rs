@@ -0,0 +1,30 @@
+//! The SQL the adaptor generates for a schema, to see what a call runs.
+use yoshino_core::{Cond, Schema};
+use crate::{quote_identifier, SQLiteAdaptor};
+
+impl SQLiteAdaptor {
+    /// The statement `create_table_for_schema` runs to create the table of the schema.
+    pub fn sql_for_create_table<T: Schema>() -> String {
+        SQLiteAdaptor::get_create_table_stmt_code(
+            &quote_identifier(&T::get_schema_name()), &T::get_fields(), &T::get_unique_constraints(), &T::get_foreign_keys(), &T::get_collations(), T::get_primary_key().as_deref())
+    }
+
+    /// The statement `insert_record` runs to insert a record of the schema,
+    /// with a `?N` parameter for each field.
+    pub fn sql_for_insert<T: Schema>() -> String {
+        SQLiteAdaptor::get_insert_value_stmt_code(&quote_identifier(&T::get_schema_name()), &T::get_fields())
+    }
+
+    /// The statement `query_all` runs to query the records of the schema.
+    pub fn sql_for_query_all<T: Schema>() -> String {
+        SQLiteAdaptor::get_query_clause(&quote_identifier(&T::get_schema_name()), &T::get_fields()) + ";"
+    }
+
+    /// The statement `query_with_cond` runs to query the records of the schema
+    /// that match the condition, with a `?N` parameter for each value in it.
+    /// The values are `cond.bind_values()`, or use `set_trace` to see them.
+    pub fn sql_for_query_with_cond<T: Schema>(cond: &Cond) -> String {
+        let cond_stmt = cond.to_sql(&mut 1);
+        format!("{} WHERE {};", SQLiteAdaptor::get_query_clause(&quote_identifier(&T::get_schema_name()), &T::get_fields()), cond_stmt)
+    }
+}
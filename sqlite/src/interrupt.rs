@@ -0,0 +1,72 @@
+//! Interrupting the statements running on a connection from another thread.
+use std::sync::{Arc, Mutex, MutexGuard};
+use libsqlite3_sys::sqlite3;
+use crate::SQLiteAdaptor;
+
+/// A handle to interrupt the statements running on a connection, e.g. to
+/// cancel a long query from another thread. A query that is interrupted fails
+/// with `DbError::Interrupted`.
+///
+/// Cloning the handle refers to the same connection. Once the adaptor is
+/// dropped, or returned to the `SQLitePool` it was taken from, `interrupt`
+/// does nothing.
+#[derive(Clone)]
+pub struct SQLiteInterruptHandle {
+    db_handler: Arc<Mutex<InterruptTarget>>
+}
+
+/// The connection to interrupt, null once it's closed.
+struct InterruptTarget(*mut sqlite3);
+
+// SAFETY: the pointer is only used to call `sqlite3_interrupt`, which may be
+// called from any thread, and it's cleared under the lock before the
+// connection is closed.
+unsafe impl Send for InterruptTarget {}
+
+impl SQLiteInterruptHandle {
+    pub(crate) fn new(db_handler: *mut sqlite3) -> SQLiteInterruptHandle {
+        SQLiteInterruptHandle { db_handler: Arc::new(Mutex::new(InterruptTarget(db_handler))) }
+    }
+
+    /// Interrupt the statements running on the connection. They stop at the
+    /// next row or step, and statements started afterwards run as usual.
+    #[doc(alias = "sqlite3_interrupt")]
+    pub fn interrupt(&self) {
+        let target = self.lock_target();
+        if !target.0.is_null() {
+            unsafe {
+                libsqlite3_sys::sqlite3_interrupt(target.0);
+            }
+        }
+    }
+
+    /// Whether the connection is closed, so `interrupt` does nothing.
+    pub fn is_closed(&self) -> bool {
+        self.lock_target().0.is_null()
+    }
+
+    /// Forget the connection before it's closed.
+    pub(crate) fn close(&self) {
+        self.lock_target().0 = std::ptr::null_mut();
+    }
+
+    fn lock_target(&self) -> MutexGuard<'_, InterruptTarget> {
+        // a poisoned lock only means another thread panicked, the pointer is still valid
+        self.db_handler.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SQLiteAdaptor {
+    /// Get a handle to interrupt the statements running on the connection from
+    /// another thread, e.g. to cancel a query over a large table.
+    pub fn interrupt_handle(&self) -> SQLiteInterruptHandle {
+        self.interrupt_handle.clone()
+    }
+
+    /// Detach the handles given out so far, so they can't interrupt whoever
+    /// uses the connection next. Later handles refer to the connection as usual.
+    pub(crate) fn renew_interrupt_handle(&mut self) {
+        self.interrupt_handle.close();
+        self.interrupt_handle = SQLiteInterruptHandle::new(self.db_handler);
+    }
+}
@@ -1,204 +1,1032 @@
 //! SQLite adaptor for Project Yoshino
-use yoshino_core::Schema;
-use yoshino_core::db::{DbAdaptor, DbData, DbDataType, DbError, DbQueryResult};
+use yoshino_core::{Aggregate, ColumnValue, FieldUpdates, Collation, ForeignKey, OnDelete, RowID, Schema, SortDir};
+use yoshino_core::db::{quote_identifier, quote_identifiers, ConstraintKind, DbAdaptor, DbData, DbDataType, DbError, DbFailure, DbQueryResult, ViolatedConstraint};
 use libsqlite3_sys::{sqlite3, sqlite3_stmt};
 use std::ptr;
-use std::ffi::CString;
-use std::os::raw::{c_int, c_char};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_int;
 use std::ops::Drop;
 use std::marker::PhantomData;
+use std::time::Duration;
 
+mod open_flags;
+pub use open_flags::OpenFlags;
+
+/// A connection to a SQLite database.
+///
+/// The adaptor is `Send` but not `Sync`. Its methods take `&mut self`, even to
+/// read, because they share its cache of prepared statements: a cached statement
+/// is bound, stepped and reset by one call at a time. To run queries from several
+/// threads, give each of them a connection, e.g. from a `SQLitePool`, instead
+/// of sharing one adaptor behind a `Mutex`.
 pub struct SQLiteAdaptor {
-    db_handler: *mut sqlite3
+    db_handler: *mut sqlite3,
+    /// prepared statements by their SQL, reset after each use
+    stmt_cache: StmtCache,
+    /// callback of `set_trace`, kept while SQLite may call it
+    trace_callback: Option<Box<TraceCallback>>,
+    /// handler of `set_busy_handler`, kept while SQLite may call it
+    busy_handler: Option<Box<BusyHandler>>,
+    /// shared with the handles of `interrupt_handle`, which forget the connection when it's closed
+    interrupt_handle: SQLiteInterruptHandle,
+    /// whether invalid UTF-8 in text columns is replaced instead of failing the read, see `set_lossy_utf8`
+    lossy_utf8: bool,
+    /// number of statements prepared, for tests to check statements are reused
+    #[cfg(test)]
+    prepare_count: usize
 }
 
+// SAFETY: the connection and its cached statements are only used through
+// `&mut self`. The statements of live query iterators aren't tied to the
+// adaptor, so the adaptor may be sent to another thread and used there while
+// an iterator steps its statement on the thread that created it (iterators
+// aren't `Send`). Every connection is opened in serialized mode, where SQLite
+// locks the connection for each call. The error message of the connection is
+// shared by all its statements, so it's copied under the connection mutex, and
+// an iterator holds that mutex from stepping until it has copied the error of
+// the step, see `sqlite_error_with_sql` and `SQLiteRowIterator::next`.
+unsafe impl Send for SQLiteAdaptor {}
+
 macro_rules! db_try {
-    ($e: expr) => {{
+    ($db: expr, $e: expr) => {{
+        {
+            let return_value = $e;
+            match return_value {
+                libsqlite3_sys::SQLITE_OK | libsqlite3_sys::SQLITE_DONE | libsqlite3_sys::SQLITE_ROW => {
+                    // success, ignore it
+                }
+                error_code => {
+                    return Err($crate::sqlite_error($db, error_code))
+                }
+            }
+        }
+    }};
+    ($db: expr, $stmt: expr, $e: expr) => {{
         {
             let return_value = $e;
             match return_value {
-                libsqlite3_sys::SQLITE_OK | libsqlite3_sys::SQLITE_DONE => {
+                libsqlite3_sys::SQLITE_OK | libsqlite3_sys::SQLITE_DONE | libsqlite3_sys::SQLITE_ROW => {
                     // success, ignore it
                 }
                 error_code => {
-                    return Err(DbError(format!("SQLite3 error {}", error_code)))
+                    // the statement is finalized when it's dropped, after the message and SQL are read
+                    return Err($crate::sqlite_stmt_error($db, $stmt, error_code))
                 }
             }
         }
     }};
 }
 
+mod attach;
+#[cfg(feature = "async")]
+mod async_adaptor;
+#[cfg(feature = "async")]
+pub use async_adaptor::AsyncSQLiteAdaptor;
+mod backup;
+pub use backup::BackupProgress;
+mod busy_handler;
+pub use busy_handler::BusyHandler;
+mod generated_sql;
+mod interrupt;
+pub use interrupt::SQLiteInterruptHandle;
+mod maintenance;
+mod migration;
+pub use migration::{AppliedReport, Migration};
+mod pragma;
+pub use pragma::{JournalMode, Synchronous};
+mod pool;
+pub use pool::{PooledSQLiteAdaptor, SQLitePool};
+mod statement;
+use statement::Statement;
+mod stmt_cache;
+use stmt_cache::{StmtCache, DEFAULT_STMT_CACHE_CAPACITY};
+mod trace;
+pub use trace::TraceCallback;
+mod transaction;
+pub use transaction::{SQLiteSavepoint, SQLiteTransaction};
+mod schema_check;
+pub use schema_check::{ColumnTypeMismatch, SchemaMigration, SchemaMismatch};
+use schema_check::{get_field_affinity, is_nullable};
+
+/// Build a `DbError` from a failed result code and the last error message of the connection.
+fn sqlite_error(db_handler: *mut sqlite3, error_code: c_int) -> DbError {
+    sqlite_error_with_sql(db_handler, error_code, None)
+}
+
+/// Build a `DbError` for a statement that failed, with its SQL.
+fn sqlite_stmt_error(db_handler: *mut sqlite3, stmt: *mut sqlite3_stmt, error_code: c_int) -> DbError {
+    let sql = unsafe {
+        // null if the statement failed to prepare
+        let sql_ptr = libsqlite3_sys::sqlite3_sql(stmt);
+        (!sql_ptr.is_null()).then(|| CStr::from_ptr(sql_ptr).to_string_lossy().into_owned())
+    };
+    sqlite_error_with_sql(db_handler, error_code, sql)
+}
+
+/// Build a `DbError` from a failed result code, which may be an extended
+/// result code, classified by its primary result code.
+fn sqlite_error_with_sql(db_handler: *mut sqlite3, error_code: c_int, sql: Option<String>) -> DbError {
+    let message = unsafe {
+        // the message may be replaced by a call on another thread, e.g. by the
+        // adaptor while a query iterator fails, so it's copied under the
+        // connection mutex, which is recursive
+        let mutex = libsqlite3_sys::sqlite3_db_mutex(db_handler);
+        libsqlite3_sys::sqlite3_mutex_enter(mutex);
+        let message = CStr::from_ptr(libsqlite3_sys::sqlite3_errmsg(db_handler)).to_string_lossy().into_owned();
+        libsqlite3_sys::sqlite3_mutex_leave(mutex);
+        message
+    };
+    let code = error_code & 0xff;
+    // a statement of a script starts with the whitespace after the one before it
+    let sql = sql.map(|sql| sql.trim().to_string());
+    let failure = DbFailure { code, extended_code: error_code, sqlstate: None, message, sql };
+    match code {
+        libsqlite3_sys::SQLITE_CONSTRAINT => {
+            let constraint = get_violated_constraint(error_code, &failure.message);
+            DbError::ConstraintViolation(failure, Box::new(constraint))
+        }
+        libsqlite3_sys::SQLITE_BUSY => DbError::Busy(failure),
+        libsqlite3_sys::SQLITE_LOCKED => DbError::Locked(failure),
+        libsqlite3_sys::SQLITE_MISUSE => DbError::Misuse(failure),
+        libsqlite3_sys::SQLITE_NOTADB => DbError::NotADatabase(failure),
+        libsqlite3_sys::SQLITE_READONLY => DbError::ReadOnly(failure),
+        libsqlite3_sys::SQLITE_IOERR => DbError::Io(failure),
+        libsqlite3_sys::SQLITE_INTERRUPT => DbError::Interrupted,
+        _ => DbError::DatabaseFailure(failure)
+    }
+}
+
+/// Tell the violated constraint from the extended result code and the message,
+/// like `UNIQUE constraint failed: y_member.first_name, y_member.last_name`.
+fn get_violated_constraint(extended_code: c_int, message: &str) -> ViolatedConstraint {
+    let kind = match extended_code {
+        libsqlite3_sys::SQLITE_CONSTRAINT_UNIQUE => ConstraintKind::Unique,
+        libsqlite3_sys::SQLITE_CONSTRAINT_PRIMARYKEY | libsqlite3_sys::SQLITE_CONSTRAINT_ROWID => ConstraintKind::PrimaryKey,
+        libsqlite3_sys::SQLITE_CONSTRAINT_NOTNULL => ConstraintKind::NotNull,
+        libsqlite3_sys::SQLITE_CONSTRAINT_FOREIGNKEY => ConstraintKind::ForeignKey,
+        libsqlite3_sys::SQLITE_CONSTRAINT_CHECK => ConstraintKind::Check,
+        _ => ConstraintKind::Other
+    };
+    let mut constraint = ViolatedConstraint { kind, table: None, columns: Vec::new(), name: None };
+    let detail = match message.split_once("constraint failed: ") {
+        Some((_, detail)) => detail,
+        None => return constraint
+    };
+    match kind {
+        ConstraintKind::Unique | ConstraintKind::PrimaryKey | ConstraintKind::NotNull => {
+            for column in detail.split(", ") {
+                if let Some((table, column)) = column.split_once('.') {
+                    constraint.table = Some(table.to_string());
+                    constraint.columns.push(column.to_string());
+                }
+            }
+        }
+        ConstraintKind::Check => constraint.name = Some(detail.to_string()),
+        ConstraintKind::ForeignKey | ConstraintKind::Other => {}
+    }
+    constraint
+}
+
+/// The nullable version of a field type, to read a value that may be NULL.
+fn get_nullable_type(field_type: &DbDataType) -> DbDataType {
+    match field_type {
+        DbDataType::Int | DbDataType::NullableInt | DbDataType::RowID => DbDataType::NullableInt,
+        DbDataType::Text | DbDataType::NullableText => DbDataType::NullableText,
+        DbDataType::Float | DbDataType::NullableFloat => DbDataType::NullableFloat,
+        DbDataType::Blob | DbDataType::NullableBlob => DbDataType::NullableBlob
+    }
+}
+
+/// Whether SQLite copies the text and blobs bound to a statement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BindMode {
+    /// The values are read in place, so they must outlive the steps of the
+    /// statement and be unbound, by finalizing it or clearing its bindings,
+    /// before they're dropped.
+    Borrow,
+    /// The values are copied, as the statement may be stepped after they're dropped.
+    Copy
+}
+
 impl SQLiteAdaptor {
+    /// Open the SQLite database file, creating it if it doesn't exist.
+    ///
+    /// Fails if the file can't be opened or it isn't a SQLite database.
+    ///
+    /// Foreign keys are enforced on every opened connection, see `set_foreign_keys`.
+    /// There is no busy timeout, so when the database is shared by several
+    /// processes, open it with `open_with_busy_timeout` or call
+    /// `set_busy_timeout` to wait for their locks instead of failing with
+    /// `DbError::Busy`.
     pub fn open(filename: &str) -> Result<SQLiteAdaptor, DbError> {
-        let filename_cstring = CString::new(filename).unwrap();
+        SQLiteAdaptor::open_with_flags(filename, OpenFlags::default())
+    }
+
+    /// Open the SQLite database file like `open`, waiting up to `timeout` for
+    /// a lock held by another connection before failing with `DbError::Busy`.
+    /// See `set_busy_timeout`.
+    pub fn open_with_busy_timeout(filename: &str, timeout: Duration) -> Result<SQLiteAdaptor, DbError> {
+        let mut adaptor = SQLiteAdaptor::open(filename)?;
+        adaptor.set_busy_timeout(timeout)?;
+        Ok(adaptor)
+    }
+
+    /// Open a private in-memory database. The data lives as long as the adaptor.
+    pub fn open_in_memory() -> Result<SQLiteAdaptor, DbError> {
+        SQLiteAdaptor::open(":memory:")
+    }
+
+    /// Open a named in-memory database with shared cache. All adaptors opened
+    /// with the same name in this process see the same data, which is kept
+    /// until the last of them is dropped.
+    pub fn open_in_memory_named(name: &str) -> Result<SQLiteAdaptor, DbError> {
+        SQLiteAdaptor::open_with_flags(
+            &format!("file:{}?mode=memory&cache=shared", name),
+            OpenFlags::default() | OpenFlags::URI
+        )
+    }
+
+    /// Open the SQLite database with the given flags.
+    ///
+    /// Without `OpenFlags::CREATE`, opening a file that doesn't exist fails
+    /// instead of creating an empty database.
+    pub fn open_with_flags(filename: &str, flags: OpenFlags) -> Result<SQLiteAdaptor, DbError> {
+        SQLiteAdaptor::open_with_vfs(filename, flags, None)
+    }
+
+    /// Open the SQLite database with the given flags through the VFS registered
+    /// under `vfs_name`, or the default VFS if it's `None`.
+    ///
+    /// Fails with `DbError::DatabaseFailure` if there is no such VFS.
+    ///
+    /// The connection is always opened in serialized mode, so the adaptor can be
+    /// sent to another thread while query results stay on this one. Passing
+    /// `OpenFlags::NOMUTEX` is an error.
+    pub fn open_with_vfs(filename: &str, flags: OpenFlags, vfs_name: Option<&str>) -> Result<SQLiteAdaptor, DbError> {
+        if flags.bits() & libsqlite3_sys::SQLITE_OPEN_NOMUTEX != 0 {
+            return Err(DbError::Other("Connections must be opened in serialized mode, without OpenFlags::NOMUTEX".to_string()));
+        }
+        if unsafe { libsqlite3_sys::sqlite3_threadsafe() } == 0 {
+            return Err(DbError::Other("SQLite is compiled without thread safety".to_string()));
+        }
+        let flags = flags | OpenFlags::FULLMUTEX;
+        let filename_cstring = CString::new(filename)
+            .map_err(|_| DbError::Other(format!("Database filename contains a NUL byte: {:?}", filename)))?;
+        let vfs_cstring = vfs_name.map(|name| CString::new(name)
+            .map_err(|_| DbError::Other(format!("VFS name contains a NUL byte: {:?}", name))))
+            .transpose()?;
+        let vfs_ptr = vfs_cstring.as_ref().map_or(ptr::null(), |name| name.as_ptr());
         let mut db_handler: *mut sqlite3 = ptr::null_mut();
+        let return_value = unsafe {
+            libsqlite3_sys::sqlite3_open_v2(filename_cstring.as_ptr(), &mut db_handler, flags.bits(), vfs_ptr)
+        };
+        if return_value != libsqlite3_sys::SQLITE_OK {
+            // a handle is allocated even if the open fails, so it must be closed here
+            let error = sqlite_error(db_handler, return_value);
+            unsafe {
+                libsqlite3_sys::sqlite3_close(db_handler);
+            }
+            return Err(error);
+        }
+        unsafe {
+            // tell apart failures of the same kind, e.g. which constraint failed
+            libsqlite3_sys::sqlite3_extended_result_codes(db_handler, 1);
+        }
+        let adaptor = SQLiteAdaptor {
+            db_handler,
+            stmt_cache: StmtCache::new(DEFAULT_STMT_CACHE_CAPACITY),
+            trace_callback: None,
+            busy_handler: None,
+            interrupt_handle: SQLiteInterruptHandle::new(db_handler),
+            lossy_utf8: false,
+            #[cfg(test)]
+            prepare_count: 0
+        };
+        adaptor.check_database_header()?;
+        adaptor.execute_sql("PRAGMA foreign_keys = ON;")?;
+        Ok(adaptor)
+    }
+
+    /// Set how long to wait for a lock held by another connection before
+    /// failing with `DbError::Busy`. By default there is no timeout and a
+    /// locked database fails immediately. A zero duration turns the timeout off.
+    ///
+    /// It replaces the handler set with `set_busy_handler`.
+    #[doc(alias = "sqlite3_busy_timeout")]
+    pub fn set_busy_timeout(&mut self, timeout: Duration) -> Result<(), DbError> {
+        let timeout_ms = timeout.as_millis().min(c_int::MAX as u128) as c_int;
         unsafe {
-            db_try!(libsqlite3_sys::sqlite3_open(filename_cstring.as_ptr(), &mut db_handler));
+            db_try!(self.db_handler, libsqlite3_sys::sqlite3_busy_timeout(self.db_handler, timeout_ms));
         }
-        Ok(SQLiteAdaptor {
-            db_handler
+        // SQLite no longer calls the busy handler
+        self.busy_handler = None;
+        Ok(())
+    }
+
+    /// Insert the record, replacing the stored record that has the same row id
+    /// or violates a uniqueness constraint. Return the row id of the inserted record.
+    pub fn insert_record_or_replace<T: Schema>(&mut self, record: T) -> Result<i64, DbError> {
+        let insert_stmt = SQLiteAdaptor::get_insert_stmt_code("INSERT OR REPLACE", &quote_identifier(&T::get_schema_name()), &T::get_fields());
+        self.insert_with_stmt(&insert_stmt, record)
+    }
+
+    /// Insert the record unless it has the same row id as a stored record or
+    /// violates a uniqueness constraint. Return the row id of the inserted record,
+    /// or `None` if the record was ignored.
+    pub fn insert_record_or_ignore<T: Schema>(&mut self, record: T) -> Result<Option<i64>, DbError> {
+        let insert_stmt = SQLiteAdaptor::get_insert_stmt_code("INSERT OR IGNORE", &quote_identifier(&T::get_schema_name()), &T::get_fields());
+        let row_id = self.insert_with_stmt(&insert_stmt, record)?;
+        if unsafe { libsqlite3_sys::sqlite3_changes(self.db_handler) } == 0 {
+            return Ok(None);
+        }
+        Ok(Some(row_id))
+    }
+
+    fn insert_with_stmt<T: Schema>(&mut self, insert_record_stmt: &str, record: T) -> Result<i64, DbError> {
+        let db_handler = self.db_handler;
+        self.execute_cached(insert_record_stmt, &record.try_get_values()?, |_| unsafe {
+            libsqlite3_sys::sqlite3_last_insert_rowid(db_handler)
         })
     }
 
-    fn get_create_table_stmt_code(schema_name: &str, fields: &Vec<(String, DbDataType)>) -> String {
-        let mut s = format!("CREATE TABLE IF NOT EXISTS {} (", schema_name);
+    /// SQLite opens the file lazily, so read the schema version to make sure
+    /// the file is really a database before handing out the adaptor.
+    fn check_database_header(&self) -> Result<(), DbError> {
+        self.execute_sql("PRAGMA schema_version;")
+    }
+
+    /// Prepare a single SQL statement.
+    fn prepare_stmt(&mut self, sql: &str) -> Result<Statement, DbError> {
+        self.prepare_stmt_with_tail(sql).map(|(stmt, _)| stmt)
+    }
+
+    /// Prepare the first statement in the SQL and return it with the byte offset
+    /// of the rest of the SQL. The statement is null if there is only whitespace
+    /// or comments.
+    fn prepare_stmt_with_tail(&mut self, sql: &str) -> Result<(Statement, usize), DbError> {
+        let stmt_cstring = CString::new(sql)
+            .map_err(|_| DbError::Other("SQL statement contains a NUL byte".to_string()))?;
+        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
+        let mut tail = ptr::null();
+        let return_value = unsafe {
+            libsqlite3_sys::sqlite3_prepare_v2(
+                self.db_handler,
+                stmt_cstring.as_ptr(),
+                sql.len() as c_int,
+                &mut stmt,
+                &mut tail
+            )
+        };
+        if return_value != libsqlite3_sys::SQLITE_OK {
+            // no statement is prepared on failure, so the SQL comes from the caller
+            return Err(sqlite_error_with_sql(self.db_handler, return_value, Some(sql.to_string())));
+        }
+        #[cfg(test)]
+        {
+            self.prepare_count += 1;
+        }
+        let tail_offset = tail as usize - stmt_cstring.as_ptr() as usize;
+        Ok((Statement::new(stmt), tail_offset))
+    }
+
+    /// Execute a single SQL statement, binding `params` to its `?` or `?N`
+    /// parameters in order. Return the number of rows changed by it, including
+    /// changes made by triggers and foreign key actions.
+    ///
+    /// It's an error if there is more than one statement, or if the number of
+    /// parameters doesn't match the statement. Neither is executed then.
+    pub fn execute_raw(&mut self, sql: &str, params: &[&dyn DbData]) -> Result<u64, DbError> {
+        let (stmt, tail_offset) = self.prepare_stmt_with_tail(sql)?;
+        if stmt.is_null() {
+            return Err(DbError::Other("There is no SQL statement to execute".to_string()));
+        }
+        let (tail_stmt, _) = self.prepare_stmt_with_tail(&sql[tail_offset..])?;
+        if !tail_stmt.is_null() {
+            return Err(DbError::Other(format!("Only one SQL statement can be executed, found more: {}", sql[tail_offset..].trim())));
+        }
+        let param_count = unsafe { libsqlite3_sys::sqlite3_bind_parameter_count(stmt.as_ptr()) } as usize;
+        if param_count != params.len() {
+            return Err(DbError::Other(format!("The statement has {} parameters, but {} are given", param_count, params.len())));
+        }
+        for (ii, param) in params.iter().enumerate() {
+            // the statement is finalized before returning, while the params are still borrowed
+            SQLiteAdaptor::bind_param_to_stmt(stmt.as_ptr(), (ii+1) as c_int, *param, BindMode::Borrow)?;
+        }
+        let total_changes = unsafe { libsqlite3_sys::sqlite3_total_changes64(self.db_handler) };
+        self.run_to_completion(stmt)?;
+        let changes = unsafe { libsqlite3_sys::sqlite3_total_changes64(self.db_handler) } - total_changes;
+        Ok(changes as u64)
+    }
+
+    /// Execute the SQL statements in order, e.g. from a file that sets up the
+    /// schema. Whitespace and comments between and after them are ignored.
+    ///
+    /// The statements run in a transaction, so if one of them fails, the
+    /// changes made by the earlier ones are rolled back and the error tells
+    /// which statement failed, counting from 1. The script itself can't begin
+    /// or end transactions.
+    pub fn execute_script(&mut self, sql: &str) -> Result<(), DbError> {
+        let transaction = self.begin_transaction()?;
+        let mut rest = sql;
+        let mut statement_number = 0;
+        while !rest.is_empty() {
+            statement_number += 1;
+            let in_statement = |mut error: DbError| {
+                if let Some(failure) = error.failure_mut() {
+                    failure.message = format!("Statement {} of the script failed: {}", statement_number, failure.message);
+                }
+                error
+            };
+            let (stmt, tail_offset) = transaction.adaptor.prepare_stmt_with_tail(rest).map_err(in_statement)?;
+            if stmt.is_null() {
+                // only whitespace, comments or an empty statement
+                statement_number -= 1;
+                if tail_offset == 0 {
+                    break;
+                }
+            } else {
+                transaction.adaptor.run_to_completion(stmt).map_err(in_statement)?;
+            }
+            rest = &rest[tail_offset..];
+        }
+        transaction.commit()
+    }
+
+    /// Step the statement until it's done, ignoring the rows it returns, then finalize it.
+    fn run_to_completion(&self, stmt: Statement) -> Result<(), DbError> {
+        unsafe {
+            loop {
+                let return_value = libsqlite3_sys::sqlite3_step(stmt.as_ptr());
+                db_try!(self.db_handler, stmt.as_ptr(), return_value);
+                if return_value == libsqlite3_sys::SQLITE_DONE {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert all records in a single transaction, preparing the insert statement
+    /// only once. Return the number of records inserted. For many records, this is
+    /// much faster than calling `insert_record` for each of them.
+    ///
+    /// If any record fails to insert, none of them are kept.
+    pub fn insert_records<T: Schema>(&mut self, records: impl IntoIterator<Item = T>) -> Result<usize, DbError> {
+        let insert_record_stmt = SQLiteAdaptor::get_insert_value_stmt_code(&quote_identifier(&T::get_schema_name()), &T::get_fields());
+        let transaction = self.begin_transaction()?;
+        let mut count = 0;
+        for record in records {
+            transaction.adaptor.execute_cached(&insert_record_stmt, &record.try_get_values()?, |_| ())?;
+            count += 1;
+        }
+        transaction.commit()?;
+        Ok(count)
+    }
+
+    /// Set how many prepared statements are kept for reuse. When more are
+    /// needed, the least recently used one is discarded. The default is 32,
+    /// and zero turns the cache off.
+    pub fn set_stmt_cache_capacity(&mut self, capacity: usize) {
+        self.stmt_cache.set_capacity(capacity);
+    }
+
+    /// Replace invalid UTF-8 in the text columns read with U+FFFD, e.g. to read
+    /// text another tool wrote in Latin-1. By default such a read fails with
+    /// `DbError::InvalidUtf8`. Queries started before the change keep the old mode.
+    pub fn set_lossy_utf8(&mut self, lossy: bool) {
+        self.lossy_utf8 = lossy;
+    }
+
+    /// Bind the parameters to a cached statement and step it once, then pass it
+    /// to `read` to get the result. The statement is prepared and cached if it
+    /// isn't in the cache yet, and reset for reuse afterwards, also on error.
+    /// If the cache is turned off, it's finalized instead.
+    fn execute_cached<R>(&mut self, sql: &str, params: &[Box<dyn DbData>], read: impl FnOnce(*mut sqlite3_stmt) -> R) -> Result<R, DbError> {
+        let mut uncached_stmt = None;
+        let stmt = match self.stmt_cache.get(sql) {
+            Some(stmt) => stmt,
+            None => {
+                let prepared_stmt = self.prepare_stmt(sql)?;
+                let stmt = prepared_stmt.as_ptr();
+                uncached_stmt = self.stmt_cache.insert(sql, prepared_stmt);
+                stmt
+            }
+        };
+        let result = self.step_once(stmt, params).map(|()| read(stmt));
+        if uncached_stmt.is_none() {
+            unsafe {
+                libsqlite3_sys::sqlite3_reset(stmt);
+                libsqlite3_sys::sqlite3_clear_bindings(stmt);
+            }
+        }
+        result
+    }
+
+    fn step_once(&self, stmt: *mut sqlite3_stmt, params: &[Box<dyn DbData>]) -> Result<(), DbError> {
+        // `execute_cached` clears the bindings or finalizes the statement before the params are dropped
+        SQLiteAdaptor::bind_params_to_stmt(stmt, params, BindMode::Borrow)?;
+        let return_value = unsafe { libsqlite3_sys::sqlite3_step(stmt) };
+        match return_value {
+            libsqlite3_sys::SQLITE_ROW | libsqlite3_sys::SQLITE_DONE => Ok(()),
+            // the statement is cached, so it's not finalized here
+            error_code => Err(sqlite_stmt_error(self.db_handler, stmt, error_code))
+        }
+    }
+
+    /// Drop the table of the schema. If `if_exists` is false, dropping a table
+    /// that doesn't exist is an error.
+    pub fn drop_table_for_schema<T: Schema>(&mut self, if_exists: bool) -> Result<(), DbError> {
+        let drop_table_stmt = format!(
+            "DROP TABLE {}{};",
+            if if_exists { "IF EXISTS " } else { "" },
+            quote_identifier(&T::get_schema_name())
+        );
+        self.execute_sql(&drop_table_stmt)
+    }
+
+    /// Delete all records of the schema like `delete_all`, and also reset the
+    /// row id counter that SQLite keeps for tables with `AUTOINCREMENT`, so new
+    /// records are numbered from 1 again. Tables created for schemas don't
+    /// have the counter, as their new records are numbered after the largest
+    /// row id left, but tables created with `execute_script` may.
+    pub fn delete_all_and_reset_sequence<T: Schema>(&mut self) -> Result<u64, DbError> {
+        let mut transaction = self.begin_transaction()?;
+        let deleted = transaction.delete_all::<T>()?;
+        // the sequence table is only created with the first AUTOINCREMENT table
+        let sequence_count_stmt = "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_sequence';";
+        if transaction.adaptor.query_count(sequence_count_stmt, &[])? > 0 {
+            let params: [Box<dyn DbData>; 1] = [Box::new(T::get_schema_name())];
+            transaction.adaptor.execute_cached("DELETE FROM sqlite_sequence WHERE name = ?;", &params, |_| ())?;
+        }
+        transaction.commit()?;
+        Ok(deleted)
+    }
+
+    /// Create an index on the columns of the schema table, unless it already
+    /// exists. The index is named `idx_` followed by the table name and the
+    /// column names, joined with `_`.
+    ///
+    /// Columns that are not fields of the schema are an error.
+    pub fn create_index<T: Schema>(&mut self, columns: &[&str], unique: bool) -> Result<(), DbError> {
+        if columns.is_empty() {
+            return Err(DbError::Other("An index needs at least one column".to_string()));
+        }
+        let fields = T::get_fields();
+        for column in columns {
+            if !fields.iter().any(|(field_name, _)| field_name == column) {
+                return Err(DbError::Other(format!("Unknown field in index: {}", column)));
+            }
+        }
+        let schema_name = T::get_schema_name();
+        let index_name = format!("idx_{}_{}", schema_name, columns.join("_"));
+        let create_index_stmt = format!(
+            "CREATE {}INDEX IF NOT EXISTS {} ON {} ({});",
+            if unique { "UNIQUE " } else { "" },
+            quote_identifier(&index_name),
+            quote_identifier(&schema_name),
+            quote_identifiers(columns)
+        );
+        self.execute_sql(&create_index_stmt)
+    }
+
+    /// Read the values of one column from the records of the schema that
+    /// match the condition, without reading the other fields. `V` is the type
+    /// to read the values as, like `String` or `Option<i64>`.
+    ///
+    /// It's an error if the column is not a field of the schema, if `V` is for
+    /// another type of field, or if a value is NULL and `V` is not nullable.
+    pub fn query_column<T: Schema, V: ColumnValue>(&mut self, column: &str, cond: yoshino_core::Cond) -> Result<Vec<V>, DbError> {
+        let schema_name = T::get_schema_name();
+        let fields = T::get_fields();
+        let (_, field_type) = fields.iter().find(|(field_name, _)| field_name == column)
+            .ok_or_else(|| DbError::Other(format!("Unknown field in {}: {}", schema_name, column)))?;
+        let value_type = V::column_data_type();
+        if get_field_affinity(field_type) != get_field_affinity(&value_type) {
+            return Err(DbError::Other(format!("Field {} of {} can't be read as {}", column, schema_name, get_field_affinity(&value_type))));
+        }
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        let query_stmt = format!("SELECT {} FROM {} WHERE {};", quote_identifier(column), quote_identifier(&schema_name), cond_stmt);
+        let stmt = self.prepare_stmt(&query_stmt)?;
+        // the statement is finalized before the params are dropped
+        SQLiteAdaptor::bind_params_to_stmt(stmt.as_ptr(), &cond_params, BindMode::Borrow)?;
+        let mut values = Vec::new();
+        unsafe {
+            loop {
+                let return_value = libsqlite3_sys::sqlite3_step(stmt.as_ptr());
+                db_try!(self.db_handler, stmt.as_ptr(), return_value);
+                if return_value == libsqlite3_sys::SQLITE_DONE {
+                    break;
+                }
+                if !is_nullable(&value_type) && libsqlite3_sys::sqlite3_column_type(stmt.as_ptr(), 0) == libsqlite3_sys::SQLITE_NULL {
+                    return Err(DbError::Other(format!("Field {} of {} is NULL, but it's read as a non-nullable value", column, schema_name)));
+                }
+                let value = SQLiteRowIterator::<T>::read_column(stmt.as_ptr(), 0, &value_type, self.lossy_utf8)?;
+                values.push(V::try_from_column_data(&value)?);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Read the values of some columns from all records of the schema, without
+    /// reading the other fields. Each row has a value for each of the columns
+    /// in order, of the type of its field.
+    ///
+    /// It's an error if there is no column or a column is not a field of the schema.
+    pub fn query_columns<T: Schema>(&mut self, columns: &[&str]) -> Result<Vec<Vec<Box<dyn DbData>>>, DbError> {
+        self.query_columns_with_stmt::<T>(columns, "", Vec::new())
+    }
+
+    /// Read the values of some columns from the records of the schema that
+    /// match the condition, like `query_columns`.
+    pub fn query_columns_with_cond<T: Schema>(&mut self, columns: &[&str], cond: yoshino_core::Cond) -> Result<Vec<Vec<Box<dyn DbData>>>, DbError> {
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        self.query_columns_with_stmt::<T>(columns, &format!(" WHERE {}", cond_stmt), cond_params)
+    }
+
+    /// Select the columns with the clause after the table name, which has its parameters in `params`.
+    fn query_columns_with_stmt<T: Schema>(&mut self, columns: &[&str], clause: &str, params: Vec<Box<dyn DbData>>) -> Result<Vec<Vec<Box<dyn DbData>>>, DbError> {
+        let schema_name = T::get_schema_name();
+        if columns.is_empty() {
+            return Err(DbError::Other(format!("No column of {} to query", schema_name)));
+        }
+        let fields = T::get_fields();
+        let mut column_types = Vec::new();
+        for column in columns {
+            let (_, field_type) = fields.iter().find(|(field_name, _)| field_name == column)
+                .ok_or_else(|| DbError::Other(format!("Unknown field in {}: {}", schema_name, column)))?;
+            column_types.push(field_type);
+        }
+        let query_stmt = format!("SELECT {} FROM {}{};", quote_identifiers(columns), quote_identifier(&schema_name), clause);
+        let stmt = self.prepare_stmt(&query_stmt)?;
+        // the statement is finalized before the params are dropped
+        SQLiteAdaptor::bind_params_to_stmt(stmt.as_ptr(), &params, BindMode::Borrow)?;
+        let mut rows = Vec::new();
+        unsafe {
+            loop {
+                let return_value = libsqlite3_sys::sqlite3_step(stmt.as_ptr());
+                db_try!(self.db_handler, stmt.as_ptr(), return_value);
+                if return_value == libsqlite3_sys::SQLITE_DONE {
+                    break;
+                }
+                let row = column_types.iter().enumerate()
+                    .map(|(i, field_type)| SQLiteRowIterator::<T>::read_column(stmt.as_ptr(), i as c_int, field_type, self.lossy_utf8))
+                    .collect::<Result<Vec<_>, DbError>>()?;
+                rows.push(row);
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Create an index on each field of the schema marked with
+    /// `#[yoshino(index)]`, like `create_index` does for a single column.
+    pub fn create_indexes_for_schema<T: Schema>(&mut self) -> Result<(), DbError> {
+        for column in T::get_indexes() {
+            self.create_index::<T>(&[&column], false)?;
+        }
+        Ok(())
+    }
+
+    /// Execute SQL statements without parameters, ignoring the rows they return.
+    fn execute_sql(&self, sql: &str) -> Result<(), DbError> {
+        let stmt_cstring = CString::new(sql)
+            .map_err(|_| DbError::Other("SQL statement contains a NUL byte".to_string()))?;
+        unsafe {
+            db_try!(self.db_handler, libsqlite3_sys::sqlite3_exec(
+                self.db_handler,
+                stmt_cstring.as_ptr(),
+                None,
+                ptr::null_mut(),
+                ptr::null_mut()
+            ));
+        }
+        Ok(())
+    }
+
+    /// `table` is quoted, and may be qualified with an attached database, as
+    /// in the other statement builders.
+    fn get_create_table_stmt_code(table: &str, fields: &[(String, DbDataType)], unique_constraints: &[Vec<String>], foreign_keys: &[ForeignKey], collations: &[(String, Collation)], primary_key: Option<&str>) -> String {
+        let mut s = format!("CREATE TABLE IF NOT EXISTS {} (", table);
         for i in 0..fields.len() {
             if i != 0 {
-                s = s + ", ";
+                s += ", ";
             }
             let (field_name, field_type) = fields.get(i).unwrap();
-            s = s + field_name + " ";
-            s = s + match  field_type {
-                DbDataType::Int => "INTEGER NOT NULL",
-                DbDataType::NullableInt => "INTEGER",
-                DbDataType::Text => "TEXT NOT NULL",
-                DbDataType::NullableText => "TEXT",
-                DbDataType::Float => "REAL",
-                DbDataType::RowID => "INTEGER PRIMARY KEY"
+            s = s + &quote_identifier(field_name) + " ";
+            s += SQLiteAdaptor::get_column_type_code(field_type);
+            if primary_key == Some(field_name.as_str()) {
+                s += " PRIMARY KEY";
+            }
+            if let Some((_, collation)) = collations.iter().find(|(column, _)| column == field_name) {
+                s += SQLiteAdaptor::get_collation_code(collation);
             }
+            if unique_constraints.iter().any(|columns| columns.len() == 1 && &columns[0] == field_name) {
+                s += " UNIQUE";
+            }
+            if let Some(foreign_key) = foreign_keys.iter().find(|foreign_key| &foreign_key.column == field_name) {
+                s += &format!(" REFERENCES {}({})", quote_identifier(&foreign_key.referred_schema), quote_identifier(&foreign_key.referred_column));
+                s += match foreign_key.on_delete {
+                    OnDelete::NoAction => "",
+                    OnDelete::Restrict => " ON DELETE RESTRICT",
+                    OnDelete::SetNull => " ON DELETE SET NULL",
+                    OnDelete::Cascade => " ON DELETE CASCADE"
+                };
+            }
+        }
+        for columns in unique_constraints.iter().filter(|columns| columns.len() > 1) {
+            s += &format!(", UNIQUE({})", quote_identifiers(columns));
         }
-        s = s + ");";
+        s += ");";
         s
     }
 
-    fn get_insert_value_stmt_code(schema_name: &str, fields: &Vec<(String, DbDataType)>) -> String {
-        let mut s = format!("INSERT INTO {} (", schema_name);
+    fn get_collation_code(collation: &Collation) -> &'static str {
+        match collation {
+            Collation::Binary => "",
+            Collation::NoCase => " COLLATE NOCASE",
+            Collation::RTrim => " COLLATE RTRIM"
+        }
+    }
+
+    fn get_column_type_code(field_type: &DbDataType) -> &'static str {
+        match field_type {
+            DbDataType::Int => "INTEGER NOT NULL",
+            DbDataType::NullableInt => "INTEGER",
+            DbDataType::Text => "TEXT NOT NULL",
+            DbDataType::NullableText => "TEXT",
+            DbDataType::Float => "REAL NOT NULL",
+            DbDataType::NullableFloat => "REAL",
+            DbDataType::Blob => "BLOB NOT NULL",
+            DbDataType::NullableBlob => "BLOB",
+            DbDataType::RowID => "INTEGER PRIMARY KEY"
+        }
+    }
+
+    fn get_insert_value_stmt_code(table: &str, fields: &[(String, DbDataType)]) -> String {
+        SQLiteAdaptor::get_insert_stmt_code("INSERT", table, fields)
+    }
+
+    /// `insert_verb` is `INSERT` or one of its `INSERT OR ...` conflict resolutions.
+    fn get_insert_stmt_code(insert_verb: &str, table: &str, fields: &[(String, DbDataType)]) -> String {
+        let mut s = format!("{} INTO {} (", insert_verb, table);
         for i in 0..fields.len() {
             if i != 0 {
-                s = s + ", ";
+                s += ", ";
             }
             let (field_name, _) = fields.get(i).unwrap();
-            s = s + &field_name;
+            s = s + &quote_identifier(field_name);
         }
-        s = s + ") VALUES (";
+        s += ") VALUES (";
         for i in 0..fields.len() {
             if i != 0 {
-                s = s + ", ";
+                s += ", ";
             }
-            s = s + format!("?{}", i+1).as_ref();
+            s += format!("?{}", i+1).as_ref();
         }
-        s = s + ");";
+        s += ");";
         s
     }
 
-    fn get_query_clause(schema_name: &str, fields: &Vec<(String, DbDataType)>) -> String {
-        let mut s = format!("SELECT ");
+    fn get_query_clause(table: &str, fields: &[(String, DbDataType)]) -> String {
+        let mut s = "SELECT ".to_string();
         for i in 0..fields.len() {
             if i != 0 {
-                s = s + ", ";
+                s += ", ";
             }
             let (field_name, _) = fields.get(i).unwrap();
-            s = s + &field_name;
+            s = s + &quote_identifier(field_name);
         }
-        s = s + " FROM " + schema_name;
+        s = s + " FROM " + table;
         s 
     }
 
-    fn get_update_clause(schema_name: &str, fields: &Vec<(String, DbDataType)>) -> String {
-        let mut s = format!("UPDATE {} SET ", schema_name);
-        for i in 0..fields.len() {
-            if i != 0 {
-                s = s + ", "
+    fn get_update_clause(schema_name: &str, fields: &[(String, DbDataType)]) -> String {
+        let mut s = format!("UPDATE {} SET ", quote_identifier(schema_name));
+        let mut first = true;
+        for (field_name, field_type) in fields {
+            // the row id identifies the record, so it's never updated
+            if let DbDataType::RowID = field_type {
+                continue;
             }
-            let (field_name, _) = fields.get(i).unwrap();
-            s = s + format!("{} = ?", field_name).as_ref();
+            if !first {
+                s += ", "
+            }
+            first = false;
+            s += format!("{} = ?", quote_identifier(field_name)).as_ref();
         }
         s
     }
 
-    fn get_condition_stmt_and_params(cond: yoshino_core::query_cond::Cond) -> (String, Vec<Box<dyn DbData>>) {
-        use yoshino_core::query_cond::Cond::*;
-        match cond {
-            IsNull{field_name} => {
-                (format!("{} IS NULL", field_name), vec![])
-            }
-            IsNotNull { field_name } => {
-                (format!("{} IS NOT NULL", field_name), vec![])
-            }
-            IntegerEqualTo { field_name, value } => {
-                (format!("{}=?", field_name), vec![Box::new(value)])
-            }
-            IntegerNotEqualTo { field_name, value } => {
-                (format!("{}<>?", field_name), vec![Box::new(value)])
-            }
-            IntegerGreaterThan { field_name, value } => {
-                (format!("{}>?", field_name), vec![Box::new(value)])
-            }
-            IntegerLessThan { field_name, value } => {
-                (format!("{}<?", field_name), vec![Box::new(value)])
-            }
-            IntegerGreaterThanOrEqualTo { field_name, value } => {
-                (format!("{}>=?", field_name), vec![Box::new(value)])
-            }
-            IntegerLessThanOrEqualTo { field_name, value } => {
-                (format!("{}<=?", field_name), vec![Box::new(value)])
-            }
-            TextEqualTo { field_name, value } => {
-                (format!("{}=?", field_name), vec![Box::new(value)])
-            }
-            And{left, right} => {
-                let (left_stmt, left_params) = Self::get_condition_stmt_and_params(*left);
-                let (right_stmt, right_params) = Self::get_condition_stmt_and_params(*right);
-                let mut params = left_params;
-                params.extend(right_params.into_iter());
-                (format!("({}) AND ({})", left_stmt, right_stmt), params)
+    /// Values of a record to bind to the clause from `get_update_clause`.
+    fn get_update_params(fields: &[(String, DbDataType)], values: Vec<Box<dyn DbData>>) -> Vec<Box<dyn DbData>> {
+        fields.iter()
+            .zip(values)
+            .filter(|((_, field_type), _)| !matches!(field_type, DbDataType::RowID))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Delete records matching the condition and return the number of rows deleted.
+    fn delete_rows<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<c_int, DbError> {
+        let schema_name = T::get_schema_name();
+        let delete_clause = SQLiteAdaptor::get_delete_clause(&quote_identifier(&schema_name));
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        let delete_where_cond_stmt = format!("{} WHERE {};", delete_clause, cond_stmt);
+        let db_handler = self.db_handler;
+        self.execute_cached(&delete_where_cond_stmt, &cond_params, |_| unsafe {
+            libsqlite3_sys::sqlite3_changes(db_handler)
+        })
+    }
+
+    /// Get the condition matching a stored record by its row id field, or else
+    /// by its primary key, for the `action` on it. It comes with the key to
+    /// name in errors.
+    fn get_stored_record_cond<T: Schema>(record: &T, action: &str) -> Result<(yoshino_core::Cond, String), DbError> {
+        let schema_name = T::get_schema_name();
+        match record.try_get_row_id_field()? {
+            Some((field_name, RowID::ID(id))) => Ok((yoshino_core::Cond::integer_equal_to(&field_name, id), format!("{} = {}", field_name, id))),
+            Some((_, RowID::NEW)) => Err(DbError::Other(format!("Cannot {} a new {} record", action, schema_name))),
+            None => {
+                let primary_key = T::get_primary_key().ok_or_else(||
+                    DbError::Other(format!("{} has no row id field or primary key", schema_name)))?;
+                let cond = yoshino_core::Cond::is_primary_key_equal_to(record)?.ok_or_else(||
+                    DbError::Other(format!("Cannot {} a {} record by {}, which is neither text nor an integer", action, schema_name, primary_key)))?;
+                Ok((cond, format!("the same {}", primary_key)))
             }
-            Or{left, right} => {
-                let (left_stmt, left_params) = Self::get_condition_stmt_and_params(*left);
-                let (right_stmt, right_params) = Self::get_condition_stmt_and_params(*right);
-                let mut params = left_params;
-                params.extend(right_params.into_iter());
-                (format!("({}) OR ({})", left_stmt, right_stmt), params)
+        }
+    }
+
+    /// Update records matching the condition and return the number of rows changed.
+    fn update_rows<T: Schema>(&mut self, cond: yoshino_core::Cond, record: &T) -> Result<c_int, DbError> {
+        let schema_name = T::get_schema_name();
+        let fields = T::get_fields();
+        let update_clause = SQLiteAdaptor::get_update_clause(&schema_name, &fields);
+        let mut update_stmt_params = SQLiteAdaptor::get_update_params(&fields, record.try_get_values()?);
+        let cond_stmt = SQLiteAdaptor::get_condition_stmt(&cond, &mut update_stmt_params);
+        let update_where_cond_stmt = format!("{} WHERE {};", update_clause, cond_stmt);
+
+        let db_handler = self.db_handler;
+        self.execute_cached(&update_where_cond_stmt, &update_stmt_params, |_| unsafe {
+            libsqlite3_sys::sqlite3_changes(db_handler)
+        })
+    }
+
+    /// The SQL of the condition numbered from `?1`, and the values to bind to it.
+    fn get_condition_stmt_and_params(cond: yoshino_core::query_cond::Cond) -> (String, Vec<Box<dyn DbData>>) {
+        let mut params = Vec::new();
+        let cond_stmt = SQLiteAdaptor::get_condition_stmt(&cond, &mut params);
+        (cond_stmt, params)
+    }
+
+    /// Render the condition with `?N` placeholders numbered after the ones
+    /// already in `params`, and add its parameters to `params`.
+    fn get_condition_stmt(cond: &yoshino_core::Cond, params: &mut Vec<Box<dyn DbData>>) -> String {
+        let mut next_param_index = params.len() as i32 + 1;
+        let cond_stmt = cond.to_sql(&mut next_param_index);
+        params.extend(cond.bind_values());
+        cond_stmt
+    }
+
+    /// Build the ORDER BY clause with a leading space, or an empty string if there is no ordering.
+    /// The column names are checked against the fields, as they can't be bound as parameters.
+    fn get_order_by_clause(fields: &[(String, DbDataType)], order: &[(String, SortDir)]) -> Result<String, DbError> {
+        if order.is_empty() {
+            return Ok(String::new());
+        }
+        let mut s = " ORDER BY ".to_string();
+        for (i, (field_name, sort_dir)) in order.iter().enumerate() {
+            if !fields.iter().any(|(name, _)| name == field_name) {
+                return Err(DbError::Other(format!("Unknown field to sort by: {}", field_name)));
             }
-            Not{cond} => {
-                let (stmt, params) = Self::get_condition_stmt_and_params(*cond);          
-                (format!("NOT ({})", stmt), params)
+            if i != 0 {
+                s += ", ";
             }
+            s += &quote_identifier(field_name);
+            s += match sort_dir {
+                SortDir::Asc => " ASC",
+                SortDir::Desc => " DESC"
+            };
+        }
+        Ok(s)
+    }
+
+    /// Prepare the query statement, bind the parameters and iterate over the rows.
+    fn query_with_stmt<T: Schema>(&mut self, query_stmt: &str, params: &[Box<dyn DbData>]) -> Result<DbQueryResult<T>, DbError> {
+        let stmt = self.prepare_stmt(query_stmt)?;
+        // the rows are read after the params are dropped
+        SQLiteAdaptor::bind_params_to_stmt(stmt.as_ptr(), params, BindMode::Copy)?;
+        let iter:Box<SQLiteRowIterator<T>> = Box::new(SQLiteRowIterator::new(stmt, self.lossy_utf8));
+        Ok(DbQueryResult::new(iter))
+    }
+
+    /// Values to bind to `LIMIT ? OFFSET ?`. SQLite takes signed integers, so
+    /// larger values are clamped, which is the same as no limit.
+    fn get_limit_params(limit: u64, offset: u64) -> Vec<Box<dyn DbData>> {
+        let limit = limit.min(i64::MAX as u64) as i64;
+        let offset = offset.min(i64::MAX as u64) as i64;
+        vec![Box::new(limit), Box::new(offset)]
+    }
+
+    fn get_count_clause(table: &str) -> String {
+        format!("SELECT COUNT(*) FROM {}", table)
+    }
+
+    /// Run a `SELECT COUNT(*)` statement and read the count.
+    fn query_count(&mut self, count_stmt: &str, params: &[Box<dyn DbData>]) -> Result<u64, DbError> {
+        // COUNT(*) is never negative
+        self.execute_cached(count_stmt, params, |stmt| unsafe {
+            libsqlite3_sys::sqlite3_column_int64(stmt, 0) as u64
+        })
+    }
+
+    fn get_delete_clause(table: &str) -> String {
+        format!("DELETE FROM {}", table)
+    }
+
+    /// SQLite stores NaN as NULL, so it's rejected instead of silently
+    /// turning into `None` or failing a NOT NULL constraint.
+    fn check_not_nan(value: f64, index: c_int) -> Result<(), DbError> {
+        if value.is_nan() {
+            return Err(DbError::Other(format!("Cannot bind NaN to parameter {}", index)));
         }
+        Ok(())
     }
 
-    fn get_delete_clause(schema_name: &str) -> String {
-        format!("DELETE FROM {}", schema_name)
+    fn bind_params_to_stmt(stmt: *mut sqlite3_stmt, params: &[Box<dyn DbData>], mode: BindMode) -> Result<(), DbError> {
+        for (ii, param) in params.iter().enumerate() {
+            SQLiteAdaptor::bind_param_to_stmt(stmt, (ii+1) as c_int, param.as_ref(), mode)?;
+        }
+        Ok(())
     }
 
-    fn bind_params_to_stmt(stmt: *mut sqlite3_stmt, params: &Vec<Box<dyn DbData>>) {
-        for ii in 0..params.len() {
-            let db_data_box = params.get(ii).unwrap();
-            let i = (ii+1) as i32;
-            unsafe{
-                match db_data_box.db_data_type() {
-                    yoshino_core::db::DbDataType::Int => {
-                        let data_ptr = db_data_box.db_data_ptr() as *const i64;
+    /// Bind the value to the `i`-th parameter of the statement, counting from 1.
+    fn bind_param_to_stmt(stmt: *mut sqlite3_stmt, i: c_int, db_data_box: &dyn DbData, mode: BindMode) -> Result<(), DbError> {
+        let db_handler = unsafe { libsqlite3_sys::sqlite3_db_handle(stmt) };
+        let destructor = match mode {
+            BindMode::Borrow => libsqlite3_sys::SQLITE_STATIC(),
+            BindMode::Copy => libsqlite3_sys::SQLITE_TRANSIENT()
+        };
+        unsafe{
+            let return_value = match db_data_box.db_data_type() {
+                yoshino_core::db::DbDataType::Int => {
+                    let data_ptr = db_data_box.db_data_ptr() as *const i64;
+                    let data_value = *data_ptr;
+                    libsqlite3_sys::sqlite3_bind_int64(stmt, i, data_value)
+                }
+                yoshino_core::db::DbDataType::Float => {
+                    let data_ptr = db_data_box.db_data_ptr() as *const f64;
+                    let data_value = *data_ptr;
+                    SQLiteAdaptor::check_not_nan(data_value, i)?;
+                    libsqlite3_sys::sqlite3_bind_double(stmt, i, data_value)
+                }
+                yoshino_core::db::DbDataType::NullableFloat => {
+                    let data_ptr = db_data_box.db_data_ptr() as *const f64;
+                    if !data_ptr.is_null() {
                         let data_value = *data_ptr;
-                        libsqlite3_sys::sqlite3_bind_int64(stmt, i, data_value);
+                        SQLiteAdaptor::check_not_nan(data_value, i)?;
+                        libsqlite3_sys::sqlite3_bind_double(stmt, i, data_value)
+                    } else {
+                        libsqlite3_sys::sqlite3_bind_null(stmt, i)
                     }
-                    yoshino_core::db::DbDataType::Float => {
-                        let data_ptr = db_data_box.db_data_ptr() as *const f64;
+                }
+                yoshino_core::db::DbDataType::NullableInt | yoshino_core::db::DbDataType::RowID => {
+                    let data_ptr = db_data_box.db_data_ptr() as *const i64;
+                    if !data_ptr.is_null() {
                         let data_value = *data_ptr;
-                        libsqlite3_sys::sqlite3_bind_double(stmt, i, data_value);
-                    }
-                    yoshino_core::db::DbDataType::NullableInt | yoshino_core::db::DbDataType::RowID => {
-                        let data_ptr = db_data_box.db_data_ptr() as *const i64;
-                        if data_ptr != ptr::null() {
-                            let data_value = *data_ptr;
-                            libsqlite3_sys::sqlite3_bind_int64(stmt, i, data_value);
-                        } else {
-                            libsqlite3_sys::sqlite3_bind_null(stmt, i);
-                        }
+                        libsqlite3_sys::sqlite3_bind_int64(stmt, i, data_value)
+                    } else {
+                        libsqlite3_sys::sqlite3_bind_null(stmt, i)
                     }
-                    yoshino_core::db::DbDataType::Text | yoshino_core::db::DbDataType::NullableText => {
-                        let data_ptr = db_data_box.db_data_ptr() as *const i8;
+                }
+                yoshino_core::db::DbDataType::Text | yoshino_core::db::DbDataType::NullableText => {
+                    let data_ptr = db_data_box.db_data_ptr() as *const i8;
+                    let data_len = db_data_box.db_data_len();
+                    libsqlite3_sys::sqlite3_bind_text(stmt, i, data_ptr, data_len as i32, destructor)
+                }
+                yoshino_core::db::DbDataType::Blob | yoshino_core::db::DbDataType::NullableBlob => {
+                    let data_ptr = db_data_box.db_data_ptr();
+                    if !data_ptr.is_null() {
                         let data_len = db_data_box.db_data_len();
-                        libsqlite3_sys::sqlite3_bind_text(stmt, i, data_ptr, data_len as i32, libsqlite3_sys::SQLITE_TRANSIENT());
+                        libsqlite3_sys::sqlite3_bind_blob(stmt, i, data_ptr, data_len as i32, destructor)
+                    } else {
+                        libsqlite3_sys::sqlite3_bind_null(stmt, i)
                     }
                 }
-            }
+            };
+            db_try!(db_handler, return_value);
         }
+        Ok(())
     }
 }
 
 impl Drop for SQLiteAdaptor {
     fn drop(&mut self) {
+        // statements must be finalized before the connection can be closed
+        self.stmt_cache.clear();
+        // the callbacks are dropped with the adaptor, even if the connection stays open
+        self.clear_trace();
+        self.clear_busy_handler();
+        // an interrupt can't reach the connection once it's closed
+        self.interrupt_handle.close();
         unsafe {
             libsqlite3_sys::sqlite3_close(self.db_handler);
         }
@@ -206,219 +1034,332 @@ impl Drop for SQLiteAdaptor {
 }
 
 pub struct SQLiteRowIterator<T: Schema + 'static> {
-    stmt: *mut sqlite3_stmt,
+    stmt: Statement,
+    /// types of the fields of `T`, read once instead of for every row
+    field_types: Vec<DbDataType>,
+    /// whether the statement is done or has failed, so it won't be stepped again
+    finished: bool,
+    /// the `set_lossy_utf8` mode of the adaptor when the query started
+    lossy_utf8: bool,
     phantom: PhantomData<T>
 }
 
+impl<T: Schema> SQLiteRowIterator<T> {
+    /// Iterate over the rows of the statement, which selects the fields of `T` in order.
+    fn new(stmt: Statement, lossy_utf8: bool) -> SQLiteRowIterator<T> {
+        let field_types = T::get_fields().into_iter().map(|(_, field_type)| field_type).collect();
+        SQLiteRowIterator { stmt, field_types, finished: false, lossy_utf8, phantom: PhantomData }
+    }
+
+    /// Copy the text in column `i` of the current row. Invalid UTF-8 fails with
+    /// `DbError::InvalidUtf8`, or is replaced with U+FFFD if `lossy_utf8` is set.
+    unsafe fn read_text_column(stmt: *mut sqlite3_stmt, i: c_int, lossy_utf8: bool) -> Result<String, DbError> {
+        let str_ptr = libsqlite3_sys::sqlite3_column_text(stmt, i);
+        // the length must be read after the text, as it's the length of the converted value
+        let str_len = libsqlite3_sys::sqlite3_column_bytes(stmt, i) as usize;
+        if str_ptr.is_null() || str_len == 0 {
+            return Ok(String::new());
+        }
+        let bytes = std::slice::from_raw_parts(str_ptr, str_len);
+        if lossy_utf8 {
+            Ok(String::from_utf8_lossy(bytes).into_owned())
+        } else {
+            String::from_utf8(bytes.to_vec()).map_err(|_| DbError::InvalidUtf8 { column: i as usize })
+        }
+    }
+
+    /// Read the current row of the statement as a record, whose fields have the types in order.
+    /// Fail if a value isn't valid for its field, e.g. an unknown enum variant.
+    unsafe fn read_row(stmt: *mut sqlite3_stmt, field_types: &[DbDataType], lossy_utf8: bool) -> Result<T, DbError> {
+        let values = field_types.iter().enumerate()
+            .map(|(i, field_type)| Self::read_column(stmt, i as c_int, field_type, lossy_utf8))
+            .collect::<Result<Vec<_>, DbError>>()?;
+        T::try_create_with_values(values)
+    }
+
+    /// Read column `i` of the current row as a value of the field type.
+    /// Only text can fail to be read, see `read_text_column`.
+    unsafe fn read_column(stmt: *mut sqlite3_stmt, i: c_int, field_type: &DbDataType, lossy_utf8: bool) -> Result<Box<dyn DbData>, DbError> {
+        let value: Box<dyn DbData> = match field_type {
+            DbDataType::NullableInt => {
+                let type_code = unsafe {
+                    libsqlite3_sys::sqlite3_column_type(stmt, i)
+                };
+                let v = match type_code {
+                    libsqlite3_sys::SQLITE_INTEGER => {
+                        Some(unsafe {
+                            libsqlite3_sys::sqlite3_column_int64(stmt, i)
+                        })
+                    }
+                    _ => {
+                        None
+                    }
+                };
+                Box::new(v)
+            }
+            DbDataType::Int => {
+                let v = unsafe { libsqlite3_sys::sqlite3_column_int64(stmt, i) as i64};
+                Box::new(v)
+            }
+            DbDataType::Float => {
+                let v = unsafe {
+                    libsqlite3_sys::sqlite3_column_double(stmt, i) as f64
+                };
+                Box::new(v)
+            }
+            DbDataType::NullableFloat => {
+                let type_code = unsafe {
+                    libsqlite3_sys::sqlite3_column_type(stmt, i)
+                };
+                let v = match type_code {
+                    libsqlite3_sys::SQLITE_NULL => None,
+                    _ => {
+                        Some(unsafe {
+                            libsqlite3_sys::sqlite3_column_double(stmt, i)
+                        })
+                    }
+                };
+                Box::new(v)
+            }
+            DbDataType::Blob => {
+                let v = unsafe { Self::read_blob_column(stmt, i) };
+                Box::new(v)
+            }
+            DbDataType::NullableBlob => {
+                let type_code = unsafe {
+                    libsqlite3_sys::sqlite3_column_type(stmt, i)
+                };
+                let v = match type_code {
+                    libsqlite3_sys::SQLITE_NULL => None,
+                    _ => Some(unsafe { Self::read_blob_column(stmt, i) })
+                };
+                Box::new(v)
+            }
+            DbDataType::RowID => {
+                let v = unsafe { libsqlite3_sys::sqlite3_column_int64(stmt, i) as i64};
+                Box::new(yoshino_core::RowID::ID(v))
+            }
+            DbDataType::Text => {
+                let v = unsafe { Self::read_text_column(stmt, i, lossy_utf8)? };
+                Box::new(v)
+            }
+            DbDataType::NullableText => {
+                let type_code = unsafe {
+                    libsqlite3_sys::sqlite3_column_type(stmt, i)
+                };
+                let v = match type_code {
+                    libsqlite3_sys::SQLITE_NULL => None,
+                    _ => Some(unsafe { Self::read_text_column(stmt, i, lossy_utf8)? })
+                };
+                Box::new(v)
+            }
+        };
+        Ok(value)
+    }
+
+    /// Copy the blob in column `i` of the current row.
+    unsafe fn read_blob_column(stmt: *mut sqlite3_stmt, i: c_int) -> Vec<u8> {
+        let blob_ptr = libsqlite3_sys::sqlite3_column_blob(stmt, i) as *const u8;
+        let blob_len = libsqlite3_sys::sqlite3_column_bytes(stmt, i) as usize;
+        // zero-length blobs are returned as a null pointer
+        if blob_ptr.is_null() || blob_len == 0 {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(blob_ptr, blob_len).to_vec()
+        }
+    }
+}
+
 impl<T: Schema> Iterator for SQLiteRowIterator<T> {
-    type Item = T;
+    type Item = Result<T, DbError>;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let stmt = self.stmt.as_ptr();
+        let db_handler = unsafe { libsqlite3_sys::sqlite3_db_handle(stmt) };
+        // the adaptor may be used on another thread meanwhile, so the
+        // connection stays locked until the error of this step is copied
+        let mutex = unsafe { libsqlite3_sys::sqlite3_db_mutex(db_handler) };
+        unsafe { libsqlite3_sys::sqlite3_mutex_enter(mutex) };
         let r = unsafe {
-            libsqlite3_sys::sqlite3_step(self.stmt)
+            libsqlite3_sys::sqlite3_step(stmt)
         };
+        let error = match r {
+            libsqlite3_sys::SQLITE_ROW | libsqlite3_sys::SQLITE_DONE => None,
+            _ => Some(sqlite_stmt_error(db_handler, stmt, r))
+        };
+        unsafe { libsqlite3_sys::sqlite3_mutex_leave(mutex) };
         match r {
-            libsqlite3_sys::SQLITE_DONE => None,
-            libsqlite3_sys::SQLITE_ROW => {
-                let mut values:Vec<Box<dyn DbData>> = vec![];
-                let fields = T::get_fields();
-                for i in 0..fields.len() {
-                    let (_, field_type) = fields.get(i).unwrap();
-                    match field_type {
-                        DbDataType::NullableInt => {
-                            let type_code = unsafe {
-                                libsqlite3_sys::sqlite3_column_type(self.stmt, i as i32)
-                            };
-                            let v = match type_code {
-                                libsqlite3_sys::SQLITE_INTEGER => {
-                                    Some(unsafe {
-                                        libsqlite3_sys::sqlite3_column_int64(self.stmt, i as i32)
-                                    })
-                                }
-                                _ => {
-                                    None
-                                }
-                            };
-                            values.push(Box::new(v));
-                            
-                        }
-                        DbDataType::Int => {
-                            let v = unsafe { libsqlite3_sys::sqlite3_column_int64(self.stmt, i as i32) as i64};
-                            values.push(Box::new(v));               
-                        }
-                        DbDataType::Float => {
-                            let v = unsafe {
-                                libsqlite3_sys::sqlite3_column_double(self.stmt, i as i32) as f64
-                            };
-                            values.push(Box::new(v));
-                        }
-                        DbDataType::RowID => {
-                            let v = unsafe { libsqlite3_sys::sqlite3_column_int64(self.stmt, i as i32) as i64};
-                            values.push(Box::new(yoshino_core::RowID::ID(v)))
-                        }
-                        DbDataType::NullableText| DbDataType::Text => {
-                            let v = unsafe { 
-                                let str_ptr = libsqlite3_sys::sqlite3_column_text(self.stmt, i as i32) as *const c_char;
-                                let str_len = libc::strlen(str_ptr);
-                                let str_copy = libc::malloc(str_len) as *mut i8;
-                                libc::strncpy(str_copy, str_ptr, str_len);
-                                String::from_raw_parts(str_copy as *mut u8, str_len, str_len)
-                            };
-                            values.push(Box::new(v));
-                        }
-                    };
-                }
-                Some(T::create_with_values(values))
+            libsqlite3_sys::SQLITE_ROW => Some(unsafe { Self::read_row(stmt, &self.field_types, self.lossy_utf8) }),
+            libsqlite3_sys::SQLITE_DONE => {
+                self.finished = true;
+                None
+            }
+            _ => {
+                self.finished = true;
+                error.map(Err)
             }
-            _ => None
-        }
-    }
-}
-
-impl<T:Schema> Drop for SQLiteRowIterator<T> {
-    fn drop(&mut self) {
-        unsafe {
-            libsqlite3_sys::sqlite3_finalize(self.stmt);
         }
     }
 }
 
 impl DbAdaptor for SQLiteAdaptor {
     fn create_table_for_schema<T: Schema>(&mut self) -> Result<(), DbError>{
+        let create_table_stmt = SQLiteAdaptor::sql_for_create_table::<T>();
+        let stmt = self.prepare_stmt(&create_table_stmt)?;
+        self.run_to_completion(stmt)
+    }
+
+    fn insert_record<T: Schema>(&mut self, record: T) -> Result<i64, DbError>{
+        let insert_record_stmt = SQLiteAdaptor::sql_for_insert::<T>();
+        self.insert_with_stmt(&insert_record_stmt, record)
+    }
+
+    fn query_all<T:Schema>(&mut self) -> Result<DbQueryResult<T>, DbError>{
+        let query_stmt = SQLiteAdaptor::sql_for_query_all::<T>();
+        self.query_with_stmt(&query_stmt, &[])
+    }
+
+    fn query_with_cond<T:Schema>(&mut self, cond: yoshino_core::query_cond::Cond) -> Result<DbQueryResult<T>, DbError> {
         let schema_name = T::get_schema_name();
         let fields = T::get_fields();
-        let create_table_stmt = SQLiteAdaptor::get_create_table_stmt_code(&schema_name, &fields);
-        let stmt_cstring = CString::new(create_table_stmt.as_str()).unwrap();
-        let mut stmt : *mut sqlite3_stmt = ptr::null_mut();
-        let mut tail = ptr::null();
-        unsafe {
-            // TODO: check result value and generate errors
-            db_try!(libsqlite3_sys::sqlite3_prepare_v2(
-                self.db_handler, 
-                stmt_cstring.as_ptr(),
-                create_table_stmt.len() as c_int,
-                &mut stmt,
-                &mut tail
-            ));
-            db_try!(libsqlite3_sys::sqlite3_step(stmt));
-            db_try!(libsqlite3_sys::sqlite3_finalize(stmt));
-        };
-        Ok(())
+        let query_stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(&schema_name), &fields);
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        let query_where_cond_stmt = format!("{} WHERE {};", query_stmt, cond_stmt);
+        self.query_with_stmt(&query_where_cond_stmt, &cond_params)
     }
 
-    fn insert_record<T: Schema>(&mut self, record: T) -> Result<(), DbError>{
+    fn query_first_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<Option<T>, DbError> {
         let schema_name = T::get_schema_name();
         let fields = T::get_fields();
-        let insert_record_stmt = SQLiteAdaptor::get_insert_value_stmt_code(&schema_name, &fields);
-        let stmt_cstring = CString::new(insert_record_stmt.as_str()).unwrap();
-        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
-        let mut tail = ptr::null();
-        let params = record.get_values();
-        unsafe {
-            db_try!(libsqlite3_sys::sqlite3_prepare_v2(
-                self.db_handler, 
-                stmt_cstring.as_ptr(),
-                insert_record_stmt.len() as c_int,
-                &mut stmt, 
-            &mut tail));
-        }
-        SQLiteAdaptor::bind_params_to_stmt(stmt, &params);
-        unsafe{
-            db_try!(libsqlite3_sys::sqlite3_step(stmt));
-            db_try!(libsqlite3_sys::sqlite3_finalize(stmt));
-        }
-        Ok(())
+        let query_stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(&schema_name), &fields);
+        let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        let query_where_cond_first_stmt = format!("{} WHERE {} LIMIT 1;", query_stmt, cond_stmt);
+        let field_types: Vec<DbDataType> = fields.into_iter().map(|(_, field_type)| field_type).collect();
+        let lossy_utf8 = self.lossy_utf8;
+        self.execute_cached(&query_where_cond_first_stmt, &cond_params, |stmt| unsafe {
+            // there is no data in the statement if the step is done without a row
+            if libsqlite3_sys::sqlite3_data_count(stmt) == 0 {
+                None
+            } else {
+                Some(SQLiteRowIterator::<T>::read_row(stmt, &field_types, lossy_utf8))
+            }
+        })?.transpose()
     }
 
-    fn query_all<T:Schema>(&mut self) -> Result<DbQueryResult<T>, DbError>{
+    fn query_sorted<T: Schema>(&mut self, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError> {
         let schema_name = T::get_schema_name();
         let fields = T::get_fields();
-        let query_stmt = SQLiteAdaptor::get_query_clause(&schema_name, &fields) + ";";
-        let stmt_cstring = CString::new(query_stmt.as_str()).unwrap();
-        let mut stmt : *mut sqlite3_stmt = ptr::null_mut();
-        let mut tail = ptr::null();
-        unsafe {
-            // TODO: check result value and generate errors
-            db_try!(libsqlite3_sys::sqlite3_prepare_v2(
-                self.db_handler, 
-                stmt_cstring.as_ptr(),
-                query_stmt.len() as c_int,
-                &mut stmt,
-                &mut tail
-            ));
-        };
-        let iter:Box<SQLiteRowIterator<T>> = Box::new(SQLiteRowIterator{stmt, phantom: PhantomData});
-        Ok(DbQueryResult{data_iter: iter})
+        let query_stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(&schema_name), &fields);
+        let order_by_clause = SQLiteAdaptor::get_order_by_clause(&fields, &order)?;
+        let query_sorted_stmt = format!("{}{};", query_stmt, order_by_clause);
+        self.query_with_stmt(&query_sorted_stmt, &[])
     }
 
-    fn query_with_cond<T:Schema>(&mut self, cond: yoshino_core::query_cond::Cond) -> Result<DbQueryResult<T>, DbError> {
+    fn query_with_cond_sorted<T: Schema>(&mut self, cond: yoshino_core::Cond, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError> {
         let schema_name = T::get_schema_name();
         let fields = T::get_fields();
-        let query_stmt = SQLiteAdaptor::get_query_clause(&schema_name, &fields);
+        let query_stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(&schema_name), &fields);
+        let order_by_clause = SQLiteAdaptor::get_order_by_clause(&fields, &order)?;
         let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        let query_where_cond_stmt = format!("{} WHERE {};", query_stmt, cond_stmt);
-        let stmt_cstring = CString::new(query_where_cond_stmt.as_str()).unwrap();
-        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
-        let mut tail = ptr::null();
-        unsafe {
-            db_try!(
-                libsqlite3_sys::sqlite3_prepare_v2(
-                    self.db_handler, 
-                    stmt_cstring.as_ptr(), 
-                    query_where_cond_stmt.len() as c_int,
-                     &mut stmt,
-                     &mut tail
-                ));
-            SQLiteAdaptor::bind_params_to_stmt(stmt, &cond_params);
-        }
-        let iter:Box<SQLiteRowIterator<T>> = Box::new(SQLiteRowIterator{stmt, phantom: PhantomData});
-        Ok(DbQueryResult{data_iter: iter})
+        let query_where_cond_sorted_stmt = format!("{} WHERE {}{};", query_stmt, cond_stmt, order_by_clause);
+        self.query_with_stmt(&query_where_cond_sorted_stmt, &cond_params)
     }
 
-    fn delete_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<(), DbError> {
+    fn query_limit<T: Schema>(&mut self, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError> {
         let schema_name = T::get_schema_name();
-        let delete_clause = SQLiteAdaptor::get_delete_clause(&schema_name);
+        let fields = T::get_fields();
+        let query_stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(&schema_name), &fields);
+        let query_limit_stmt = format!("{} LIMIT ? OFFSET ?;", query_stmt);
+        self.query_with_stmt(&query_limit_stmt, &SQLiteAdaptor::get_limit_params(limit, offset))
+    }
+
+    fn query_with_cond_limit<T: Schema>(&mut self, cond: yoshino_core::Cond, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError> {
+        let schema_name = T::get_schema_name();
+        let fields = T::get_fields();
+        let query_stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(&schema_name), &fields);
+        let (cond_stmt, mut cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        let query_where_cond_limit_stmt = format!("{} WHERE {} LIMIT ? OFFSET ?;", query_stmt, cond_stmt);
+        cond_params.extend(SQLiteAdaptor::get_limit_params(limit, offset));
+        self.query_with_stmt(&query_where_cond_limit_stmt, &cond_params)
+    }
+
+    fn count_all<T: Schema>(&mut self) -> Result<u64, DbError> {
+        let count_stmt = SQLiteAdaptor::get_count_clause(&quote_identifier(&T::get_schema_name())) + ";";
+        self.query_count(&count_stmt, &[])
+    }
+
+    fn count_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<u64, DbError> {
+        let count_clause = SQLiteAdaptor::get_count_clause(&quote_identifier(&T::get_schema_name()));
         let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        let delete_where_cond_stmt = format!("{} WHERE {};", delete_clause, cond_stmt);
-        let stmt_cstring = CString::new(delete_where_cond_stmt.as_str()).unwrap();
-        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
-        let mut tail = ptr::null();
-        unsafe {
-            db_try!(libsqlite3_sys::sqlite3_prepare_v2(
-                self.db_handler, 
-                stmt_cstring.as_ptr(),
-                delete_where_cond_stmt.len() as c_int,
-                &mut stmt, &mut tail));
-            SQLiteAdaptor::bind_params_to_stmt(stmt, &cond_params);
-            db_try!(libsqlite3_sys::sqlite3_step(stmt));
-            db_try!(libsqlite3_sys::sqlite3_finalize(stmt));
-        }
-        Ok(())
+        let count_where_cond_stmt = format!("{} WHERE {};", count_clause, cond_stmt);
+        self.query_count(&count_where_cond_stmt, &cond_params)
     }
 
-    fn update_with_cond<T: Schema>(&mut self, cond:yoshino_core::Cond, record: T) -> Result<(), DbError> {
+    fn aggregate_with_cond<T: Schema, V: ColumnValue>(&mut self, aggregate: Aggregate, cond: yoshino_core::Cond) -> Result<Option<V>, DbError> {
         let schema_name = T::get_schema_name();
         let fields = T::get_fields();
-        let update_clause = SQLiteAdaptor::get_update_clause(&schema_name, &fields);
+        let field_name = aggregate.field_name();
+        let (_, field_type) = fields.iter().find(|(name, _)| name == field_name)
+            .ok_or_else(|| DbError::Other(format!("Unknown field in {}: {}", schema_name, field_name)))?;
+        aggregate.check_types(field_type, &V::column_data_type())?;
         let (cond_stmt, cond_params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        let update_where_cond_stmt = format!("{} WHERE {};", update_clause, cond_stmt);
-        let mut update_stmt_params = record.get_values();
-        update_stmt_params.extend(cond_params);
+        let aggregate_stmt = format!("SELECT {}({}) FROM {} WHERE {};", aggregate.function_name(), quote_identifier(field_name), quote_identifier(&schema_name), cond_stmt);
+        let value_type = get_nullable_type(&V::column_data_type());
+        let lossy_utf8 = self.lossy_utf8;
+        self.execute_cached(&aggregate_stmt, &cond_params, |stmt| {
+            let value = unsafe { SQLiteRowIterator::<T>::read_column(stmt, 0, &value_type, lossy_utf8)? };
+            // the aggregate of no records is NULL
+            if value.db_data_ptr().is_null() {
+                Ok(None)
+            } else {
+                V::try_from_column_data(&value).map(Some)
+            }
+        })?
+    }
 
-        let stmt_cstring = CString::new(update_where_cond_stmt.as_str()).unwrap();
-        let mut stmt: *mut sqlite3_stmt = ptr::null_mut();
-        let mut tail = ptr::null();
+    fn delete_with_cond<T: Schema>(&mut self, cond: yoshino_core::Cond) -> Result<(), DbError> {
+        self.delete_rows::<T>(cond)?;
+        Ok(())
+    }
 
-        unsafe {
-            db_try!(
-                libsqlite3_sys::sqlite3_prepare_v2(
-                    self.db_handler,
-                    stmt_cstring.as_ptr(),
-                    update_where_cond_stmt.len() as c_int,
-                    &mut stmt, &mut tail)
-                );
-            SQLiteAdaptor::bind_params_to_stmt(stmt, &update_stmt_params);
-            db_try!(libsqlite3_sys::sqlite3_step(stmt));
-            db_try!(libsqlite3_sys::sqlite3_finalize(stmt));
+    fn delete_all<T: Schema>(&mut self) -> Result<u64, DbError> {
+        let delete_stmt = SQLiteAdaptor::get_delete_clause(&quote_identifier(&T::get_schema_name())) + ";";
+        let db_handler = self.db_handler;
+        self.execute_cached(&delete_stmt, &[], |_| unsafe {
+            libsqlite3_sys::sqlite3_changes(db_handler) as u64
+        })
+    }
+
+    fn delete_record<T: Schema>(&mut self, record: &T) -> Result<bool, DbError> {
+        let (cond, _) = SQLiteAdaptor::get_stored_record_cond(record, "delete")?;
+        let changes = self.delete_rows::<T>(cond)?;
+        Ok(changes > 0)
+    }
+
+    fn update_with_cond<T: Schema>(&mut self, cond:yoshino_core::Cond, record: T) -> Result<(), DbError> {
+        self.update_rows(cond, &record)?;
+        Ok(())
+    }
+
+    fn update_fields_with_cond<T: Schema>(&mut self, updates: FieldUpdates, cond: yoshino_core::Cond) -> Result<u64, DbError> {
+        let field_values = updates.into_field_values::<T>()?;
+        let assignments: Vec<String> = field_values.iter().map(|(field_name, _)| format!("{} = ?", quote_identifier(field_name))).collect();
+        let mut params: Vec<Box<dyn DbData>> = field_values.into_iter().map(|(_, value)| value).collect();
+        let cond_stmt = SQLiteAdaptor::get_condition_stmt(&cond, &mut params);
+        let update_stmt = format!("UPDATE {} SET {} WHERE {};", quote_identifier(&T::get_schema_name()), assignments.join(", "), cond_stmt);
+        let db_handler = self.db_handler;
+        self.execute_cached(&update_stmt, &params, |_| unsafe {
+            libsqlite3_sys::sqlite3_changes(db_handler) as u64
+        })
+    }
+
+    fn update_record<T: Schema>(&mut self, record: &T) -> Result<(), DbError> {
+        let (cond, key) = SQLiteAdaptor::get_stored_record_cond(record, "update")?;
+        let changes = self.update_rows(cond, record)?;
+        if changes == 0 {
+            return Err(DbError::Other(format!("No {} record with {}", T::get_schema_name(), key)));
         }
         Ok(())
     }
@@ -0,0 +1,59 @@
+//! Maintenance of long-lived databases: compacting and checking for corruption.
+use std::ffi::CStr;
+use yoshino_core::db::DbError;
+use crate::SQLiteAdaptor;
+
+impl SQLiteAdaptor {
+    /// Rebuild the database file to reclaim the space of deleted records and
+    /// defragment it. It needs as much free disk space as the database takes,
+    /// and can't run inside a transaction.
+    pub fn vacuum(&mut self) -> Result<(), DbError> {
+        self.check_no_transaction("VACUUM")?;
+        self.execute_sql("VACUUM;")
+    }
+
+    /// Write a compacted copy of the database to a new file at `path`, leaving
+    /// the database itself unchanged. It fails if the file exists and isn't
+    /// empty, and can't run inside a transaction.
+    pub fn vacuum_into(&mut self, path: &str) -> Result<(), DbError> {
+        self.check_no_transaction("VACUUM INTO")?;
+        self.execute_raw("VACUUM INTO ?;", &[&path.to_string()])?;
+        Ok(())
+    }
+
+    /// Check the database for corruption with `PRAGMA integrity_check` and
+    /// return the problems it finds, at most `max_errors` of them, or SQLite's
+    /// default of 100 if it's 0. No problem means the database is intact.
+    pub fn integrity_check(&mut self, max_errors: u32) -> Result<Vec<String>, DbError> {
+        let stmt = self.prepare_stmt(&format!("PRAGMA integrity_check({});", max_errors))?;
+        let mut problems = Vec::new();
+        unsafe {
+            loop {
+                let return_value = libsqlite3_sys::sqlite3_step(stmt.as_ptr());
+                db_try!(self.db_handler, stmt.as_ptr(), return_value);
+                if return_value != libsqlite3_sys::SQLITE_ROW {
+                    break;
+                }
+                let value_ptr = libsqlite3_sys::sqlite3_column_text(stmt.as_ptr(), 0);
+                if !value_ptr.is_null() {
+                    problems.push(CStr::from_ptr(value_ptr as *const _).to_string_lossy().into_owned());
+                }
+            }
+        }
+        // an intact database is reported as a single "ok" row
+        if problems.len() == 1 && problems[0] == "ok" {
+            problems.clear();
+        }
+        Ok(problems)
+    }
+
+    /// Fail with a clear error for a statement SQLite can't run inside a transaction.
+    fn check_no_transaction(&self, stmt_name: &str) -> Result<(), DbError> {
+        let is_autocommit = unsafe { libsqlite3_sys::sqlite3_get_autocommit(self.db_handler) } != 0;
+        if is_autocommit {
+            Ok(())
+        } else {
+            Err(DbError::Other(format!("{} can't run inside a transaction", stmt_name)))
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Ordered migrations of a database, tracked with `PRAGMA user_version`.
+use yoshino_core::db::DbError;
+use crate::SQLiteAdaptor;
+
+/// A step to bring the database to `version`, run once by `SQLiteAdaptor::run_migrations`.
+pub struct Migration {
+    version: u32,
+    name: String,
+    step: MigrationStep
+}
+
+/// A migration step run on the adaptor inside the migration's transaction.
+type MigrationFn = Box<dyn Fn(&mut SQLiteAdaptor) -> Result<(), DbError>>;
+
+enum MigrationStep {
+    Sql(String),
+    Function(MigrationFn)
+}
+
+impl Migration {
+    /// A migration that runs a script of SQL statements separated by `;`.
+    pub fn from_sql(version: u32, name: &str, script: &str) -> Migration {
+        Migration { version, name: name.to_string(), step: MigrationStep::Sql(script.to_string()) }
+    }
+
+    /// A migration that runs `step` on the adaptor, e.g. to create a table for
+    /// a schema or rewrite records. It already runs inside a transaction, so
+    /// it must not begin, commit or roll back one.
+    pub fn from_fn(version: u32, name: &str, step: impl Fn(&mut SQLiteAdaptor) -> Result<(), DbError> + 'static) -> Migration {
+        Migration { version, name: name.to_string(), step: MigrationStep::Function(Box::new(step)) }
+    }
+
+    /// The version of the database once the migration is applied.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The name of the migration, used in the report and in errors.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The migrations applied by `SQLiteAdaptor::run_migrations`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppliedReport {
+    /// the version of the database before the migrations
+    pub previous_version: u32,
+    /// the version of the database after the migrations
+    pub current_version: u32,
+    /// versions and names of the applied migrations, in order
+    pub applied: Vec<(u32, String)>
+}
+
+impl SQLiteAdaptor {
+    /// Apply the migrations with a higher version than the `user_version` of
+    /// the database, in order. Each migration runs in its own transaction,
+    /// which also sets `user_version` to its version, so running the same list
+    /// again applies nothing.
+    ///
+    /// The versions must be increasing and between 1 and `i32::MAX`, or no
+    /// migration is applied. If a migration fails, it's rolled back and the
+    /// error names it, keeping its kind if the database reported it. The
+    /// database stays at the version of the migration before.
+    pub fn run_migrations(&mut self, migrations: &[Migration]) -> Result<AppliedReport, DbError> {
+        check_migration_versions(migrations)?;
+        let previous_version = self.user_version()?;
+        let mut report = AppliedReport { previous_version, current_version: previous_version, applied: Vec::new() };
+        for migration in migrations.iter().filter(|migration| migration.version > previous_version) {
+            self.apply_migration(migration).map_err(|mut error| {
+                let context = format!("Migration {} ({}) failed, the database stays at version {}",
+                    migration.version, migration.name, report.current_version);
+                match error.failure_mut() {
+                    Some(failure) => {
+                        failure.message = format!("{}: {}", context, failure.message);
+                        error
+                    }
+                    None => DbError::Other(format!("{}: {}", context, error))
+                }
+            })?;
+            report.current_version = migration.version;
+            report.applied.push((migration.version, migration.name.clone()));
+        }
+        Ok(report)
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), DbError> {
+        let transaction = self.begin_transaction()?;
+        match &migration.step {
+            MigrationStep::Sql(script) => transaction.adaptor.execute_sql(script)?,
+            MigrationStep::Function(step) => step(&mut *transaction.adaptor)?
+        }
+        transaction.adaptor.execute_sql(&format!("PRAGMA user_version = {};", migration.version))?;
+        transaction.commit()
+    }
+
+    fn user_version(&mut self) -> Result<u32, DbError> {
+        let version = self.run_pragma_stmt("PRAGMA user_version;")?;
+        version.parse().map_err(|_| DbError::Other(format!("The user version {} of the database is no migration version", version)))
+    }
+}
+
+fn check_migration_versions(migrations: &[Migration]) -> Result<(), DbError> {
+    let mut last: Option<&Migration> = None;
+    for migration in migrations {
+        if migration.version == 0 || migration.version > i32::MAX as u32 {
+            return Err(DbError::Other(format!("Version {} of migration {} is out of range", migration.version, migration.name)));
+        }
+        if let Some(last) = last {
+            if migration.version == last.version {
+                return Err(DbError::Other(format!("Migrations {} and {} have the same version {}", last.name, migration.name, migration.version)));
+            }
+            if migration.version < last.version {
+                return Err(DbError::Other(format!("Migration {} (version {}) comes after {} (version {})",
+                    migration.name, migration.version, last.name, last.version)));
+            }
+        }
+        last = Some(migration);
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! Flags to open SQLite databases with.
+use std::ops::{BitOr, BitOrAssign};
+use std::os::raw::c_int;
+
+/// Flags passed to `sqlite3_open_v2` when opening a database. Combine them with `|`.
+///
+/// The default is `READWRITE | CREATE`, which is how `SQLiteAdaptor::open` opens files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpenFlags(c_int);
+
+impl OpenFlags {
+    /// Open the database read-only. Writes will fail with an error.
+    pub const READONLY: OpenFlags = OpenFlags(libsqlite3_sys::SQLITE_OPEN_READONLY);
+    /// Open the database for reading and writing.
+    pub const READWRITE: OpenFlags = OpenFlags(libsqlite3_sys::SQLITE_OPEN_READWRITE);
+    /// Create the database if it doesn't exist. Must be used with `READWRITE`.
+    pub const CREATE: OpenFlags = OpenFlags(libsqlite3_sys::SQLITE_OPEN_CREATE);
+    /// Interpret the filename as a URI, e.g. `file:data.db?mode=ro`.
+    pub const URI: OpenFlags = OpenFlags(libsqlite3_sys::SQLITE_OPEN_URI);
+    /// Open the connection in multi-thread mode, without a mutex on the connection.
+    #[deprecated(note = "adaptors can be sent to other threads, so connections are always opened with `FULLMUTEX`")]
+    pub const NOMUTEX: OpenFlags = OpenFlags(libsqlite3_sys::SQLITE_OPEN_NOMUTEX);
+    /// Open the connection in serialized mode, with a mutex on the connection.
+    /// It's always added when opening a connection.
+    pub const FULLMUTEX: OpenFlags = OpenFlags(libsqlite3_sys::SQLITE_OPEN_FULLMUTEX);
+
+    /// The raw flags to pass to SQLite.
+    pub fn bits(&self) -> c_int {
+        self.0
+    }
+
+    /// Whether all flags in `other` are set in this one.
+    pub fn contains(&self, other: OpenFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for OpenFlags {
+    fn default() -> OpenFlags {
+        OpenFlags::READWRITE | OpenFlags::CREATE
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+    fn bitor(self, rhs: OpenFlags) -> OpenFlags {
+        OpenFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for OpenFlags {
+    fn bitor_assign(&mut self, rhs: OpenFlags) {
+        self.0 |= rhs.0;
+    }
+}
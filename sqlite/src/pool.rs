@@ -0,0 +1,178 @@
+//! A pool of connections to the same database file, shared between threads.
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use yoshino_core::db::DbError;
+use crate::{JournalMode, SQLiteAdaptor, DEFAULT_STMT_CACHE_CAPACITY};
+
+/// A pool of at most `max_size` connections to a database file. Cloning the
+/// pool shares its connections.
+///
+/// Connections are opened when needed and put in WAL mode, so readers don't
+/// block the writer. A connection that fails a check when it's taken from the
+/// pool is discarded instead of being handed out.
+///
+/// A returned connection is reset to how it was opened: an open transaction
+/// is rolled back, attached databases are detached, and the trace callback,
+/// busy handler or timeout, lossy UTF-8 mode, statement cache capacity and
+/// foreign key enforcement are restored. A connection that can't be reset,
+/// e.g. as a query on an attached database is still being read, is closed.
+/// Other pragmas set with `pragma` are kept.
+#[derive(Clone)]
+pub struct SQLitePool {
+    inner: Arc<PoolInner>
+}
+
+struct PoolInner {
+    filename: String,
+    max_size: usize,
+    state: Mutex<PoolState>,
+    /// notified when a connection is returned or a slot is freed
+    available: Condvar
+}
+
+impl PoolInner {
+    fn lock_state(&self) -> MutexGuard<'_, PoolState> {
+        // a poisoned lock only means another thread panicked, the state is still valid
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+struct PoolState {
+    idle: Vec<SQLiteAdaptor>,
+    /// number of open connections, idle or checked out
+    open_count: usize
+}
+
+/// A connection checked out of a `SQLitePool`. It's returned to the pool when dropped.
+pub struct PooledSQLiteAdaptor {
+    adaptor: Option<SQLiteAdaptor>,
+    pool: Arc<PoolInner>
+}
+
+impl SQLitePool {
+    /// Create a pool for the database file, creating the file if it doesn't
+    /// exist. One connection is opened to check that the database can be used.
+    pub fn new(filename: &str, max_size: usize) -> Result<SQLitePool, DbError> {
+        if max_size == 0 {
+            return Err(DbError::Other("A pool needs at least one connection".to_string()));
+        }
+        let adaptor = SQLitePool::open_connection(filename)?;
+        Ok(SQLitePool {
+            inner: Arc::new(PoolInner {
+                filename: filename.to_string(),
+                max_size,
+                state: Mutex::new(PoolState { idle: vec![adaptor], open_count: 1 }),
+                available: Condvar::new()
+            })
+        })
+    }
+
+    /// Check out a connection, waiting as long as it takes for one to be returned
+    /// if all of them are in use.
+    pub fn get(&self) -> Result<PooledSQLiteAdaptor, DbError> {
+        self.checkout(None)
+    }
+
+    /// Check out a connection, failing with `DbError::PoolTimeout` if none is
+    /// returned within `timeout` while all of them are in use.
+    pub fn get_timeout(&self, timeout: Duration) -> Result<PooledSQLiteAdaptor, DbError> {
+        self.checkout(Some(Instant::now() + timeout))
+    }
+
+    fn checkout(&self, deadline: Option<Instant>) -> Result<PooledSQLiteAdaptor, DbError> {
+        let mut state = self.inner.lock_state();
+        loop {
+            while let Some(adaptor) = state.idle.pop() {
+                if adaptor.execute_sql("SELECT 1;").is_ok() {
+                    return Ok(PooledSQLiteAdaptor { adaptor: Some(adaptor), pool: self.inner.clone() });
+                }
+                // the connection is broken, so it's closed to make room for a new one
+                state.open_count -= 1;
+            }
+            if state.open_count < self.inner.max_size {
+                state.open_count += 1;
+                drop(state);
+                // the database is opened without holding the lock, as it may take a while
+                return match SQLitePool::open_connection(&self.inner.filename) {
+                    Ok(adaptor) => Ok(PooledSQLiteAdaptor { adaptor: Some(adaptor), pool: self.inner.clone() }),
+                    Err(e) => {
+                        self.inner.lock_state().open_count -= 1;
+                        self.inner.available.notify_one();
+                        Err(e)
+                    }
+                };
+            }
+            state = match deadline {
+                None => self.inner.available.wait(state).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(DbError::PoolTimeout);
+                    }
+                    self.inner.available.wait_timeout(state, deadline - now).unwrap_or_else(|e| e.into_inner()).0
+                }
+            };
+        }
+    }
+
+    fn open_connection(filename: &str) -> Result<SQLiteAdaptor, DbError> {
+        let mut adaptor = SQLiteAdaptor::open(filename)?;
+        adaptor.set_journal_mode(JournalMode::Wal)?;
+        Ok(adaptor)
+    }
+}
+
+impl Deref for PooledSQLiteAdaptor {
+    type Target = SQLiteAdaptor;
+    fn deref(&self) -> &SQLiteAdaptor {
+        self.adaptor.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledSQLiteAdaptor {
+    fn deref_mut(&mut self) -> &mut SQLiteAdaptor {
+        self.adaptor.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledSQLiteAdaptor {
+    fn drop(&mut self) {
+        if let Some(mut adaptor) = self.adaptor.take() {
+            if adaptor.reset_session().is_ok() {
+                self.pool.lock_state().idle.push(adaptor);
+            } else {
+                // closed before taking the lock, as it may take a while
+                drop(adaptor);
+                self.pool.lock_state().open_count -= 1;
+            }
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+impl SQLiteAdaptor {
+    /// Undo what the user of a pooled connection changed, so the next one gets
+    /// it as it was opened.
+    fn reset_session(&mut self) -> Result<(), DbError> {
+        // the next user of the connection mustn't be interrupted by this one
+        self.renew_interrupt_handle();
+        if unsafe { libsqlite3_sys::sqlite3_get_autocommit(self.db_handler) } == 0 {
+            self.execute_sql("ROLLBACK;")?;
+        }
+        loop {
+            // main and temp are the databases 0 and 1, the attached ones follow
+            let attached = self.run_pragma_stmt("SELECT name FROM pragma_database_list WHERE seq > 1 LIMIT 1;")?;
+            if attached.is_empty() {
+                break;
+            }
+            self.execute_raw("DETACH DATABASE ?1;", &[&attached])?;
+        }
+        self.set_trace(None)?;
+        // turning the timeout off removes the busy handler too
+        self.set_busy_timeout(Duration::ZERO)?;
+        self.set_lossy_utf8(false);
+        self.set_stmt_cache_capacity(DEFAULT_STMT_CACHE_CAPACITY);
+        self.set_foreign_keys(true)
+    }
+}
@@ -0,0 +1,122 @@
+//! Helpers to configure the connection with PRAGMA statements.
+use std::ffi::CStr;
+use yoshino_core::db::DbError;
+use crate::SQLiteAdaptor;
+
+/// Journal mode of the database, set with `PRAGMA journal_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off
+}
+
+impl JournalMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "delete",
+            JournalMode::Truncate => "truncate",
+            JournalMode::Persist => "persist",
+            JournalMode::Memory => "memory",
+            JournalMode::Wal => "wal",
+            JournalMode::Off => "off"
+        }
+    }
+}
+
+/// Synchronous flag of the connection, set with `PRAGMA synchronous`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra
+}
+
+impl Synchronous {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "0",
+            Synchronous::Normal => "1",
+            Synchronous::Full => "2",
+            Synchronous::Extra => "3"
+        }
+    }
+}
+
+impl SQLiteAdaptor {
+    /// Set the journal mode of the database.
+    ///
+    /// Fails if SQLite keeps another mode, e.g. in-memory databases can't use `JournalMode::Wal`.
+    pub fn set_journal_mode(&mut self, mode: JournalMode) -> Result<(), DbError> {
+        let applied_mode = self.pragma("journal_mode", mode.as_str())?;
+        if applied_mode.eq_ignore_ascii_case(mode.as_str()) {
+            Ok(())
+        } else {
+            Err(DbError::Other(format!("Journal mode {} can't be applied, the database is in {} mode", mode.as_str(), applied_mode)))
+        }
+    }
+
+    /// Set the synchronous flag of the connection.
+    pub fn set_synchronous(&mut self, synchronous: Synchronous) -> Result<(), DbError> {
+        self.pragma("synchronous", synchronous.as_str())?;
+        let applied_value = self.run_pragma_stmt("PRAGMA synchronous;")?;
+        if applied_value == synchronous.as_str() {
+            Ok(())
+        } else {
+            Err(DbError::Other(format!("Synchronous flag {:?} can't be applied", synchronous)))
+        }
+    }
+
+    /// Turn the enforcement of foreign keys on or off. It's on for newly opened
+    /// connections. It can't be changed inside a transaction.
+    pub fn set_foreign_keys(&mut self, enabled: bool) -> Result<(), DbError> {
+        self.pragma("foreign_keys", if enabled { "1" } else { "0" })?;
+        if self.foreign_keys_enabled()? == enabled {
+            Ok(())
+        } else {
+            Err(DbError::Other("Foreign key enforcement can't be changed inside a transaction".to_string()))
+        }
+    }
+
+    /// Whether foreign keys are enforced on this connection.
+    pub fn foreign_keys_enabled(&mut self) -> Result<bool, DbError> {
+        Ok(self.run_pragma_stmt("PRAGMA foreign_keys;")? == "1")
+    }
+
+    /// Run `PRAGMA name = value` and return the first value it reports, or an
+    /// empty string if it reports nothing.
+    ///
+    /// PRAGMA values can't be bound as parameters, so `value` is put into the
+    /// statement as is. Never pass untrusted input as the value.
+    pub fn pragma(&mut self, name: &str, value: &str) -> Result<String, DbError> {
+        let is_valid_name = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if !is_valid_name {
+            return Err(DbError::Other(format!("Invalid pragma name: {}", name)));
+        }
+        self.run_pragma_stmt(&format!("PRAGMA {} = {};", name, value))
+    }
+
+    pub(crate) fn run_pragma_stmt(&mut self, pragma_stmt: &str) -> Result<String, DbError> {
+        let stmt = self.prepare_stmt(pragma_stmt)?;
+        unsafe {
+            let return_value = libsqlite3_sys::sqlite3_step(stmt.as_ptr());
+            db_try!(self.db_handler, stmt.as_ptr(), return_value);
+            let result = if return_value == libsqlite3_sys::SQLITE_ROW {
+                let value_ptr = libsqlite3_sys::sqlite3_column_text(stmt.as_ptr(), 0);
+                if value_ptr.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(value_ptr as *const _).to_string_lossy().into_owned()
+                }
+            } else {
+                String::new()
+            };
+            Ok(result)
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Check that the tables in the database match their schemas, and migrate
+//! the tables that don't.
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use yoshino_core::Schema;
+use yoshino_core::db::{DbAdaptor, DbData, DbDataType, DbError};
+use crate::{quote_identifier, SQLiteAdaptor};
+
+/// Why a table doesn't match its schema, returned by `SQLiteAdaptor::verify_schema`.
+#[derive(Debug)]
+pub enum SchemaMismatch {
+    /// There is no table for the schema.
+    MissingTable(String),
+    /// The columns of the table are different from the fields of the schema.
+    Columns {
+        table: String,
+        /// fields of the schema without a column in the table
+        missing_columns: Vec<String>,
+        /// columns in the table without a field in the schema
+        extra_columns: Vec<String>,
+        /// columns with a different type affinity than their fields
+        type_mismatches: Vec<ColumnTypeMismatch>
+    },
+    /// The table information couldn't be read.
+    Db(DbError)
+}
+
+/// A column with a different type affinity than its field.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ColumnTypeMismatch {
+    pub column: String,
+    /// the affinity of the field type
+    pub expected: String,
+    /// the affinity of the declared column type
+    pub actual: String
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaMismatch::MissingTable(table) => write!(f, "Table {} doesn't exist", table),
+            SchemaMismatch::Columns { table, missing_columns, extra_columns, type_mismatches } => {
+                write!(f, "Table {} doesn't match its schema:", table)?;
+                if !missing_columns.is_empty() {
+                    write!(f, " missing columns {}", missing_columns.join(", "))?;
+                }
+                if !extra_columns.is_empty() {
+                    write!(f, " extra columns {}", extra_columns.join(", "))?;
+                }
+                for mismatch in type_mismatches {
+                    write!(f, " column {} is {} instead of {}", mismatch.column, mismatch.actual, mismatch.expected)?;
+                }
+                Ok(())
+            }
+            SchemaMismatch::Db(e) => e.fmt(f)
+        }
+    }
+}
+
+impl Error for SchemaMismatch {}
+
+impl From<DbError> for SchemaMismatch {
+    fn from(e: DbError) -> SchemaMismatch {
+        SchemaMismatch::Db(e)
+    }
+}
+
+/// The changes made by `SQLiteAdaptor::migrate_schema`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SchemaMigration {
+    /// columns added for fields of the schema
+    pub added_columns: Vec<String>,
+    /// columns in the table without a field in the schema, which are only
+    /// dropped by `migrate_schema_with_rebuild`
+    pub removed_columns: Vec<String>
+}
+
+/// The type affinity SQLite gives a declared column type.
+fn get_type_affinity(declared_type: &str) -> &'static str {
+    let declared_type = declared_type.to_uppercase();
+    if declared_type.contains("INT") {
+        "INTEGER"
+    } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| declared_type.contains(t)) {
+        "TEXT"
+    } else if declared_type.is_empty() || declared_type.contains("BLOB") {
+        "BLOB"
+    } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| declared_type.contains(t)) {
+        "REAL"
+    } else {
+        "NUMERIC"
+    }
+}
+
+pub(crate) fn is_nullable(field_type: &DbDataType) -> bool {
+    matches!(field_type, DbDataType::NullableInt | DbDataType::NullableText | DbDataType::NullableFloat | DbDataType::NullableBlob)
+}
+
+/// The type affinity of the column created for a field type.
+pub(crate) fn get_field_affinity(field_type: &DbDataType) -> &'static str {
+    match field_type {
+        DbDataType::Int | DbDataType::NullableInt | DbDataType::RowID => "INTEGER",
+        DbDataType::Text | DbDataType::NullableText => "TEXT",
+        DbDataType::Float | DbDataType::NullableFloat => "REAL",
+        DbDataType::Blob | DbDataType::NullableBlob => "BLOB"
+    }
+}
+
+impl SQLiteAdaptor {
+    /// Whether the table of the schema exists.
+    pub fn table_exists<T: Schema>(&mut self) -> Result<bool, DbError> {
+        let params: [Box<dyn DbData>; 1] = [Box::new(T::get_schema_name())];
+        self.execute_cached(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?;",
+            &params,
+            |stmt| unsafe { libsqlite3_sys::sqlite3_data_count(stmt) > 0 }
+        )
+    }
+
+    /// Check that the table of the schema exists and has a column of the same
+    /// type affinity for each field and no other columns.
+    ///
+    /// `create_table_for_schema` keeps an existing table as it is, so this is
+    /// useful to find tables created for an older version of the schema.
+    pub fn verify_schema<T: Schema>(&mut self) -> Result<(), SchemaMismatch> {
+        let table = T::get_schema_name();
+        let columns = self.get_table_columns(&table)?;
+        if columns.is_empty() {
+            return Err(SchemaMismatch::MissingTable(table));
+        }
+        let fields = T::get_fields();
+        let mut missing_columns = Vec::new();
+        let mut type_mismatches = Vec::new();
+        for (field_name, field_type) in &fields {
+            match columns.iter().find(|(column, _)| column == field_name) {
+                Some((_, declared_type)) => {
+                    let expected = get_field_affinity(field_type);
+                    let actual = get_type_affinity(declared_type);
+                    if expected != actual {
+                        type_mismatches.push(ColumnTypeMismatch {
+                            column: field_name.to_owned(),
+                            expected: expected.to_owned(),
+                            actual: actual.to_owned()
+                        });
+                    }
+                }
+                None => missing_columns.push(field_name.to_owned())
+            }
+        }
+        let extra_columns: Vec<String> = columns.into_iter()
+            .map(|(column, _)| column)
+            .filter(|column| !fields.iter().any(|(field_name, _)| field_name == column))
+            .collect();
+        if missing_columns.is_empty() && extra_columns.is_empty() && type_mismatches.is_empty() {
+            return Ok(());
+        }
+        Err(SchemaMismatch::Columns { table, missing_columns, extra_columns, type_mismatches })
+    }
+
+    /// Add a column for each field of the schema that the table doesn't have
+    /// yet, creating the table if it doesn't exist.
+    ///
+    /// SQLite can only add nullable columns to an existing table, so it's an
+    /// error if a missing field is not nullable, and no column is added then.
+    /// Columns without a field are reported in `removed_columns` but kept in
+    /// the table. Use `migrate_schema_with_rebuild` to drop them.
+    #[doc(alias = "alter_table")]
+    pub fn migrate_schema<T: Schema>(&mut self) -> Result<SchemaMigration, DbError> {
+        self.migrate_table::<T>(false)
+    }
+
+    /// Like `migrate_schema`, but also drop the columns without a field by
+    /// creating a new table for the schema, copying the rows into it and
+    /// replacing the old table, all in one transaction.
+    pub fn migrate_schema_with_rebuild<T: Schema>(&mut self) -> Result<SchemaMigration, DbError> {
+        self.migrate_table::<T>(true)
+    }
+
+    fn migrate_table<T: Schema>(&mut self, rebuild: bool) -> Result<SchemaMigration, DbError> {
+        let table = T::get_schema_name();
+        let fields = T::get_fields();
+        let columns = self.get_table_columns(&table)?;
+        if columns.is_empty() {
+            self.create_table_for_schema::<T>()?;
+            return Ok(SchemaMigration {
+                added_columns: fields.into_iter().map(|(field_name, _)| field_name).collect(),
+                removed_columns: Vec::new()
+            });
+        }
+        let missing_fields: Vec<&(String, DbDataType)> = fields.iter()
+            .filter(|(field_name, _)| !columns.iter().any(|(column, _)| column == field_name))
+            .collect();
+        let removed_columns: Vec<String> = columns.into_iter()
+            .map(|(column, _)| column)
+            .filter(|column| !fields.iter().any(|(field_name, _)| field_name == column))
+            .collect();
+        for (field_name, field_type) in &missing_fields {
+            if !is_nullable(field_type) {
+                return Err(DbError::Other(format!(
+                    "Column {} of table {} can't be added because it's not nullable", field_name, table)));
+            }
+        }
+        let added_columns = missing_fields.iter().map(|(field_name, _)| field_name.to_owned()).collect();
+        if rebuild && !removed_columns.is_empty() {
+            let kept_columns: Vec<String> = fields.iter()
+                .map(|(field_name, _)| field_name)
+                .filter(|field_name| !missing_fields.iter().any(|(missing_field, _)| missing_field == *field_name))
+                .map(|field_name| quote_identifier(field_name))
+                .collect();
+            // dropping the old table would delete the records referring to it
+            // if foreign keys were enforced during the rebuild
+            let foreign_keys_enabled = self.foreign_keys_enabled()?;
+            if foreign_keys_enabled {
+                self.set_foreign_keys(false)?;
+            }
+            let result = self.rebuild_table::<T>(&table, &kept_columns);
+            if foreign_keys_enabled {
+                self.set_foreign_keys(true)?;
+            }
+            result?;
+        } else {
+            let collations = T::get_collations();
+            for (field_name, field_type) in &missing_fields {
+                let collation_code = collations.iter()
+                    .find(|(column, _)| column == field_name)
+                    .map_or("", |(_, collation)| SQLiteAdaptor::get_collation_code(collation));
+                self.execute_sql(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}{};",
+                    quote_identifier(&table),
+                    quote_identifier(field_name),
+                    SQLiteAdaptor::get_column_type_code(field_type),
+                    collation_code))?;
+            }
+        }
+        Ok(SchemaMigration { added_columns, removed_columns })
+    }
+
+    /// Replace the table with a new table for the schema, copying `kept_columns` of all rows.
+    fn rebuild_table<T: Schema>(&mut self, table: &str, kept_columns: &[String]) -> Result<(), DbError> {
+        let new_table = format!("{}_yoshino_migration", table);
+        let transaction = self.begin_transaction()?;
+        transaction.adaptor.execute_sql(&SQLiteAdaptor::get_create_table_stmt_code(
+            &quote_identifier(&new_table), &T::get_fields(), &T::get_unique_constraints(), &T::get_foreign_keys(), &T::get_collations(), T::get_primary_key().as_deref()))?;
+        transaction.adaptor.execute_sql(&format!(
+            "INSERT INTO {new} ({columns}) SELECT {columns} FROM {old}; DROP TABLE {old}; ALTER TABLE {new} RENAME TO {old};",
+            new = quote_identifier(&new_table),
+            old = quote_identifier(table),
+            columns = kept_columns.join(", ")))?;
+        transaction.commit()
+    }
+
+    /// Names and declared types of the columns of the table, which are empty
+    /// if the table doesn't exist.
+    fn get_table_columns(&mut self, table: &str) -> Result<Vec<(String, String)>, DbError> {
+        let stmt = self.prepare_stmt(&format!("PRAGMA table_info({});", quote_identifier(table)))?;
+        let mut columns = Vec::new();
+        unsafe {
+            loop {
+                let return_value = libsqlite3_sys::sqlite3_step(stmt.as_ptr());
+                db_try!(self.db_handler, stmt.as_ptr(), return_value);
+                if return_value != libsqlite3_sys::SQLITE_ROW {
+                    break;
+                }
+                // the columns of table_info are cid, name, type, notnull, dflt_value and pk
+                columns.push((read_text(stmt.as_ptr(), 1), read_text(stmt.as_ptr(), 2)));
+            }
+        }
+        Ok(columns)
+    }
+}
+
+unsafe fn read_text(stmt: *mut libsqlite3_sys::sqlite3_stmt, i: i32) -> String {
+    let value_ptr = libsqlite3_sys::sqlite3_column_text(stmt, i);
+    if value_ptr.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(value_ptr as *const _).to_string_lossy().into_owned()
+    }
+}
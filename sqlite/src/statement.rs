@@ -0,0 +1,38 @@
+//! Prepared statements that are finalized when they're dropped.
+use libsqlite3_sys::sqlite3_stmt;
+
+/// A prepared statement owned by the adaptor. It's finalized when it's
+/// dropped, so an early return on an error can't leak it and keep the
+/// connection from closing.
+///
+/// The statement is null if the SQL it was prepared from has no statement,
+/// e.g. only a comment.
+pub(crate) struct Statement {
+    stmt: *mut sqlite3_stmt
+}
+
+impl Statement {
+    /// Take ownership of a statement returned by `sqlite3_prepare_v2`.
+    pub(crate) fn new(stmt: *mut sqlite3_stmt) -> Statement {
+        Statement { stmt }
+    }
+
+    /// The statement to pass to SQLite functions. It stays owned by `self`.
+    pub(crate) fn as_ptr(&self) -> *mut sqlite3_stmt {
+        self.stmt
+    }
+
+    pub(crate) fn is_null(&self) -> bool {
+        self.stmt.is_null()
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        // finalizing a null statement does nothing, and the error of the last
+        // step has been reported by whoever stepped it
+        unsafe {
+            libsqlite3_sys::sqlite3_finalize(self.stmt);
+        }
+    }
+}
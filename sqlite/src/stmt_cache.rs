@@ -0,0 +1,75 @@
+//! Cache of prepared statements, keyed by their SQL.
+use std::collections::HashMap;
+use libsqlite3_sys::sqlite3_stmt;
+use crate::statement::Statement;
+
+/// Number of statements kept by a new connection.
+pub(crate) const DEFAULT_STMT_CACHE_CAPACITY: usize = 32;
+
+/// Prepared statements by their SQL. When the cache is full, the least
+/// recently used statement is finalized to make room for a new one.
+///
+/// The statements are owned by the cache, which must be cleared before the
+/// connection is closed. A statement is finalized when it leaves the cache.
+pub(crate) struct StmtCache {
+    /// statements with the tick they were last used at
+    stmts: HashMap<String, (Statement, u64)>,
+    capacity: usize,
+    tick: u64
+}
+
+impl StmtCache {
+    pub(crate) fn new(capacity: usize) -> StmtCache {
+        StmtCache { stmts: HashMap::new(), capacity, tick: 0 }
+    }
+
+    /// Get the statement for the SQL, marking it as the most recently used.
+    pub(crate) fn get(&mut self, sql: &str) -> Option<*mut sqlite3_stmt> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.stmts.get_mut(sql).map(|(stmt, last_used)| {
+            *last_used = tick;
+            stmt.as_ptr()
+        })
+    }
+
+    /// Keep the statement for the SQL, finalizing the least recently used
+    /// statements if the cache is full. Return the statement back if the cache
+    /// can't keep any statement.
+    pub(crate) fn insert(&mut self, sql: &str, stmt: Statement) -> Option<Statement> {
+        if self.capacity == 0 {
+            return Some(stmt);
+        }
+        self.evict_to(self.capacity - 1);
+        self.tick += 1;
+        self.stmts.insert(sql.to_string(), (stmt, self.tick));
+        None
+    }
+
+    /// Whether the statement is owned by the cache.
+    #[cfg(test)]
+    pub(crate) fn contains_stmt(&self, stmt: *mut sqlite3_stmt) -> bool {
+        self.stmts.values().any(|(cached, _)| cached.as_ptr() == stmt)
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict_to(capacity);
+    }
+
+    /// Finalize all statements.
+    pub(crate) fn clear(&mut self) {
+        self.evict_to(0);
+    }
+
+    /// Finalize the least recently used statements until at most `len` are left.
+    fn evict_to(&mut self, len: usize) {
+        while self.stmts.len() > len {
+            let oldest_sql = self.stmts.iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(sql, _)| sql.to_owned())
+                .unwrap();
+            self.stmts.remove(&oldest_sql);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use yoshino_core::db::DbDataType;
 
-use crate::SQLiteAdaptor;
+use crate::{quote_identifier, SQLiteAdaptor};
 
 fn get_test_fields() -> Vec<(String, DbDataType)> {
     vec![
@@ -12,28 +12,131 @@ fn get_test_fields() -> Vec<(String, DbDataType)> {
 }
 const TEST_TABLE_NAME: &str = "test_table_name";
 
+/// A path in the temporary directory, unique to the test process.
+pub(crate) fn temp_path(name: &str) -> String {
+    let mut path = std::env::temp_dir();
+    path.push(format!("yoshino_{}_{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
 #[test]
 fn test_create_table_stmt_creation() {
-    let stmt = SQLiteAdaptor::get_create_table_stmt_code(TEST_TABLE_NAME, &get_test_fields());
-    assert_eq!(stmt, "CREATE TABLE IF NOT EXISTS test_table_name (row_id INTEGER PRIMARY KEY, name TEXT NOT NULL, desc TEXT, counter INTEGER NOT NULL);");
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &get_test_fields(), &[], &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("row_id" INTEGER PRIMARY KEY, "name" TEXT NOT NULL, "desc" TEXT, "counter" INTEGER NOT NULL);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_float_fields() {
+    let fields = vec![
+        ("price".to_string(), DbDataType::Float),
+        ("discount".to_string(), DbDataType::NullableFloat)
+    ];
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &fields, &[], &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("price" REAL NOT NULL, "discount" REAL);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_blob_fields() {
+    let fields = vec![
+        ("thumbnail".to_string(), DbDataType::Blob),
+        ("original".to_string(), DbDataType::NullableBlob)
+    ];
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &fields, &[], &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("thumbnail" BLOB NOT NULL, "original" BLOB);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_unique_constraints() {
+    let unique_constraints = vec![
+        vec!["name".to_string()],
+        vec!["desc".to_string(), "counter".to_string()]
+    ];
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &get_test_fields(), &unique_constraints, &[], &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("row_id" INTEGER PRIMARY KEY, "name" TEXT NOT NULL UNIQUE, "desc" TEXT, "counter" INTEGER NOT NULL, UNIQUE("desc", "counter"));"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_foreign_keys() {
+    use yoshino_core::{ForeignKey, OnDelete};
+    let foreign_keys = vec![ForeignKey {
+        column: "counter".to_string(),
+        referred_schema: "counters".to_string(),
+        referred_column: "id".to_string(),
+        on_delete: OnDelete::Cascade
+    }];
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &get_test_fields(), &[], &foreign_keys, &[], None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("row_id" INTEGER PRIMARY KEY, "name" TEXT NOT NULL, "desc" TEXT, "counter" INTEGER NOT NULL REFERENCES "counters"("id") ON DELETE CASCADE);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_collations() {
+    use yoshino_core::Collation;
+    let collations = vec![("name".to_string(), Collation::NoCase), ("desc".to_string(), Collation::Binary)];
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &get_test_fields(), &[vec!["name".to_string()]], &[], &collations, None);
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("row_id" INTEGER PRIMARY KEY, "name" TEXT NOT NULL COLLATE NOCASE UNIQUE, "desc" TEXT, "counter" INTEGER NOT NULL);"#);
+}
+
+#[test]
+fn test_create_table_stmt_with_primary_key() {
+    use yoshino_core::Collation;
+    let fields = vec![
+        ("id".to_string(), DbDataType::Text),
+        ("name".to_string(), DbDataType::Text)
+    ];
+    let collations = vec![("id".to_string(), Collation::NoCase)];
+    let stmt = SQLiteAdaptor::get_create_table_stmt_code(&quote_identifier(TEST_TABLE_NAME), &fields, &[], &[], &collations, Some("id"));
+    assert_eq!(stmt, r#"CREATE TABLE IF NOT EXISTS "test_table_name" ("id" TEXT NOT NULL PRIMARY KEY COLLATE NOCASE, "name" TEXT NOT NULL);"#);
 }
 
 #[test]
 fn test_insert_value_stmt_creation() {
-    let stmt = SQLiteAdaptor::get_insert_value_stmt_code(TEST_TABLE_NAME, &get_test_fields());
-    assert_eq!(stmt, "INSERT INTO test_table_name (row_id, name, desc, counter) VALUES (?1, ?2, ?3, ?4);")
+    let stmt = SQLiteAdaptor::get_insert_value_stmt_code(&quote_identifier(TEST_TABLE_NAME), &get_test_fields());
+    assert_eq!(stmt, r#"INSERT INTO "test_table_name" ("row_id", "name", "desc", "counter") VALUES (?1, ?2, ?3, ?4);"#)
 }
 
 #[test]
 fn test_query_clause() {
-    let stmt = SQLiteAdaptor::get_query_clause(TEST_TABLE_NAME, &get_test_fields());
-    assert_eq!(stmt, "SELECT row_id, name, desc, counter FROM test_table_name");
+    let stmt = SQLiteAdaptor::get_query_clause(&quote_identifier(TEST_TABLE_NAME), &get_test_fields());
+    assert_eq!(stmt, r#"SELECT "row_id", "name", "desc", "counter" FROM "test_table_name""#);
+}
+
+#[test]
+fn test_order_by_clause() {
+    use yoshino_core::SortDir;
+    let order = vec![("counter".to_string(), SortDir::Desc), ("name".to_string(), SortDir::Asc)];
+    let clause = SQLiteAdaptor::get_order_by_clause(&get_test_fields(), &order).unwrap();
+    assert_eq!(clause, r#" ORDER BY "counter" DESC, "name" ASC"#);
+    assert_eq!(SQLiteAdaptor::get_order_by_clause(&get_test_fields(), &[]).unwrap(), "");
+    let unknown = vec![("counter; DROP TABLE x".to_string(), SortDir::Asc)];
+    assert!(SQLiteAdaptor::get_order_by_clause(&get_test_fields(), &unknown).is_err());
 }
 
 #[test]
 fn test_update_clause() {
     let stmt = SQLiteAdaptor::get_update_clause(TEST_TABLE_NAME, &get_test_fields());
-    assert_eq!(stmt, "UPDATE test_table_name SET row_id = ?, name = ?, desc = ?, counter = ?");
+    assert_eq!(stmt, r#"UPDATE "test_table_name" SET "name" = ?, "desc" = ?, "counter" = ?"#);
+}
+
+#[test]
+fn test_sql_for_schema() {
+    use yoshino_core::{Cond, IntegerField, RowID, TextField};
+    use yoshino_derive::Schema;
+
+    #[derive(Schema)]
+    struct Post {
+        pub id: RowID,
+        #[yoshino(unique)]
+        pub title: String,
+        pub likes: i64
+    }
+
+    assert_eq!(SQLiteAdaptor::sql_for_create_table::<Post>(),
+        r#"CREATE TABLE IF NOT EXISTS "y_post" ("id" INTEGER PRIMARY KEY, "title" TEXT NOT NULL UNIQUE, "likes" INTEGER NOT NULL);"#);
+    assert_eq!(SQLiteAdaptor::sql_for_insert::<Post>(), r#"INSERT INTO "y_post" ("id", "title", "likes") VALUES (?1, ?2, ?3);"#);
+    assert_eq!(SQLiteAdaptor::sql_for_query_all::<Post>(), r#"SELECT "id", "title", "likes" FROM "y_post";"#);
+    let cond = Cond::and(Cond::integer_greater_than("likes", 10), Cond::text_equal_to("title", "hello"));
+    assert_eq!(SQLiteAdaptor::sql_for_query_with_cond::<Post>(&cond),
+        r#"SELECT "id", "title", "likes" FROM "y_post" WHERE ("likes">?1) AND ("title"=?2);"#);
 }
 
 mod cond_parsing_test {
@@ -44,7 +147,7 @@ mod cond_parsing_test {
     fn test_int_eq_cond() {
         let cond = Cond::integer_equal_to("value", 0xff);
         let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        assert_eq!(clause, "value=?");
+        assert_eq!(clause, r#""value"=?1"#);
         assert_eq!(i64::from_boxed_db_data(&params[0]), 0xff);
     }
 
@@ -52,10 +155,69 @@ mod cond_parsing_test {
     fn test_int_not_eq_cond() {
         let cond = Cond::integer_not_equal_to("value", 0xff);
         let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        assert_eq!(clause, "value<>?");
+        assert_eq!(clause, r#""value"<>?1"#);
         assert_eq!(i64::from_boxed_db_data(&params[0]), 0xff);
     }
 
+    #[test]
+    fn test_int_comparison_conds() {
+        let conds = [
+            (Cond::integer_less_than("value", 10), r#""value"<?1"#),
+            (Cond::integer_less_than_or_equal_to("value", 10), r#""value"<=?1"#),
+            (Cond::integer_greater_than("value", 10), r#""value">?1"#),
+            (Cond::integer_greater_than_or_equal_to("value", 10), r#""value">=?1"#),
+        ];
+        for (cond, expected_clause) in conds {
+            let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+            assert_eq!(clause, expected_clause);
+            assert_eq!(i64::from_boxed_db_data(&params[0]), 10);
+        }
+    }
+
+    #[test]
+    fn test_int_between_cond() {
+        let cond = Cond::or(Cond::integer_between("value", 10, 20), Cond::not(Cond::integer_between("other", -1, 1)));
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::and(Cond::integer_equal_to("id", 3), cond));
+        assert_eq!(clause, r#"("id"=?1) AND (("value" BETWEEN ?2 AND ?3) OR (NOT ("other" BETWEEN ?4 AND ?5)))"#);
+        assert_eq!(params.iter().map(i64::from_boxed_db_data).collect::<Vec<_>>(), vec![3, 10, 20, -1, 1]);
+    }
+
+    #[test]
+    fn test_text_like_conds() {
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::text_like("value", "a_c%"));
+        assert_eq!(clause, r#""value" LIKE ?1 ESCAPE '\'"#);
+        assert_eq!(String::from_boxed_db_data(&params[0]), "a_c%");
+        let (_, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::text_starts_with("value", "50%_off\\"));
+        assert_eq!(String::from_boxed_db_data(&params[0]), "50\\%\\_off\\\\%");
+        let (_, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::text_contains("value", "abc"));
+        assert_eq!(String::from_boxed_db_data(&params[0]), "%abc%");
+    }
+
+    #[test]
+    fn test_text_comparison_conds() {
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::text_not_equal_to("value", "a"));
+        assert_eq!(clause, r#""value"<>?1"#);
+        assert_eq!(String::from_boxed_db_data(&params[0]), "a");
+        let cond = Cond::and(Cond::text_equal_ci("value", "Admin"), Cond::is_not_null("value"));
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        assert_eq!(clause, r#"("value"=?1 COLLATE NOCASE) AND ("value" IS NOT NULL)"#);
+        assert_eq!(String::from_boxed_db_data(&params[0]), "Admin");
+    }
+
+    #[test]
+    fn test_in_conds() {
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::integer_in("value", vec![1, 5, 9]));
+        assert_eq!(clause, r#""value" IN (?1, ?2, ?3)"#);
+        assert_eq!(params.iter().map(i64::from_boxed_db_data).collect::<Vec<_>>(), vec![1, 5, 9]);
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(
+            Cond::text_in("value", vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(clause, r#""value" IN (?1, ?2)"#);
+        assert_eq!(params.iter().map(String::from_boxed_db_data).collect::<Vec<_>>(), vec!["a", "b"]);
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(Cond::integer_in("value", vec![]));
+        assert_eq!(clause, "0=1");
+        assert!(params.is_empty());
+    }
+
     #[test]
     fn test_and_cond() {
         let cond = Cond::and(
@@ -63,7 +225,7 @@ mod cond_parsing_test {
            Cond::text_equal_to("value2", "str")
         );
         let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        assert_eq!(clause, "(value1=?) AND (value2=?)");
+        assert_eq!(clause, r#"("value1"=?1) AND ("value2"=?2)"#);
         assert_eq!(i64::from_boxed_db_data(&params[0]), 0xf0);
         assert_eq!(String::from_boxed_db_data(&params[1]), "str");
     }
@@ -75,7 +237,7 @@ mod cond_parsing_test {
            Cond::text_equal_to("value2", "str")
         );
         let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        assert_eq!(clause, "(value1=?) OR (value2=?)");
+        assert_eq!(clause, r#"("value1"=?1) OR ("value2"=?2)"#);
         assert_eq!(i64::from_boxed_db_data(&params[0]), 0xf0);
         assert_eq!(String::from_boxed_db_data(&params[1]), "str");
     }
@@ -86,7 +248,2852 @@ mod cond_parsing_test {
            Cond::integer_equal_to("value1", 0xf0),
         );
         let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
-        assert_eq!(clause, "NOT (value1=?)");
+        assert_eq!(clause, r#"NOT ("value1"=?1)"#);
         assert_eq!(i64::from_boxed_db_data(&params[0]), 0xf0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_nested_not_cond() {
+        let cond = Cond::or(
+            Cond::and(
+                Cond::integer_equal_to("value1", 1),
+                Cond::not(Cond::text_equal_to("value2", "str"))
+            ),
+            Cond::not(Cond::integer_equal_to("value3", 3))
+        );
+        let (clause, params) = SQLiteAdaptor::get_condition_stmt_and_params(cond);
+        assert_eq!(clause, r#"(("value1"=?1) AND (NOT ("value2"=?2))) OR (NOT ("value3"=?3))"#);
+        assert_eq!(params.len(), 3);
+        assert_eq!(i64::from_boxed_db_data(&params[0]), 1);
+        assert_eq!(String::from_boxed_db_data(&params[1]), "str");
+        assert_eq!(i64::from_boxed_db_data(&params[2]), 3);
+    }
+}
+mod db_error_test {
+    use std::ffi::CString;
+    use std::ptr;
+    use yoshino_core::db::{ConstraintKind, DbAdaptor, DbError, ViolatedConstraint};
+    use yoshino_core::{IntegerField, RowID, TextField};
+    use yoshino_derive::Schema;
+    use crate::SQLiteAdaptor;
+
+    /// Whether a statement leaked or a cached statement wasn't reset.
+    fn has_pending_statements(adaptor: &SQLiteAdaptor) -> bool {
+        unsafe {
+            let mut stmt = libsqlite3_sys::sqlite3_next_stmt(adaptor.db_handler, ptr::null_mut());
+            while !stmt.is_null() {
+                let is_cached = adaptor.stmt_cache.contains_stmt(stmt);
+                if !is_cached || libsqlite3_sys::sqlite3_stmt_busy(stmt) != 0 {
+                    return true;
+                }
+                stmt = libsqlite3_sys::sqlite3_next_stmt(adaptor.db_handler, stmt);
+            }
+            false
+        }
+    }
+
+    mod strict {
+        use yoshino_core::TextField;
+        use yoshino_derive::Schema;
+
+        #[derive(Schema)]
+        pub struct Item {
+            pub name: String
+        }
+    }
+
+    mod loose {
+        use yoshino_core::NullableTextField;
+        use yoshino_derive::Schema;
+
+        #[derive(Schema)]
+        pub struct Item {
+            pub name: Option<String>
+        }
+    }
+
+    #[test]
+    fn test_failed_statements_are_finalized() {
+        use yoshino_core::Cond;
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<strict::Item>().unwrap();
+        adaptor.insert_record(strict::Item { name: "short".to_string() }).unwrap();
+        // statements that fail to prepare or have the wrong parameters
+        assert!(adaptor.execute_raw("SELECT * FROM missing_table;", &[]).is_err());
+        assert!(adaptor.execute_raw("SELECT 1; SELECT 2;", &[]).is_err());
+        assert!(adaptor.execute_raw("SELECT ?;", &[]).is_err());
+        assert!(adaptor.query_column::<strict::Item, String>("missing", Cond::is_not_null("name")).is_err());
+        // values longer than the length limit fail to bind
+        let long_name = "x".repeat(100);
+        unsafe {
+            libsqlite3_sys::sqlite3_limit(adaptor.db_handler, libsqlite3_sys::SQLITE_LIMIT_LENGTH, 64);
+        }
+        assert!(adaptor.execute_raw("SELECT ?;", &[&long_name]).is_err());
+        assert!(adaptor.query_with_cond::<strict::Item>(Cond::text_equal_to("name", &long_name)).is_err());
+        assert!(adaptor.query_column::<strict::Item, String>("name", Cond::text_equal_to("name", &long_name)).is_err());
+        assert!(adaptor.query_columns_with_cond::<strict::Item>(&["name"], Cond::text_equal_to("name", &long_name)).is_err());
+        assert!(adaptor.insert_record(strict::Item { name: long_name.clone() }).is_err());
+        assert!(adaptor.delete_with_cond::<strict::Item>(Cond::text_equal_to("name", &long_name)).is_err());
+        // a query left before its last row
+        let mut items = adaptor.query_all::<strict::Item>().unwrap();
+        assert_eq!(items.next().unwrap().name, "short");
+        drop(items);
+        assert!(!has_pending_statements(&adaptor));
+        // without the cached statements, nothing keeps the connection from closing
+        adaptor.set_stmt_cache_capacity(0);
+        unsafe {
+            assert!(libsqlite3_sys::sqlite3_next_stmt(adaptor.db_handler, ptr::null_mut()).is_null());
+        }
+    }
+
+    #[test]
+    fn test_not_null_violation_is_reported() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<strict::Item>().unwrap();
+        match adaptor.insert_record(loose::Item { name: None }) {
+            Err(DbError::ConstraintViolation(failure, constraint)) => {
+                assert_eq!(failure.code, libsqlite3_sys::SQLITE_CONSTRAINT);
+                assert_eq!(failure.extended_code, libsqlite3_sys::SQLITE_CONSTRAINT_NOTNULL);
+                assert!(failure.message.contains("NOT NULL"), "unexpected message: {}", failure.message);
+                assert_eq!(*constraint, ViolatedConstraint {
+                    kind: ConstraintKind::NotNull,
+                    table: Some("y_item".to_string()),
+                    columns: vec!["name".to_string()],
+                    name: None
+                });
+            }
+            _ => panic!("inserting NULL into a NOT NULL column should fail")
+        }
+        assert!(!has_pending_statements(&adaptor));
+    }
+
+    #[derive(Schema)]
+    #[yoshino(unique(first_name, last_name))]
+    struct Member {
+        #[yoshino(unique)]
+        pub user_name: String,
+        pub first_name: String,
+        pub last_name: String
+    }
+
+    fn new_member(user_name: &str, first_name: &str, last_name: &str) -> Member {
+        Member { user_name: user_name.to_string(), first_name: first_name.to_string(), last_name: last_name.to_string() }
+    }
+
+    #[derive(Schema)]
+    struct Owner {
+        pub id: RowID,
+        pub name: String
+    }
+
+    #[derive(Schema)]
+    struct Pet {
+        #[yoshino(references(Owner, id), on_delete = "cascade")]
+        pub owner_id: i64,
+        pub name: String
+    }
+
+    #[derive(Schema)]
+    struct Toy {
+        #[yoshino(foreign_key(Owner, id))]
+        pub owner_id: i64,
+        pub name: String
+    }
+
+    #[test]
+    fn test_foreign_key_violation_is_reported() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Owner>().unwrap();
+        adaptor.create_table_for_schema::<Pet>().unwrap();
+        let owner_id = adaptor.insert_record(Owner { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id, name: "tama".to_string() }).unwrap();
+        match adaptor.insert_record(Pet { owner_id: owner_id + 1, name: "pochi".to_string() }) {
+            Err(DbError::ConstraintViolation(failure, constraint)) => {
+                assert_eq!(failure.code, libsqlite3_sys::SQLITE_CONSTRAINT);
+                assert!(failure.message.contains("FOREIGN KEY"), "unexpected message: {}", failure.message);
+                assert_eq!(constraint.kind, ConstraintKind::ForeignKey);
+            }
+            _ => panic!("inserting a dangling reference should fail")
+        }
+        assert!(!has_pending_statements(&adaptor));
+    }
+
+    #[test]
+    fn test_foreign_key_attribute() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Owner>().unwrap();
+        adaptor.create_table_for_schema::<Toy>().unwrap();
+        let owner_id = adaptor.insert_record(Owner { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        adaptor.insert_record(Toy { owner_id, name: "ball".to_string() }).unwrap();
+        assert!(adaptor.insert_record(Toy { owner_id: owner_id + 1, name: "rope".to_string() }).is_err());
+        // without on_delete, the referred record can't be deleted while it's referred to
+        assert!(adaptor.delete_record(&Owner { id: RowID::ID(owner_id), name: "alice".to_string() }).is_err());
+    }
+
+    #[test]
+    fn test_foreign_key_cascade_delete() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Owner>().unwrap();
+        adaptor.create_table_for_schema::<Pet>().unwrap();
+        let alice = adaptor.insert_record(Owner { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let bob = adaptor.insert_record(Owner { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id: alice, name: "tama".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id: alice, name: "mike".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id: bob, name: "pochi".to_string() }).unwrap();
+        assert!(adaptor.delete_record(&Owner { id: RowID::ID(alice), name: "alice".to_string() }).unwrap());
+        let pets: Vec<Pet> = adaptor.query_all::<Pet>().unwrap().collect();
+        assert_eq!(pets.len(), 1);
+        assert_eq!(pets[0].name, "pochi");
+    }
+
+    #[test]
+    fn test_query_children() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Owner>().unwrap();
+        adaptor.create_table_for_schema::<Pet>().unwrap();
+        let alice = adaptor.insert_record(Owner { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let bob = adaptor.insert_record(Owner { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id: alice, name: "tama".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id: bob, name: "pochi".to_string() }).unwrap();
+        adaptor.insert_record(Pet { owner_id: alice, name: "mike".to_string() }).unwrap();
+
+        let alice = Owner { id: RowID::ID(alice), name: "alice".to_string() };
+        let pets: Vec<String> = adaptor.query_children::<Owner, Pet>(&alice, "owner_id").unwrap().map(|pet| pet.name).collect();
+        assert_eq!(pets, vec!["tama", "mike"]);
+        let carol = Owner { id: RowID::ID(bob + 1), name: "carol".to_string() };
+        assert_eq!(adaptor.query_children::<Owner, Pet>(&carol, "owner_id").unwrap().count(), 0);
+
+        assert!(adaptor.query_children::<Owner, Pet>(&Owner { id: RowID::NEW, name: "dave".to_string() }, "owner_id").is_err());
+        assert!(adaptor.query_children::<Owner, Pet>(&alice, "owner").is_err());
+    }
+
+    #[test]
+    fn test_unique_violation_is_reported() {
+        use yoshino_core::Schema;
+        assert_eq!(Member::get_unique_constraints(), vec![
+            vec!["first_name".to_string(), "last_name".to_string()],
+            vec!["user_name".to_string()]
+        ]);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Member>().unwrap();
+        adaptor.insert_record(new_member("alice", "Alice", "Smith")).unwrap();
+        adaptor.insert_record(new_member("bob", "Alice", "Jones")).unwrap();
+        let duplicates = [
+            (new_member("alice", "Alice", "Brown"), vec!["user_name"]),
+            (new_member("carol", "Alice", "Smith"), vec!["first_name", "last_name"])
+        ];
+        for (member, columns) in duplicates {
+            match adaptor.insert_record(member) {
+                Err(DbError::ConstraintViolation(failure, constraint)) => {
+                    assert_eq!(failure.code, libsqlite3_sys::SQLITE_CONSTRAINT);
+                    assert!(failure.message.contains("UNIQUE"), "unexpected message: {}", failure.message);
+                    assert_eq!(constraint.kind, ConstraintKind::Unique);
+                    assert_eq!(constraint.table.as_deref(), Some("y_member"));
+                    assert_eq!(constraint.columns, columns);
+                }
+                _ => panic!("inserting a duplicate value into a unique column should fail")
+            }
+        }
+        assert_eq!(adaptor.count_all::<Member>().unwrap(), 2);
+        assert!(!has_pending_statements(&adaptor));
+    }
+
+    #[test]
+    fn test_error_has_failed_sql() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Member>().unwrap();
+        adaptor.insert_record(new_member("alice", "Alice", "Smith")).unwrap();
+        let error = adaptor.insert_record(new_member("alice", "Alice", "Brown")).unwrap_err();
+        assert!(matches!(error, DbError::ConstraintViolation { .. }), "{:?}", error);
+        let failure = error.failure().unwrap();
+        assert_eq!(failure.sql.as_deref(), Some(r#"INSERT INTO "y_member" ("user_name", "first_name", "last_name") VALUES (?1, ?2, ?3);"#));
+        assert_eq!(error.to_string(), format!("Constraint violation: Database error 19 (extended 2067): {} (SQL: {})",
+            failure.message, failure.sql.as_ref().unwrap()));
+
+        // a statement that can't be prepared is reported with its SQL too
+        let error = adaptor.execute_raw("SELECT * FROM missing;", &[]).unwrap_err();
+        assert_eq!(error.failure().unwrap().sql.as_deref(), Some("SELECT * FROM missing;"));
+    }
+
+    #[test]
+    fn test_query_dropped_table_is_reported() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<strict::Item>().unwrap();
+        let drop_stmt = CString::new("DROP TABLE y_item;").unwrap();
+        unsafe {
+            libsqlite3_sys::sqlite3_exec(adaptor.db_handler, drop_stmt.as_ptr(), None, ptr::null_mut(), ptr::null_mut());
+        }
+        match adaptor.query_all::<strict::Item>() {
+            Err(DbError::DatabaseFailure(failure)) => {
+                assert_eq!(failure.code, libsqlite3_sys::SQLITE_ERROR);
+                assert!(failure.message.contains("no such table"), "unexpected message: {}", failure.message);
+            }
+            _ => panic!("querying a dropped table should fail")
+        }
+        assert!(adaptor.insert_record(strict::Item { name: "name".to_string() }).is_err());
+        assert!(!has_pending_statements(&adaptor));
+    }
+
+    #[test]
+    fn test_query_missing_table_is_reported() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        match adaptor.query_all::<strict::Item>() {
+            Err(DbError::DatabaseFailure(failure)) => {
+                assert_eq!(failure.code, libsqlite3_sys::SQLITE_ERROR);
+                assert!(failure.message.contains("no such table"), "unexpected message: {}", failure.message);
+            }
+            _ => panic!("querying a missing table should fail")
+        }
+    }
+}
+
+mod open_test {
+    use std::fs;
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::TextField;
+    use yoshino_derive::Schema;
+    use crate::{OpenFlags, SQLiteAdaptor};
+    use super::temp_path;
+
+    #[derive(Schema)]
+    struct Note {
+        pub text: String
+    }
+
+    #[test]
+    fn test_adaptor_can_move_to_another_thread() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.insert_record(Note { text: "main".to_string() }).unwrap();
+        let notes = adaptor.query_all::<Note>().unwrap();
+        let mut adaptor = std::thread::spawn(move || {
+            adaptor.insert_record(Note { text: "worker".to_string() }).unwrap();
+            assert_eq!(adaptor.count_all::<Note>().unwrap(), 2);
+            adaptor
+        }).join().unwrap();
+        let texts: Vec<String> = notes.map(|note| note.text).collect();
+        assert_eq!(texts, vec!["main", "worker"]);
+        assert_eq!(adaptor.query_all::<Note>().unwrap().count(), 2);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_open_without_mutex_fails() {
+        let result = SQLiteAdaptor::open_with_flags(":memory:", OpenFlags::default() | OpenFlags::NOMUTEX);
+        assert!(matches!(result, Err(DbError::Other(_))));
+    }
+
+    #[test]
+    fn test_open_directory_fails() {
+        let path = temp_path("open_directory");
+        fs::create_dir_all(&path).unwrap();
+        let result = SQLiteAdaptor::open(&path);
+        fs::remove_dir(&path).unwrap();
+        match result {
+            Err(DbError::DatabaseFailure(failure)) => assert_eq!(failure.code, libsqlite3_sys::SQLITE_CANTOPEN),
+            _ => panic!("opening a directory should fail")
+        }
+    }
+
+    #[test]
+    fn test_open_invalid_database_fails() {
+        let path = temp_path("not_a_database");
+        fs::write(&path, vec![b'x'; 1024]).unwrap();
+        let result = SQLiteAdaptor::open(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(DbError::NotADatabase(failure)) => {
+                assert_eq!(failure.code, libsqlite3_sys::SQLITE_NOTADB);
+                assert!(failure.message.contains("not a database"), "unexpected message: {}", failure.message);
+            }
+            _ => panic!("opening a file that isn't a database should fail")
+        }
+    }
+
+    #[test]
+    fn test_open_in_memory_is_private() {
+        let mut first = SQLiteAdaptor::open_in_memory().unwrap();
+        let mut second = SQLiteAdaptor::open_in_memory().unwrap();
+        first.create_table_for_schema::<Note>().unwrap();
+        first.insert_record(Note { text: "hello".to_string() }).unwrap();
+        assert_eq!(first.query_all::<Note>().unwrap().count(), 1);
+        assert!(second.query_all::<Note>().is_err());
+    }
+
+    #[test]
+    fn test_open_in_memory_named_is_shared() {
+        let mut writer = SQLiteAdaptor::open_in_memory_named("open_test_shared").unwrap();
+        let mut reader = SQLiteAdaptor::open_in_memory_named("open_test_shared").unwrap();
+        let mut other = SQLiteAdaptor::open_in_memory_named("open_test_other").unwrap();
+        writer.create_table_for_schema::<Note>().unwrap();
+        writer.insert_record(Note { text: "hello".to_string() }).unwrap();
+        let notes: Vec<Note> = reader.query_all::<Note>().unwrap().collect();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].text, "hello");
+        assert!(other.query_all::<Note>().is_err());
+    }
+
+    #[test]
+    fn test_open_filename_with_nul_fails() {
+        match SQLiteAdaptor::open("bad\0name.db") {
+            Err(DbError::Other(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("opening a filename with a NUL byte should fail")
+        }
+    }
+
+    #[test]
+    fn test_open_with_missing_vfs_fails() {
+        match SQLiteAdaptor::open_with_vfs(":memory:", OpenFlags::default(), Some("yoshino_missing_vfs")) {
+            Err(DbError::DatabaseFailure(failure)) => {
+                assert!(failure.message.contains("no such vfs"), "unexpected message: {}", failure.message);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("opening with a missing VFS should fail")
+        }
+    }
+
+    #[test]
+    fn test_open_with_default_vfs() {
+        let mut adaptor = SQLiteAdaptor::open_with_vfs(":memory:", OpenFlags::default(), None).unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.insert_record(Note { text: "hello".to_string() }).unwrap();
+        assert_eq!(adaptor.query_all::<Note>().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_open_new_file() {
+        let path = temp_path("new_database");
+        let result = SQLiteAdaptor::open(&path);
+        assert!(result.is_ok());
+        drop(result);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_missing_file_without_create_fails() {
+        let path = temp_path("missing_database");
+        match SQLiteAdaptor::open_with_flags(&path, OpenFlags::READWRITE) {
+            Err(DbError::DatabaseFailure(failure)) => assert_eq!(failure.code, libsqlite3_sys::SQLITE_CANTOPEN),
+            _ => panic!("opening a missing file without CREATE should fail")
+        }
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_open_read_only_rejects_writes() {
+        let path = temp_path("read_only_database");
+        {
+            let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+            adaptor.create_table_for_schema::<Note>().unwrap();
+        }
+        let mut adaptor = SQLiteAdaptor::open_with_flags(&path, OpenFlags::READONLY).unwrap();
+        let result = adaptor.insert_record(Note { text: "hello".to_string() });
+        drop(adaptor);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(DbError::ReadOnly(failure)) => assert_eq!(failure.code, libsqlite3_sys::SQLITE_READONLY),
+            _ => panic!("writing to a read-only database should fail")
+        }
+    }
+
+    #[test]
+    fn test_busy_timeout() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let path = temp_path("busy_database");
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+
+        let (locked_sender, locked_receiver) = mpsc::channel();
+        let holder_path = path.clone();
+        let holder = thread::spawn(move || {
+            let holder = SQLiteAdaptor::open(&holder_path).unwrap();
+            let begin = std::ffi::CString::new("BEGIN IMMEDIATE;").unwrap();
+            let commit = std::ffi::CString::new("COMMIT;").unwrap();
+            unsafe {
+                libsqlite3_sys::sqlite3_exec(holder.db_handler, begin.as_ptr(), None, std::ptr::null_mut(), std::ptr::null_mut());
+            }
+            locked_sender.send(()).unwrap();
+            thread::sleep(Duration::from_millis(200));
+            unsafe {
+                libsqlite3_sys::sqlite3_exec(holder.db_handler, commit.as_ptr(), None, std::ptr::null_mut(), std::ptr::null_mut());
+            }
+        });
+        locked_receiver.recv().unwrap();
+
+        match adaptor.insert_record(Note { text: "first".to_string() }) {
+            Err(DbError::Busy(_)) => {}
+            _ => panic!("writing to a locked database without a timeout should be busy")
+        }
+        // a second connection opened with a timeout waits for the lock too
+        let mut waiting = SQLiteAdaptor::open_with_busy_timeout(&path, Duration::from_secs(10)).unwrap();
+        waiting.insert_record(Note { text: "second".to_string() }).unwrap();
+        adaptor.set_busy_timeout(Duration::from_secs(10)).unwrap();
+        adaptor.insert_record(Note { text: "third".to_string() }).unwrap();
+
+        holder.join().unwrap();
+        assert_eq!(adaptor.count_all::<Note>().unwrap(), 2);
+        drop(waiting);
+        drop(adaptor);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_busy_handler() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicI32, Ordering};
+        use std::time::Duration;
+
+        let path = temp_path("busy_handler");
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        let holder = SQLiteAdaptor::open(&path).unwrap();
+        holder.execute_sql("BEGIN EXCLUSIVE;").unwrap();
+
+        let calls = Arc::new(AtomicI32::new(0));
+        let handler_calls = calls.clone();
+        adaptor.set_busy_handler(Some(Box::new(move |count| {
+            assert_eq!(count, handler_calls.fetch_add(1, Ordering::SeqCst));
+            count < 3
+        }))).unwrap();
+        assert!(matches!(adaptor.insert_record(Note { text: "first".to_string() }), Err(DbError::Busy(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        // the handler is dropped when it's replaced
+        adaptor.set_busy_handler(None).unwrap();
+        assert_eq!(Arc::strong_count(&calls), 1);
+        assert!(matches!(adaptor.insert_record(Note { text: "second".to_string() }), Err(DbError::Busy(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        let handler_calls = calls.clone();
+        adaptor.set_busy_handler(Some(Box::new(move |_| {
+            handler_calls.fetch_add(1, Ordering::SeqCst);
+            false
+        }))).unwrap();
+        adaptor.set_busy_timeout(Duration::from_millis(10)).unwrap();
+        assert_eq!(Arc::strong_count(&calls), 1);
+        assert!(matches!(adaptor.insert_record(Note { text: "third".to_string() }), Err(DbError::Busy(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        let handler_calls = calls.clone();
+        adaptor.set_busy_handler(Some(Box::new(move |_| {
+            handler_calls.fetch_add(1, Ordering::SeqCst);
+            false
+        }))).unwrap();
+        holder.execute_sql("COMMIT;").unwrap();
+        adaptor.insert_record(Note { text: "fourth".to_string() }).unwrap();
+        drop(adaptor);
+        assert_eq!(Arc::strong_count(&calls), 1);
+        drop(holder);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_query_on_locked_database_keeps_error() {
+        let path = temp_path("locked_query");
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.insert_record(Note { text: "first".to_string() }).unwrap();
+        let holder = SQLiteAdaptor::open(&path).unwrap();
+        holder.execute_sql("BEGIN EXCLUSIVE;").unwrap();
+
+        let mut notes = adaptor.query_all::<Note>().unwrap();
+        assert!(notes.next().is_none());
+        assert!(matches!(notes.error(), Some(DbError::Busy(_))));
+        assert!(notes.next().is_none());
+        drop(notes);
+        assert!(matches!(adaptor.query_all::<Note>().unwrap().collect_all(), Err(DbError::Busy(_))));
+        assert!(matches!(adaptor.query_all::<Note>().unwrap().try_next(), Err(DbError::Busy(_))));
+        let read_all = |adaptor: &mut SQLiteAdaptor| -> Result<Vec<String>, DbError> {
+            let mut texts = Vec::new();
+            for note in adaptor.query_all::<Note>()?.results() {
+                texts.push(note?.text);
+            }
+            Ok(texts)
+        };
+        assert!(matches!(read_all(&mut adaptor), Err(DbError::Busy(_))));
+
+        holder.execute_sql("COMMIT;").unwrap();
+        let mut notes = adaptor.query_all::<Note>().unwrap();
+        assert_eq!(notes.next().unwrap().text, "first");
+        assert!(notes.next().is_none());
+        assert!(notes.error().is_none());
+        drop(notes);
+        let notes = adaptor.query_all::<Note>().unwrap().collect_all().unwrap();
+        assert_eq!(notes.len(), 1);
+        let mut notes = adaptor.query_all::<Note>().unwrap();
+        assert_eq!(notes.try_next().unwrap().unwrap().text, "first");
+        assert!(notes.try_next().unwrap().is_none());
+        drop(notes);
+        assert_eq!(read_all(&mut adaptor).unwrap(), vec!["first"]);
+        drop(holder);
+        drop(adaptor);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_open_flags_combination() {
+        let flags = OpenFlags::READWRITE | OpenFlags::CREATE | OpenFlags::URI;
+        assert!(flags.contains(OpenFlags::default()));
+        assert!(flags.contains(OpenFlags::URI));
+        assert!(!flags.contains(OpenFlags::READONLY));
+    }
+}
+
+mod round_trip_test {
+    use yoshino_core::db::{DbAdaptor, DbData, DbDataType, DbError};
+    use yoshino_core::{Aggregate, Cond, Decimal, FieldUpdates, RowID, Timestamp, Uuid, BlobField, FloatField, IntegerField, NullableBlobField, NullableFloatField, NullableIntegerField, NullableTextField, TextField};
+    use yoshino_derive::{Schema, SchemaEnum};
+    use crate::SQLiteAdaptor;
+
+    #[derive(Schema)]
+    struct Product {
+        pub name: String,
+        pub price: f64,
+        pub discount: Option<f64>
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Product>().unwrap();
+        adaptor.insert_record(Product { name: "milk".to_string(), price: 1.25, discount: Some(0.0) }).unwrap();
+        adaptor.insert_record(Product { name: "cream".to_string(), price: 2.5, discount: None }).unwrap();
+        let products: Vec<Product> = adaptor.query_all::<Product>().unwrap().collect();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].name, "milk");
+        assert_eq!(products[0].price, 1.25);
+        assert_eq!(products[0].discount, Some(0.0));
+        assert_eq!(products[1].name, "cream");
+        assert_eq!(products[1].price, 2.5);
+        assert_eq!(products[1].discount, None);
+    }
+
+    #[test]
+    fn test_special_float_values() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Product>().unwrap();
+        adaptor.insert_record(Product { name: "inf".to_string(), price: f64::INFINITY, discount: Some(f64::NEG_INFINITY) }).unwrap();
+        adaptor.insert_record(Product { name: "zero".to_string(), price: -0.0, discount: Some(0.0) }).unwrap();
+        assert!(adaptor.insert_record(Product { name: "nan".to_string(), price: f64::NAN, discount: None }).is_err());
+        assert!(adaptor.insert_record(Product { name: "nan".to_string(), price: 1.0, discount: Some(f64::NAN) }).is_err());
+        let products: Vec<Product> = adaptor.query_all::<Product>().unwrap().collect();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0].price, f64::INFINITY);
+        assert_eq!(products[0].discount, Some(f64::NEG_INFINITY));
+        assert_eq!(products[1].price, 0.0);
+        assert_eq!(products[1].discount, Some(0.0));
+    }
+
+    #[derive(Schema)]
+    struct Note {
+        pub text: String
+    }
+
+    #[test]
+    fn test_multi_byte_text_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        let text = "héllo wörld, 東京 ß";
+        adaptor.insert_record(Note { text: text.to_string() }).unwrap();
+        let notes: Vec<Note> = adaptor.query_all::<Note>().unwrap().collect();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].text, text);
+    }
+
+    #[test]
+    fn test_special_text_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        let texts = ["nul\0inside", "\0", "emoji 🦀👩‍💻", ""];
+        for text in texts {
+            adaptor.insert_record(Note { text: text.to_string() }).unwrap();
+        }
+        let notes: Vec<String> = adaptor.query_all::<Note>().unwrap().map(|n| n.text).collect();
+        assert_eq!(notes, texts);
+    }
+
+    #[test]
+    fn test_invalid_utf8_text() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.execute_sql("INSERT INTO y_note (text) VALUES (CAST(X'FF61' AS TEXT));").unwrap();
+        assert!(matches!(adaptor.query_all::<Note>().unwrap().try_next(), Err(DbError::InvalidUtf8 { column: 0 })));
+        assert!(matches!(adaptor.query_first_with_cond::<Note>(Cond::is_not_null("text")), Err(DbError::InvalidUtf8 { column: 0 })));
+        assert!(matches!(adaptor.query_columns::<Note>(&["text"]), Err(DbError::InvalidUtf8 { column: 0 })));
+        assert!(matches!(adaptor.aggregate_with_cond::<Note, String>(Aggregate::Max("text"), Cond::is_not_null("text")), Err(DbError::InvalidUtf8 { column: 0 })));
+
+        adaptor.set_lossy_utf8(true);
+        let notes: Vec<String> = adaptor.query_all::<Note>().unwrap().map(|n| n.text).collect();
+        assert_eq!(notes, vec!["\u{FFFD}a"]);
+        assert_eq!(adaptor.query_column::<Note, String>("text", Cond::is_not_null("text")).unwrap(), vec!["\u{FFFD}a"]);
+    }
+
+    #[derive(Schema)]
+    struct Payment {
+        pub amount: Decimal,
+        pub fee: Option<Decimal>
+    }
+
+    #[test]
+    fn test_decimal_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Payment>().unwrap();
+        let amounts = ["12.30", "-0.01", "0.1", "79228162514264337593543950335", "1.0000000000000000000000000001"];
+        for amount in amounts {
+            adaptor.insert_record(Payment { amount: amount.parse().unwrap(), fee: None }).unwrap();
+        }
+        adaptor.insert_record(Payment { amount: Decimal::new(1000, 2), fee: Some(Decimal::new(25, 2)) }).unwrap();
+
+        let payments: Vec<Payment> = adaptor.query_all::<Payment>().unwrap().collect();
+        let read_amounts: Vec<String> = payments.iter().map(|payment| payment.amount.to_string()).collect();
+        assert_eq!(read_amounts, ["12.30", "-0.01", "0.1", "79228162514264337593543950335", "1.0000000000000000000000000001", "10.00"]);
+        assert_eq!(payments[5].fee, Some(Decimal::new(25, 2)));
+        assert_eq!(payments[5].fee.unwrap().to_string(), "0.25");
+        // 0.1 is stored exactly, unlike a float
+        assert_eq!(payments[2].amount * Decimal::from(3), Decimal::new(3, 1));
+        assert_eq!(adaptor.query_column::<Payment, String>("amount", Cond::is_not_null("fee")).unwrap(), vec!["10.00"]);
+        assert_eq!(adaptor.count_with_cond::<Payment>(Cond::text_equal_to("amount", "12.30")).unwrap(), 1);
+
+        adaptor.execute_sql("INSERT INTO y_payment (amount) VALUES ('12,30');").unwrap();
+        match adaptor.query_with_cond::<Payment>(Cond::text_equal_to("amount", "12,30")).unwrap().try_next() {
+            Err(DbError::Other(message)) => assert_eq!(message, "The stored text \"12,30\" is no decimal number"),
+            _ => panic!("12,30 should not be read as a decimal")
+        }
+    }
+
+    #[derive(Schema)]
+    struct Device {
+        #[yoshino(primary_key)]
+        pub id: Uuid,
+        pub name: String,
+        pub parent: Option<Uuid>
+    }
+
+    #[test]
+    fn test_uuid_primary_key_round_trip() {
+        assert_eq!(SQLiteAdaptor::sql_for_create_table::<Device>(),
+            r#"CREATE TABLE IF NOT EXISTS "y_device" ("id" TEXT NOT NULL PRIMARY KEY, "name" TEXT NOT NULL, "parent" TEXT);"#);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Device>().unwrap();
+        let hub_id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        adaptor.insert_record(Device { id: hub_id, name: "hub".to_string(), parent: None }).unwrap();
+        let lamp_id = Uuid::new_v4();
+        adaptor.insert_record(Device { id: lamp_id, name: "lamp".to_string(), parent: Some(hub_id) }).unwrap();
+        adaptor.insert_records(vec![
+            Device { id: Uuid::new_v4(), name: "fan".to_string(), parent: Some(hub_id) },
+            Device { id: Uuid::new_v4(), name: "heater".to_string(), parent: Some(hub_id) }
+        ]).unwrap();
+
+        let hub = adaptor.query_first_with_cond::<Device>(Cond::text_equal_to("id", "67e55044-10b1-426f-9247-bb680e5fe0c8")).unwrap().unwrap();
+        assert_eq!(hub.id, hub_id);
+        assert_eq!(hub.parent, None);
+        let lamp = adaptor.query_first_with_cond::<Device>(Cond::text_equal_to("id", &lamp_id.to_string())).unwrap().unwrap();
+        assert_eq!(lamp.name, "lamp");
+        assert_eq!(lamp.parent, Some(hub_id));
+        assert_eq!(adaptor.count_with_cond::<Device>(Cond::text_equal_to("parent", &hub_id.to_string())).unwrap(), 3);
+
+        // the key is unique
+        assert!(adaptor.insert_record(Device { id: hub_id, name: "hub".to_string(), parent: None }).is_err());
+
+        adaptor.execute_sql("INSERT INTO y_device (id, name) VALUES ('hub-2', 'hub');").unwrap();
+        match adaptor.query_with_cond::<Device>(Cond::text_equal_to("id", "hub-2")).unwrap().try_next() {
+            Err(DbError::Other(message)) => assert_eq!(message, "The stored text \"hub-2\" is no UUID"),
+            _ => panic!("hub-2 should not be read as a UUID")
+        }
+    }
+
+    #[test]
+    fn test_update_and_delete_record_by_uuid_primary_key() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Device>().unwrap();
+        let lamp_id = Uuid::new_v4();
+        adaptor.insert_record(Device { id: lamp_id, name: "lamp".to_string(), parent: None }).unwrap();
+        adaptor.insert_record(Device { id: Uuid::new_v4(), name: "fan".to_string(), parent: None }).unwrap();
+
+        let mut lamp = adaptor.query_first_with_cond::<Device>(Cond::text_equal_to("id", &lamp_id.to_string())).unwrap().unwrap();
+        lamp.name = "desk lamp".to_string();
+        adaptor.update_record(&lamp).unwrap();
+        let names: Vec<String> = adaptor.query_all::<Device>().unwrap().map(|device| device.name).collect();
+        assert_eq!(names, vec!["desk lamp", "fan"]);
+
+        assert!(adaptor.delete_record(&lamp).unwrap());
+        assert!(!adaptor.delete_record(&lamp).unwrap());
+        match adaptor.update_record(&lamp) {
+            Err(DbError::Other(message)) => assert_eq!(message, "No y_device record with the same id"),
+            other => panic!("unexpected result: {:?}", other)
+        }
+        let names: Vec<String> = adaptor.query_all::<Device>().unwrap().map(|device| device.name).collect();
+        assert_eq!(names, vec!["fan"]);
+    }
+
+    #[derive(Schema)]
+    struct Contact {
+        pub id: RowID,
+        pub nickname: Option<String>
+    }
+
+    #[test]
+    fn test_invalid_utf8_column_index() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Contact>().unwrap();
+        adaptor.insert_record(Contact { id: RowID::NEW, nickname: Some("Zoë".to_string()) }).unwrap();
+        // "Zoë" in Latin-1
+        adaptor.execute_sql("INSERT INTO y_contact (nickname) VALUES (CAST(X'5A6FEB' AS TEXT));").unwrap();
+        adaptor.insert_record(Contact { id: RowID::NEW, nickname: None }).unwrap();
+        let results: Vec<Result<Contact, DbError>> = adaptor.query_all::<Contact>().unwrap().results().collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().nickname.as_deref(), Some("Zoë"));
+        assert!(matches!(&results[1], Err(error) if error.to_string() == "The text in column 1 is not valid UTF-8"));
+        assert_eq!(results[2].as_ref().unwrap().nickname, None);
+    }
+
+    #[derive(Schema)]
+    struct Memo {
+        pub text: Option<String>
+    }
+
+    #[test]
+    fn test_null_text_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Memo>().unwrap();
+        adaptor.insert_record(Memo { text: None }).unwrap();
+        adaptor.insert_record(Memo { text: Some(String::new()) }).unwrap();
+        adaptor.insert_record(Memo { text: Some("a\0b".to_string()) }).unwrap();
+        let memos: Vec<Option<String>> = adaptor.query_all::<Memo>().unwrap().map(|m| m.text).collect();
+        assert_eq!(memos, vec![None, Some(String::new()), Some("a\0b".to_string())]);
+    }
+
+    #[derive(Schema)]
+    struct Image {
+        pub data: Vec<u8>,
+        pub thumbnail: Option<Vec<u8>>
+    }
+
+    #[test]
+    fn test_blob_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Image>().unwrap();
+        adaptor.insert_record(Image { data: vec![0x00, 0xff, 0x7f, 0x00], thumbnail: Some(vec![]) }).unwrap();
+        adaptor.insert_record(Image { data: vec![], thumbnail: None }).unwrap();
+        let images: Vec<Image> = adaptor.query_all::<Image>().unwrap().collect();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].data, vec![0x00, 0xff, 0x7f, 0x00]);
+        assert_eq!(images[0].thumbnail, Some(vec![]));
+        assert_eq!(images[1].data, Vec::<u8>::new());
+        assert_eq!(images[1].thumbnail, None);
+    }
+
+    #[test]
+    fn test_arbitrary_blob_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Image>().unwrap();
+        let all_bytes: Vec<u8> = (0..=255).collect();
+        // bytes from a linear congruential generator, so the test is reproducible
+        let mut seed: u32 = 0x2545_f491;
+        let random_bytes: Vec<u8> = (0..4096).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        }).collect();
+        adaptor.insert_record(Image { data: all_bytes.clone(), thumbnail: Some(random_bytes.clone()) }).unwrap();
+        let images: Vec<Image> = adaptor.query_all::<Image>().unwrap().collect();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].data, all_bytes);
+        assert_eq!(images[0].thumbnail, Some(random_bytes));
+    }
+
+    #[derive(Schema)]
+    #[yoshino(table = "user_accounts")]
+    struct UserAccount {
+        pub name: String
+    }
+
+    #[test]
+    fn test_custom_table_name() {
+        use yoshino_core::Schema;
+        assert_eq!(UserAccount::get_schema_name(), "user_accounts");
+        assert_eq!(Account::get_schema_name(), "y_account");
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<UserAccount>().unwrap();
+        adaptor.insert_record(UserAccount { name: "alice".to_string() }).unwrap();
+        let names: Vec<String> = adaptor.query_all::<UserAccount>().unwrap().map(|a| a.name).collect();
+        assert_eq!(names, vec!["alice"]);
+        adaptor.execute_sql("SELECT name FROM user_accounts;").unwrap();
+    }
+
+    #[derive(Schema)]
+    #[yoshino(table = "users")]
+    struct User {
+        pub id: RowID,
+        pub user_name: String
+    }
+
+    #[test]
+    fn test_cond_values_are_bound() {
+        let injection = "'; DROP TABLE users; --";
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<User>().unwrap();
+        for user_name in ["admin", injection, "O'Brien"] {
+            adaptor.insert_record(User { id: RowID::NEW, user_name: user_name.to_string() }).unwrap();
+        }
+        let users = adaptor.query_with_cond::<User>(Cond::text_equal_to("user_name", injection)).unwrap().collect_all().unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].user_name, injection);
+        let users = adaptor.query_with_cond::<User>(Cond::text_in("user_name", vec!["O'Brien".to_string()])).unwrap().collect_all().unwrap();
+        assert_eq!(users[0].user_name, "O'Brien");
+        assert_eq!(SQLiteAdaptor::sql_for_query_with_cond::<User>(&Cond::text_equal_to("user_name", injection)),
+            r#"SELECT "id", "user_name" FROM "users" WHERE "user_name"=?1;"#);
+
+        adaptor.delete_with_cond::<User>(Cond::text_equal_to("user_name", injection)).unwrap();
+        adaptor.delete_with_cond::<User>(Cond::text_like("user_name", "O'%")).unwrap();
+        let names: Vec<String> = adaptor.query_all::<User>().unwrap().map(|user| user.user_name).collect();
+        assert_eq!(names, vec!["admin"]);
+    }
+
+    #[test]
+    fn test_text_comparison_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<User>().unwrap();
+        for user_name in ["admin", "O'Brien", "Zoë"] {
+            adaptor.insert_record(User { id: RowID::NEW, user_name: user_name.to_string() }).unwrap();
+        }
+        let user = adaptor.query_first_with_cond::<User>(Cond::text_equal_ci("user_name", "o'brIEN")).unwrap().unwrap();
+        assert_eq!(user.user_name, "O'Brien");
+        assert_eq!(adaptor.count_with_cond::<User>(Cond::text_equal_ci("user_name", "ADMIN")).unwrap(), 1);
+        assert_eq!(adaptor.count_with_cond::<User>(Cond::text_equal_to("user_name", "ADMIN")).unwrap(), 0);
+        assert_eq!(adaptor.count_with_cond::<User>(Cond::text_not_equal_to("user_name", "admin")).unwrap(), 2);
+        assert_eq!(adaptor.count_with_cond::<User>(Cond::or(Cond::text_equal_ci("user_name", "zoë"), Cond::text_equal_ci("user_name", "ZOË"))).unwrap(), 1);
+    }
+
+    #[derive(Schema)]
+    struct Role {
+        #[yoshino(collate = "nocase")]
+        #[yoshino(unique)]
+        name: String,
+        #[yoshino(collate = "rtrim")]
+        code: Option<String>
+    }
+
+    #[test]
+    fn test_column_collation_round_trip() {
+        assert_eq!(SQLiteAdaptor::sql_for_create_table::<Role>(),
+            r#"CREATE TABLE IF NOT EXISTS "y_role" ("name" TEXT NOT NULL COLLATE NOCASE UNIQUE, "code" TEXT COLLATE RTRIM);"#);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Role>().unwrap();
+        adaptor.insert_record(Role { name: "Admin".to_string(), code: Some("a1".to_string()) }).unwrap();
+        let role = adaptor.query_first_with_cond::<Role>(Cond::text_equal_to("name", "admin")).unwrap().unwrap();
+        assert_eq!(role.name, "Admin");
+        assert_eq!(adaptor.count_with_cond::<Role>(Cond::text_equal_ci("name", "ADMIN")).unwrap(), 1);
+        assert_eq!(adaptor.count_with_cond::<Role>(Cond::text_equal_to("code", "a1  ")).unwrap(), 1);
+        assert_eq!(adaptor.count_with_cond::<Role>(Cond::text_equal_to("code", "A1")).unwrap(), 0);
+        // the unique constraint uses the collation of the column too
+        assert!(adaptor.insert_record(Role { name: "ADMIN".to_string(), code: None }).is_err());
+    }
+
+    #[derive(Schema)]
+    struct Event {
+        /// doc comments are attributes too
+        #[yoshino(column = "created_ts")]
+        pub created_at: i64,
+        pub name: String
+    }
+
+    #[test]
+    fn test_custom_column_name() {
+        use yoshino_core::Schema;
+        let columns: Vec<String> = Event::get_fields().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(columns, vec!["created_ts", "name"]);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Event>().unwrap();
+        adaptor.insert_record(Event { created_at: 10, name: "start".to_string() }).unwrap();
+        adaptor.insert_record(Event { created_at: 20, name: "stop".to_string() }).unwrap();
+        let event = adaptor.query_first_with_cond::<Event>(Cond::integer_greater_than("created_ts", 15)).unwrap().unwrap();
+        assert_eq!(event.created_at, 20);
+        assert_eq!(event.name, "stop");
+        adaptor.execute_sql("SELECT created_ts FROM y_event;").unwrap();
+    }
+
+    #[test]
+    fn test_drop_table() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        assert!(adaptor.drop_table_for_schema::<Account>(false).is_err());
+        adaptor.drop_table_for_schema::<Account>(true).unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        adaptor.drop_table_for_schema::<Account>(false).unwrap();
+        assert!(adaptor.query_all::<Account>().is_err());
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 0);
+        adaptor.insert_record(Account { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_create_index() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        assert!(adaptor.create_index::<Account>(&["email"], false).is_err());
+        assert!(adaptor.create_index::<Account>(&[], false).is_err());
+        adaptor.create_index::<Account>(&["name"], true).unwrap();
+        adaptor.create_index::<Account>(&["name"], true).unwrap();
+        assert!(adaptor.execute_sql("CREATE INDEX idx_y_account_name ON y_account (id);").is_err());
+        adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        assert!(adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).is_err());
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 1);
+    }
+
+    #[derive(Schema)]
+    struct Stock {
+        #[yoshino(index, column = "user_name")]
+        pub name: String,
+        #[yoshino(index)]
+        pub stock: i64,
+        pub note: Option<String>
+    }
+
+    #[test]
+    fn test_create_indexes_for_schema() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Stock>().unwrap();
+        adaptor.create_indexes_for_schema::<Stock>().unwrap();
+        adaptor.create_indexes_for_schema::<Stock>().unwrap();
+        assert!(adaptor.execute_sql("CREATE INDEX idx_y_stock_user_name ON y_stock (note);").is_err());
+        assert!(adaptor.execute_sql("CREATE INDEX idx_y_stock_stock ON y_stock (note);").is_err());
+        adaptor.execute_sql("CREATE INDEX idx_y_stock_note ON y_stock (note);").unwrap();
+        adaptor.insert_record(Stock { name: "alice".to_string(), stock: 3, note: None }).unwrap();
+        let stock = adaptor.query_first_with_cond::<Stock>(Cond::integer_greater_than("stock", 2)).unwrap().unwrap();
+        assert_eq!(stock.name, "alice");
+    }
+
+    #[derive(Schema)]
+    struct Setting {
+        pub name: String,
+        pub enabled: bool,
+        pub overridden: Option<bool>
+    }
+
+    #[test]
+    fn test_bool_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Setting>().unwrap();
+        adaptor.insert_record(Setting { name: "sync".to_string(), enabled: true, overridden: None }).unwrap();
+        adaptor.insert_record(Setting { name: "backup".to_string(), enabled: false, overridden: Some(true) }).unwrap();
+        adaptor.execute_sql("INSERT INTO y_setting (name, enabled, overridden) VALUES ('debug', 5, 0);").unwrap();
+        let settings: Vec<Setting> = adaptor.query_all::<Setting>().unwrap().collect();
+        assert!(settings[0].enabled);
+        assert_eq!(settings[0].overridden, None);
+        assert!(!settings[1].enabled);
+        assert_eq!(settings[1].overridden, Some(true));
+        assert!(settings[2].enabled);
+        assert_eq!(settings[2].overridden, Some(false));
+        assert_eq!(adaptor.count_with_cond::<Setting>(Cond::integer_equal_to("enabled", 1)).unwrap(), 1);
+    }
+
+    #[derive(Schema)]
+    struct Counter {
+        pub small: i32,
+        pub count: u32,
+        pub total: u64,
+        pub cap: Option<u64>
+    }
+
+    #[test]
+    fn test_sized_integer_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Counter>().unwrap();
+        adaptor.insert_record(Counter { small: i32::MIN, count: u32::MAX, total: i64::MAX as u64, cap: None }).unwrap();
+        adaptor.insert_record(Counter { small: -1, count: 0, total: 7, cap: Some(10) }).unwrap();
+        let counters: Vec<Counter> = adaptor.query_all::<Counter>().unwrap().collect();
+        assert_eq!((counters[0].small, counters[0].count, counters[0].total, counters[0].cap), (i32::MIN, u32::MAX, i64::MAX as u64, None));
+        assert_eq!((counters[1].small, counters[1].count, counters[1].total, counters[1].cap), (-1, 0, 7, Some(10)));
+        let totals = adaptor.query_column::<Counter, u64>("total", Cond::integer_less_than("small", 0)).unwrap();
+        assert_eq!(totals, vec![i64::MAX as u64, 7]);
+    }
+
+    #[test]
+    fn test_sized_integer_out_of_range() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Counter>().unwrap();
+        adaptor.execute_sql("INSERT INTO y_counter (small, count, total) VALUES (0, -1, 0);").unwrap();
+        match adaptor.query_all::<Counter>().unwrap().try_next() {
+            Err(DbError::Other(message)) => assert_eq!(message, "The stored integer -1 is out of the range of u32"),
+            _ => panic!("-1 should not be read as a u32")
+        }
+        assert!(adaptor.query_column::<Counter, u32>("count", Cond::is_not_null("count")).is_err());
+    }
+
+    #[test]
+    fn test_u64_above_i64_max_is_not_stored() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Counter>().unwrap();
+        match adaptor.insert_record(Counter { small: 0, count: 0, total: u64::MAX, cap: None }) {
+            Err(DbError::Other(message)) => assert_eq!(message, "The integer 18446744073709551615 is out of the range of i64, so it can't be stored"),
+            other => panic!("unexpected result: {:?}", other)
+        }
+        assert!(adaptor.insert_record(Counter { small: 0, count: 0, total: 0, cap: Some(u64::MAX) }).is_err());
+        assert!(adaptor.update_with_cond(Cond::is_not_null("total"), Counter { small: 0, count: 0, total: u64::MAX, cap: None }).is_err());
+        assert_eq!(adaptor.count_all::<Counter>().unwrap(), 0);
+    }
+
+    #[derive(Schema)]
+    struct Meter {
+        pub id: RowID,
+        pub reading: u64
+    }
+
+    #[derive(Schema)]
+    struct Gauge {
+        #[yoshino(primary_key)]
+        pub serial: u64,
+        pub name: String
+    }
+
+    #[test]
+    fn test_u64_above_i64_max_fails_record_lookup() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Meter>().unwrap();
+        adaptor.create_table_for_schema::<Gauge>().unwrap();
+        let id = adaptor.insert_record(Meter { id: RowID::NEW, reading: 1 }).unwrap();
+        let meter = Meter { id: RowID::ID(id), reading: u64::MAX };
+        let expected = "The integer 18446744073709551615 is out of the range of i64, so it can't be stored";
+        assert!(matches!(adaptor.update_record(&meter), Err(DbError::Other(message)) if message == expected));
+        assert!(matches!(adaptor.delete_record(&meter), Err(DbError::Other(message)) if message == expected));
+        assert!(matches!(adaptor.query_children::<Meter, Meter>(&meter, "reading"), Err(DbError::Other(message)) if message == expected));
+        assert_eq!(adaptor.query_all::<Meter>().unwrap().next().unwrap().reading, 1);
+
+        adaptor.insert_record(Gauge { serial: 5, name: "boiler".to_string() }).unwrap();
+        let gauge = Gauge { serial: u64::MAX, name: "boiler".to_string() };
+        assert!(matches!(adaptor.update_record(&gauge), Err(DbError::Other(message)) if message == expected));
+        assert!(matches!(adaptor.delete_record(&gauge), Err(DbError::Other(message)) if message == expected));
+        assert_eq!(adaptor.count_all::<Gauge>().unwrap(), 1);
+    }
+
+    #[derive(SchemaEnum, Clone, Copy, Debug, PartialEq)]
+    enum Status {
+        Active,
+        /// kept out of the listings
+        Archived = 5,
+        Deleted
+    }
+
+    #[derive(Schema)]
+    struct Ticket {
+        pub id: RowID,
+        pub status: Status,
+        pub previous: Option<Status>
+    }
+
+    #[test]
+    fn test_enum_round_trip() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Ticket>().unwrap();
+        adaptor.insert_record(Ticket { id: RowID::NEW, status: Status::Active, previous: None }).unwrap();
+        adaptor.insert_record(Ticket { id: RowID::NEW, status: Status::Deleted, previous: Some(Status::Archived) }).unwrap();
+        let tickets = adaptor.query_all::<Ticket>().unwrap().collect_all().unwrap();
+        assert_eq!((tickets[0].status, tickets[0].previous), (Status::Active, None));
+        assert_eq!((tickets[1].status, tickets[1].previous), (Status::Deleted, Some(Status::Archived)));
+        assert_eq!(adaptor.query_column::<Ticket, i64>("status", Cond::is_not_null("previous")).unwrap(), vec![6]);
+        let previous = adaptor.query_column::<Ticket, Option<Status>>("previous", Cond::integer_equal_to("status", Status::Deleted as i64)).unwrap();
+        assert_eq!(previous, vec![Some(Status::Archived)]);
+    }
+
+    #[test]
+    fn test_unknown_enum_value_is_an_error() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Ticket>().unwrap();
+        adaptor.insert_record(Ticket { id: RowID::NEW, status: Status::Active, previous: None }).unwrap();
+        adaptor.update_fields_with_cond::<Ticket>(FieldUpdates::new().set_integer("previous", 2), Cond::is_null("previous")).unwrap();
+        let is_unknown_variant = |e: DbError| matches!(e, DbError::Other(message) if message.contains("The stored integer 2 is no variant of"));
+        assert!(is_unknown_variant(adaptor.query_all::<Ticket>().unwrap().collect_all().err().unwrap()));
+        assert!(is_unknown_variant(adaptor.query_first_with_cond::<Ticket>(Cond::integer_equal_to("status", 0)).err().unwrap()));
+        assert!(is_unknown_variant(adaptor.query_column::<Ticket, Option<Status>>("previous", Cond::is_not_null("previous")).err().unwrap()));
+        assert_eq!(adaptor.query_column::<Ticket, Status>("status", Cond::is_not_null("previous")).unwrap(), vec![Status::Active]);
+    }
+
+    #[derive(Schema)]
+    struct Session {
+        pub started_at: Timestamp,
+        pub ended_at: Option<Timestamp>
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        use yoshino_core::Schema;
+        assert!(matches!(Session::get_fields()[0].1, yoshino_core::db::DbDataType::Int));
+        let started_at = SystemTime::now();
+        let before_epoch = UNIX_EPOCH - Duration::from_millis(1500);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Session>().unwrap();
+        adaptor.insert_record(Session { started_at: Timestamp::from(started_at), ended_at: None }).unwrap();
+        adaptor.insert_record(Session { started_at: Timestamp::from(before_epoch), ended_at: Some(Timestamp::from_unix_seconds(60)) }).unwrap();
+        let sessions: Vec<Session> = adaptor.query_all::<Session>().unwrap().collect();
+        let expected_seconds = started_at.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        assert_eq!(sessions[0].started_at.unix_seconds(), expected_seconds);
+        assert_eq!(sessions[0].ended_at, None);
+        assert_eq!(sessions[1].started_at.unix_seconds(), -2);
+        assert_eq!(SystemTime::from(sessions[1].started_at), UNIX_EPOCH - Duration::from_secs(2));
+        assert_eq!(sessions[1].ended_at, Some(Timestamp::from_unix_seconds(60)));
+        let ended: Vec<Session> = adaptor.query_with_cond::<Session>(Cond::integer_greater_than("ended_at", 0)).unwrap().collect();
+        assert_eq!(ended.len(), 1);
+        let started = adaptor.query_with_cond::<Session>(Cond::integer_between("started_at", -2, 0)).unwrap().collect_all().unwrap();
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].started_at, Timestamp::from_unix_seconds(-2));
+        assert_eq!(adaptor.count_with_cond::<Session>(Cond::integer_between("started_at", 0, -2)).unwrap(), 0);
+    }
+
+    /// A custom field type, whose `Option` is nullable without implementing
+    /// `NullableIntegerField` for it.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Cents(i64);
+
+    impl IntegerField for Cents {
+        fn from_db_data(data: &Box<dyn DbData>) -> Self {
+            Cents(<i64 as DbData>::from_boxed_db_data(data))
+        }
+        fn to_db_data(&self) -> i64 {
+            self.0
+        }
+    }
+
+    #[derive(Schema)]
+    struct Invoice {
+        pub total: Cents,
+        pub discount: Option<Cents>,
+        pub paid: std::option::Option<u32>,
+        pub scan: Option<bytes::Bytes>
+    }
+
+    #[test]
+    fn test_option_of_any_field_type_is_nullable() {
+        use yoshino_core::Schema;
+        let field_types: Vec<DbDataType> = Invoice::get_fields().into_iter().map(|(_, field_type)| field_type).collect();
+        assert!(matches!(field_types[..], [DbDataType::Int, DbDataType::NullableInt, DbDataType::NullableInt, DbDataType::NullableBlob]));
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Invoice>().unwrap();
+        adaptor.insert_record(Invoice { total: Cents(1250), discount: Some(Cents(0)), paid: None, scan: Some(bytes::Bytes::new()) }).unwrap();
+        adaptor.insert_record(Invoice { total: Cents(99), discount: None, paid: Some(7), scan: None }).unwrap();
+        let invoices = adaptor.query_all::<Invoice>().unwrap().collect_all().unwrap();
+        assert_eq!((invoices[0].total, invoices[0].discount, invoices[0].paid), (Cents(1250), Some(Cents(0)), None));
+        assert_eq!(invoices[0].scan, Some(bytes::Bytes::new()));
+        assert_eq!((invoices[1].total, invoices[1].discount, invoices[1].paid), (Cents(99), None, Some(7)));
+        assert_eq!(invoices[1].scan, None);
+        assert_eq!(adaptor.count_with_cond::<Invoice>(Cond::is_null("discount")).unwrap(), 1);
+    }
+
+    #[derive(Schema)]
+    struct Account {
+        pub id: RowID,
+        pub name: String
+    }
+
+    #[test]
+    fn test_insert_returns_row_id() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let first_id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let second_id = adaptor.insert_record(Account { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        assert!(second_id > first_id);
+        let accounts: Vec<Account> = adaptor.query_with_cond::<Account>(Cond::integer_equal_to("id", second_id)).unwrap().collect();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].name, "bob");
+    }
+
+    #[test]
+    fn test_new_row_id_is_assigned_by_database() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let first_id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let second_id = adaptor.insert_record(Account { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        assert_ne!(first_id, 0);
+        let ids: Vec<RowID> = adaptor.query_all::<Account>().unwrap().map(|a| a.id).collect();
+        assert_eq!(ids, vec![RowID::ID(first_id), RowID::ID(second_id)]);
+    }
+
+    #[derive(Schema)]
+    struct Task {
+        pub id: RowID,
+        pub title: String,
+        pub note: Option<String>,
+        pub processed: i64
+    }
+
+    #[test]
+    fn test_count() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        assert_eq!(adaptor.count_all::<Task>().unwrap(), 0);
+        for (title, note) in [("a", None), ("b", Some("x")), ("c", None)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: note.map(|n| n.to_string()), processed: 0 }).unwrap();
+        }
+        assert_eq!(adaptor.count_all::<Task>().unwrap(), 3);
+        assert_eq!(adaptor.count_with_cond::<Task>(Cond::is_null("note")).unwrap(), 2);
+        assert_eq!(adaptor.count_with_cond::<Task>(Cond::text_equal_to("title", "z")).unwrap(), 0);
+        let either = Cond::or(Cond::text_equal_to("title", "a"), Cond::is_not_null("note"));
+        assert_eq!(adaptor.count_with_cond::<Task>(either).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_query_with_not_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for (title, note, processed) in [("a", None, 1), ("b", Some("x"), 0), ("c", Some("y"), 2)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: note.map(|n| n.to_string()), processed }).unwrap();
+        }
+        let cond = Cond::and(
+            Cond::not(Cond::is_null("note")),
+            Cond::not(Cond::integer_equal_to("processed", 0))
+        );
+        let titles: Vec<String> = adaptor.query_with_cond::<Task>(cond).unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["c"]);
+    }
+
+    #[test]
+    fn test_query_first_with_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        assert!(adaptor.query_first_with_cond::<Account>(Cond::text_equal_to("name", "alice")).unwrap().is_none());
+        let id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let alice = adaptor.query_first_with_cond::<Account>(Cond::text_equal_to("name", "alice")).unwrap().unwrap();
+        assert_eq!(alice.id, RowID::ID(id));
+        assert!(adaptor.query_first_with_cond::<Account>(Cond::text_equal_to("name", "bob")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_query_one() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let alice = adaptor.query_one::<Account>(Cond::integer_equal_to("id", id)).unwrap().unwrap();
+        assert_eq!(alice.name, "alice");
+        assert!(adaptor.query_one::<Account>(Cond::text_equal_to("name", "bob")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_query_with_comparison_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for (title, processed) in [("a", 3), ("b", 10), ("c", 12)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed }).unwrap();
+        }
+        let titles: Vec<String> = adaptor.query_with_cond::<Task>(Cond::integer_less_than("processed", 10))
+            .unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["a"]);
+        let in_range = Cond::and(
+            Cond::integer_greater_than_or_equal_to("processed", 10),
+            Cond::integer_less_than_or_equal_to("processed", 12)
+        );
+        assert_eq!(adaptor.count_with_cond::<Task>(in_range).unwrap(), 2);
+        assert_eq!(adaptor.count_with_cond::<Task>(Cond::integer_greater_than("processed", 12)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_query_with_like_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for title in ["Apple pie", "apple", "pineapple", "100% apple", "100 apples"] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed: 0 }).unwrap();
+        }
+        let query = |adaptor: &mut SQLiteAdaptor, cond: Cond| -> Vec<String> {
+            adaptor.query_with_cond::<Task>(cond).unwrap().map(|t| t.title).collect()
+        };
+        assert_eq!(query(&mut adaptor, Cond::text_starts_with("title", "apple")), vec!["Apple pie", "apple"]);
+        assert_eq!(query(&mut adaptor, Cond::text_contains("title", "% ")), vec!["100% apple"]);
+        assert_eq!(query(&mut adaptor, Cond::text_like("title", "100_a%")), vec!["100 apples"]);
+        assert_eq!(query(&mut adaptor, Cond::text_contains("title", "apple")).len(), 5);
+    }
+
+    #[test]
+    fn test_query_with_in_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for (title, processed) in [("a", 1), ("b", 5), ("c", 9), ("d", 2)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed }).unwrap();
+        }
+        let titles: Vec<String> = adaptor.query_with_cond::<Task>(Cond::integer_in("processed", vec![1, 9, 7]))
+            .unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["a", "c"]);
+        let cond = Cond::and(
+            Cond::text_in("title", vec!["b".to_string(), "d".to_string()]),
+            Cond::integer_greater_than("processed", 2)
+        );
+        let titles: Vec<String> = adaptor.query_with_cond::<Task>(cond).unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["b"]);
+        assert_eq!(adaptor.count_with_cond::<Task>(Cond::text_in("title", vec![])).unwrap(), 0);
+        assert_eq!(adaptor.count_with_cond::<Task>(Cond::not(Cond::integer_in("processed", vec![]))).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_query_limit() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for (title, processed) in [("a", 1), ("b", 0), ("c", 1), ("d", 1)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed }).unwrap();
+        }
+        let titles: Vec<String> = adaptor.query_limit::<Task>(2, 1).unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["b", "c"]);
+        let titles: Vec<String> = adaptor.query_with_cond_limit::<Task>(Cond::integer_equal_to("processed", 1), 2, 1)
+            .unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["c", "d"]);
+        let titles: Vec<String> = adaptor.query_limit::<Task>(u64::MAX, 3).unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["d"]);
+        assert_eq!(adaptor.query_limit::<Task>(0, 0).unwrap().count(), 0);
+        assert_eq!(adaptor.query_limit::<Task>(10, 10).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_query_sorted() {
+        use yoshino_core::SortDir;
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for (title, processed) in [("b", 1), ("c", 0), ("a", 1)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed }).unwrap();
+        }
+        let titles: Vec<String> = adaptor.query_sorted::<Task>(vec![("title".to_string(), SortDir::Desc)])
+            .unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["c", "b", "a"]);
+        let titles: Vec<String> = adaptor.query_with_cond_sorted::<Task>(
+            Cond::integer_equal_to("processed", 1),
+            vec![("title".to_string(), SortDir::Asc)]
+        ).unwrap().map(|t| t.title).collect();
+        assert_eq!(titles, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_insert_or_replace() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        assert!(adaptor.insert_record(Account { id: RowID::ID(id), name: "carol".to_string() }).is_err());
+        let replaced_id = adaptor.insert_record_or_replace(Account { id: RowID::ID(id), name: "carol".to_string() }).unwrap();
+        assert_eq!(replaced_id, id);
+        let accounts: Vec<Account> = adaptor.query_all::<Account>().unwrap().collect();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].id, RowID::ID(id));
+        assert_eq!(accounts[0].name, "carol");
+    }
+
+    #[test]
+    fn test_insert_or_ignore() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let id = adaptor.insert_record_or_ignore(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap().unwrap();
+        assert_eq!(adaptor.insert_record_or_ignore(Account { id: RowID::ID(id), name: "carol".to_string() }).unwrap(), None);
+        let names: Vec<String> = adaptor.query_all::<Account>().unwrap().map(|a| a.name).collect();
+        assert_eq!(names, vec!["alice"]);
+    }
+
+    #[test]
+    fn test_insert_records() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let prepare_count = adaptor.prepare_count;
+        let inserted = adaptor.insert_records((0..1000).map(|i| Account { id: RowID::NEW, name: format!("user{}", i) })).unwrap();
+        assert_eq!(inserted, 1000);
+        assert_eq!(adaptor.prepare_count, prepare_count + 1);
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_statements_are_cached() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let prepare_count = adaptor.prepare_count;
+        for i in 0..100 {
+            adaptor.insert_record(Account { id: RowID::NEW, name: format!("user{}", i) }).unwrap();
+        }
+        assert_eq!(adaptor.prepare_count, prepare_count + 1);
+        assert!(adaptor.insert_record(Account { id: RowID::ID(1), name: "again".to_string() }).is_err());
+        let id = adaptor.insert_record(Account { id: RowID::NEW, name: "last".to_string() }).unwrap();
+        assert_eq!(id, 101);
+        assert_eq!(adaptor.prepare_count, prepare_count + 1);
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 101);
+    }
+
+    #[test]
+    fn test_stmt_cache_capacity() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        adaptor.set_stmt_cache_capacity(1);
+        let prepare_count = adaptor.prepare_count;
+        for i in 0..3 {
+            adaptor.insert_record(Account { id: RowID::NEW, name: format!("user{}", i) }).unwrap();
+            assert_eq!(adaptor.count_all::<Account>().unwrap(), i + 1);
+        }
+        // the two statements evict each other
+        assert_eq!(adaptor.prepare_count, prepare_count + 6);
+        adaptor.set_stmt_cache_capacity(2);
+        adaptor.insert_record(Account { id: RowID::NEW, name: "user3".to_string() }).unwrap();
+        adaptor.count_all::<Account>().unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "user4".to_string() }).unwrap();
+        assert_eq!(adaptor.prepare_count, prepare_count + 7);
+        adaptor.set_stmt_cache_capacity(0);
+        adaptor.insert_record(Account { id: RowID::NEW, name: "user5".to_string() }).unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "user6".to_string() }).unwrap();
+        assert_eq!(adaptor.prepare_count, prepare_count + 9);
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 7);
+    }
+
+    #[test]
+    fn test_insert_records_from_vec() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        let tasks: Vec<Task> = (0..5000)
+            .map(|i| Task { id: RowID::NEW, title: format!("task{}", i), note: None, processed: i })
+            .collect();
+        assert_eq!(adaptor.insert_records(tasks).unwrap(), 5000);
+        let tasks: Vec<Task> = adaptor.query_all::<Task>().unwrap().collect();
+        assert_eq!(tasks.len(), 5000);
+        assert!(tasks.iter().enumerate().all(|(i, t)| t.processed == i as i64 && t.title == format!("task{}", i)));
+    }
+
+    #[test]
+    fn test_insert_records_rolls_back_on_error() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let records = vec![
+            Account { id: RowID::ID(1), name: "alice".to_string() },
+            Account { id: RowID::ID(2), name: "bob".to_string() },
+            Account { id: RowID::ID(1), name: "carol".to_string() },
+        ];
+        assert!(adaptor.insert_records(records).is_err());
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 0);
+        assert_eq!(adaptor.insert_records(Vec::<Account>::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_update_record() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let alice_id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        let bob_id = adaptor.insert_record(Account { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        adaptor.update_record(&Account { id: RowID::ID(alice_id), name: "carol".to_string() }).unwrap();
+        let names: Vec<String> = adaptor.query_all::<Account>().unwrap().map(|a| a.name).collect();
+        assert_eq!(names, vec!["carol", "bob"]);
+
+        assert!(adaptor.update_record(&Account { id: RowID::NEW, name: "dave".to_string() }).is_err());
+        adaptor.delete_with_cond::<Account>(Cond::integer_equal_to("id", bob_id)).unwrap();
+        assert!(adaptor.update_record(&Account { id: RowID::ID(bob_id), name: "bob".to_string() }).is_err());
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_delete_record() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        let alice_id = adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        let alice = Account { id: RowID::ID(alice_id), name: "alice".to_string() };
+        assert!(adaptor.delete_record(&alice).unwrap());
+        assert!(!adaptor.delete_record(&alice).unwrap());
+        assert!(adaptor.delete_record(&Account { id: RowID::NEW, name: "bob".to_string() }).is_err());
+        let names: Vec<String> = adaptor.query_all::<Account>().unwrap().map(|a| a.name).collect();
+        assert_eq!(names, vec!["bob"]);
+    }
+
+    #[test]
+    fn test_delete_and_update_record_without_row_id() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Product>().unwrap();
+        let milk = Product { name: "milk".to_string(), price: 1.25, discount: None };
+        adaptor.insert_record(Product { name: "milk".to_string(), price: 1.25, discount: None }).unwrap();
+        match adaptor.delete_record(&milk) {
+            Err(DbError::Other(message)) => assert_eq!(message, "y_product has no row id field or primary key"),
+            other => panic!("unexpected result: {:?}", other)
+        }
+        assert!(adaptor.update_record(&milk).is_err());
+        assert_eq!(adaptor.count_all::<Product>().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_delete_all() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        assert!(adaptor.delete_all::<Account>().is_err());
+        adaptor.create_table_for_schema::<Account>().unwrap();
+        for name in ["alice", "bob", "carol"] {
+            adaptor.insert_record(Account { id: RowID::NEW, name: name.to_string() }).unwrap();
+        }
+        assert_eq!(adaptor.delete_all::<Account>().unwrap(), 3);
+        assert_eq!(adaptor.count_all::<Account>().unwrap(), 0);
+        assert_eq!(adaptor.delete_all::<Account>().unwrap(), 0);
+        assert_eq!(adaptor.delete_all_and_reset_sequence::<Account>().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_delete_all_and_reset_sequence() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.execute_script("CREATE TABLE y_account (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL);").unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "alice".to_string() }).unwrap();
+        adaptor.insert_record(Account { id: RowID::NEW, name: "bob".to_string() }).unwrap();
+        assert_eq!(adaptor.delete_all::<Account>().unwrap(), 2);
+        assert_eq!(adaptor.insert_record(Account { id: RowID::NEW, name: "carol".to_string() }).unwrap(), 3);
+        assert_eq!(adaptor.delete_all_and_reset_sequence::<Account>().unwrap(), 1);
+        assert_eq!(adaptor.insert_record(Account { id: RowID::NEW, name: "dave".to_string() }).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_update_with_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        let id = adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: Some("draft".to_string()), processed: 0 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "read".to_string(), note: None, processed: 0 }).unwrap();
+
+        let update = Task { id: RowID::NEW, title: "write".to_string(), note: None, processed: 1 };
+        adaptor.update_with_cond(Cond::integer_equal_to("id", id), update).unwrap();
+
+        let tasks: Vec<Task> = adaptor.query_all::<Task>().unwrap().collect();
+        assert_eq!(tasks.len(), 2);
+        assert!(matches!(tasks[0].id, RowID::ID(v) if v == id));
+        assert_eq!(tasks[0].note, None);
+        assert_eq!(tasks[0].processed, 1);
+        assert_eq!(tasks[1].title, "read");
+        assert_eq!(tasks[1].processed, 0);
+    }
+
+    #[test]
+    fn test_update_fields_with_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: Some("draft".to_string()), processed: 1 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "read".to_string(), note: None, processed: 0 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "review".to_string(), note: None, processed: 0 }).unwrap();
+
+        let updates = FieldUpdates::new().set_integer("processed", 2).set_text("note", "it's done");
+        assert_eq!(adaptor.update_fields_with_cond::<Task>(updates, Cond::is_null("note")).unwrap(), 2);
+        let updates = FieldUpdates::new().set_null("note").set_text("title", "rewrite").set_text("title", "edit");
+        assert_eq!(adaptor.update_fields_with_cond::<Task>(updates, Cond::integer_equal_to("id", 1)).unwrap(), 1);
+        let updates = FieldUpdates::new().set_integer("processed", 5);
+        assert_eq!(adaptor.update_fields_with_cond::<Task>(updates, Cond::integer_equal_to("id", 4)).unwrap(), 0);
+
+        let tasks: Vec<Task> = adaptor.query_all::<Task>().unwrap().collect();
+        assert_eq!(tasks[0].title, "edit");
+        assert_eq!(tasks[0].note, None);
+        assert_eq!(tasks[0].processed, 1);
+        assert_eq!(tasks[1].note, Some("it's done".to_string()));
+        assert_eq!(tasks[2].processed, 2);
+    }
+
+    #[test]
+    fn test_update_fields_with_cond_errors() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: None, processed: 0 }).unwrap();
+        let all = || Cond::is_not_null("title");
+        assert!(adaptor.update_fields_with_cond::<Task>(FieldUpdates::new(), all()).is_err());
+        assert!(adaptor.update_fields_with_cond::<Task>(FieldUpdates::new().set_integer("owner", 1), all()).is_err());
+        assert!(adaptor.update_fields_with_cond::<Task>(FieldUpdates::new().set_integer("id", 2), all()).is_err());
+        assert!(adaptor.update_fields_with_cond::<Task>(FieldUpdates::new().set_text("processed", "yes"), all()).is_err());
+        assert!(adaptor.update_fields_with_cond::<Task>(FieldUpdates::new().set_null("title"), all()).is_err());
+        assert!(adaptor.update_fields_with_cond::<Task>(FieldUpdates::new().set_float("processed", 1.0), all()).is_err());
+        let tasks: Vec<Task> = adaptor.query_all::<Task>().unwrap().collect();
+        assert_eq!(tasks[0].title, "write");
+        assert_eq!(tasks[0].processed, 0);
+    }
+
+    #[test]
+    fn test_execute_raw() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for title in ["write", "read", "review"] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed: 1 }).unwrap();
+        }
+        let changed = adaptor.execute_raw("UPDATE y_task SET processed = processed + ? WHERE title LIKE ?;", &[&2i64, &"r%".to_string()]).unwrap();
+        assert_eq!(changed, 2);
+        let processed: Vec<i64> = adaptor.query_all::<Task>().unwrap().map(|t| t.processed).collect();
+        assert_eq!(processed, vec![1, 3, 3]);
+
+        let changed = adaptor.execute_raw("UPDATE y_task SET note = ?1 WHERE id = ?2;", &[&Some("done".to_string()), &1i64]).unwrap();
+        assert_eq!(changed, 1);
+        let task = adaptor.query_first_with_cond::<Task>(Cond::integer_equal_to("id", 1)).unwrap().unwrap();
+        assert_eq!(task.note, Some("done".to_string()));
+    }
+
+    #[test]
+    fn test_execute_raw_rejects_multiple_statements() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: None, processed: 0 }).unwrap();
+        assert!(adaptor.execute_raw("UPDATE y_task SET processed = 1; DROP TABLE y_task;", &[]).is_err());
+        // neither statement is executed
+        let tasks: Vec<Task> = adaptor.query_all::<Task>().unwrap().collect();
+        assert_eq!(tasks[0].processed, 0);
+        // a trailing comment is not another statement
+        assert_eq!(adaptor.execute_raw("UPDATE y_task SET processed = 1; -- done", &[]).unwrap(), 1);
+        assert!(adaptor.execute_raw("  ", &[]).is_err());
+    }
+
+    #[test]
+    fn test_execute_raw_parameter_count_mismatch() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: None, processed: 0 }).unwrap();
+        assert!(adaptor.execute_raw("UPDATE y_task SET processed = ? WHERE id = ?;", &[&1i64]).is_err());
+        assert!(adaptor.execute_raw("UPDATE y_task SET processed = 1;", &[&1i64]).is_err());
+        let tasks: Vec<Task> = adaptor.query_all::<Task>().unwrap().collect();
+        assert_eq!(tasks[0].processed, 0);
+    }
+
+    #[test]
+    fn test_aggregate_with_cond() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        for (title, processed) in [("write", 3), ("read", 4), ("review", 8)] {
+            adaptor.insert_record(Task { id: RowID::NEW, title: title.to_string(), note: None, processed }).unwrap();
+        }
+        let cond = || Cond::text_starts_with("title", "r");
+        assert_eq!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Sum("processed"), cond()).unwrap(), Some(12));
+        assert_eq!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Min("processed"), cond()).unwrap(), Some(4));
+        assert_eq!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Max("processed"), cond()).unwrap(), Some(8));
+        assert_eq!(adaptor.aggregate_with_cond::<Task, f64>(Aggregate::Avg("processed"), cond()).unwrap(), Some(6.0));
+        assert_eq!(adaptor.aggregate_with_cond::<Task, String>(Aggregate::Max("title"), cond()).unwrap(), Some("review".to_string()));
+        assert_eq!(adaptor.aggregate_with_cond::<Task, RowID>(Aggregate::Max("id"), cond()).unwrap(), Some(RowID::ID(3)));
+        // the aggregate of no records is None rather than 0
+        let none = Cond::text_equal_to("title", "sleep");
+        assert_eq!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Sum("processed"), none.clone()).unwrap(), None);
+        assert_eq!(adaptor.aggregate_with_cond::<Task, f64>(Aggregate::Avg("processed"), none).unwrap(), None);
+
+        adaptor.create_table_for_schema::<Product>().unwrap();
+        adaptor.insert_record(Product { name: "milk".to_string(), price: 1.25, discount: None }).unwrap();
+        adaptor.insert_record(Product { name: "cream".to_string(), price: 2.5, discount: Some(0.5) }).unwrap();
+        let all = || Cond::is_not_null("name");
+        assert_eq!(adaptor.aggregate_with_cond::<Product, f64>(Aggregate::Sum("price"), all()).unwrap(), Some(3.75));
+        assert_eq!(adaptor.aggregate_with_cond::<Product, f64>(Aggregate::Max("discount"), all()).unwrap(), Some(0.5));
+    }
+
+    #[test]
+    fn test_aggregate_with_cond_errors() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        let all = || Cond::is_not_null("title");
+        assert!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Sum("owner"), all()).is_err());
+        assert!(adaptor.aggregate_with_cond::<Task, String>(Aggregate::Sum("title"), all()).is_err());
+        assert!(adaptor.aggregate_with_cond::<Task, f64>(Aggregate::Avg("note"), all()).is_err());
+        assert!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Avg("processed"), all()).is_err());
+        assert!(adaptor.aggregate_with_cond::<Task, f64>(Aggregate::Sum("processed"), all()).is_err());
+        assert!(adaptor.aggregate_with_cond::<Task, i64>(Aggregate::Min("title"), all()).is_err());
+    }
+
+    #[test]
+    fn test_query_column() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: Some("draft".to_string()), processed: 0 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "read".to_string(), note: None, processed: 0 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "review".to_string(), note: None, processed: 1 }).unwrap();
+
+        let titles = adaptor.query_column::<Task, String>("title", Cond::integer_equal_to("processed", 0)).unwrap();
+        assert_eq!(titles, vec!["write", "read"]);
+        let notes = adaptor.query_column::<Task, Option<String>>("note", Cond::integer_less_than("id", 3)).unwrap();
+        assert_eq!(notes, vec![Some("draft".to_string()), None]);
+        let ids = adaptor.query_column::<Task, RowID>("id", Cond::text_starts_with("title", "re")).unwrap();
+        assert_eq!(ids, vec![RowID::ID(2), RowID::ID(3)]);
+        let processed = adaptor.query_column::<Task, bool>("processed", Cond::integer_greater_than("id", 1)).unwrap();
+        assert_eq!(processed, vec![false, true]);
+        assert!(adaptor.query_column::<Task, i64>("id", Cond::integer_equal_to("id", 4)).unwrap().is_empty());
+    }
+
+    #[derive(Schema)]
+    #[yoshino(table = "order")]
+    struct Order {
+        pub id: RowID,
+        #[yoshino(index)]
+        pub select: String,
+        #[yoshino(column = "group")]
+        pub group_id: i64
+    }
+
+    #[test]
+    fn test_keyword_identifiers() {
+        use yoshino_core::SortDir;
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Order>().unwrap();
+        adaptor.create_indexes_for_schema::<Order>().unwrap();
+        adaptor.insert_record(Order { id: RowID::NEW, select: "first".to_string(), group_id: 1 }).unwrap();
+        adaptor.insert_record(Order { id: RowID::NEW, select: "second".to_string(), group_id: 2 }).unwrap();
+        adaptor.insert_record(Order { id: RowID::NEW, select: "third".to_string(), group_id: 2 }).unwrap();
+
+        let orders: Vec<Order> = adaptor.query_with_cond_sorted(Cond::integer_equal_to("group", 2), vec![("select".to_string(), SortDir::Desc)]).unwrap().collect();
+        assert_eq!(orders.iter().map(|order| order.select.as_str()).collect::<Vec<_>>(), vec!["third", "second"]);
+        assert_eq!(adaptor.query_column::<Order, String>("select", Cond::text_in("select", vec!["first".to_string()])).unwrap(), vec!["first"]);
+        assert_eq!(adaptor.aggregate_with_cond::<Order, i64>(Aggregate::Sum("group"), Cond::is_not_null("select")).unwrap(), Some(5));
+        let updates = FieldUpdates::new().set_text("select", "updated");
+        assert_eq!(adaptor.update_fields_with_cond::<Order>(updates, Cond::integer_equal_to("group", 1)).unwrap(), 1);
+        let mut order = adaptor.query_first_with_cond::<Order>(Cond::text_equal_to("select", "updated")).unwrap().unwrap();
+        order.group_id = 3;
+        adaptor.update_record(&order).unwrap();
+        assert_eq!(adaptor.count_with_cond::<Order>(Cond::integer_equal_to("group", 3)).unwrap(), 1);
+        assert!(adaptor.delete_record(&order).unwrap());
+        adaptor.delete_with_cond::<Order>(Cond::text_like("select", "sec%")).unwrap();
+        assert_eq!(adaptor.count_all::<Order>().unwrap(), 1);
+        adaptor.drop_table_for_schema::<Order>(false).unwrap();
+    }
+
+    #[test]
+    fn test_bound_values_are_not_kept() {
+        use std::ffi::CStr;
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        let title = "x".repeat(1 << 20);
+        adaptor.insert_record(Task { id: RowID::NEW, title: title.clone(), note: Some("draft".to_string()), processed: 0 }).unwrap();
+
+        // the values are read in place while inserting, so they're unbound before they're dropped
+        let stmt = adaptor.stmt_cache.get(&SQLiteAdaptor::sql_for_insert::<Task>()).unwrap();
+        let expanded_sql = unsafe {
+            let sql_ptr = libsqlite3_sys::sqlite3_expanded_sql(stmt);
+            let sql = CStr::from_ptr(sql_ptr).to_string_lossy().into_owned();
+            libsqlite3_sys::sqlite3_free(sql_ptr as *mut std::os::raw::c_void);
+            sql
+        };
+        assert_eq!(expanded_sql, r#"INSERT INTO "y_task" ("id", "title", "note", "processed") VALUES (NULL, NULL, NULL, NULL);"#);
+
+        // the values of a query are copied, as its rows are read after the condition is dropped
+        let result = {
+            let cond = Cond::text_equal_to("title", &title);
+            adaptor.query_with_cond::<Task>(cond).unwrap()
+        };
+        let tasks: Vec<Task> = result.collect();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].title, title);
+        assert_eq!(tasks[0].note.as_deref(), Some("draft"));
+    }
+
+    #[test]
+    fn test_query_columns() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: Some("draft".to_string()), processed: 0 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "read".to_string(), note: None, processed: 1 }).unwrap();
+
+        let rows = adaptor.query_columns::<Task>(&["note", "title"]).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), 2);
+        assert_eq!(Option::<String>::from_boxed_db_data(&rows[0][0]), Some("draft".to_string()));
+        assert_eq!(String::from_boxed_db_data(&rows[0][1]), "write");
+        assert_eq!(Option::<String>::from_boxed_db_data(&rows[1][0]), None);
+        assert_eq!(String::from_boxed_db_data(&rows[1][1]), "read");
+
+        let rows = adaptor.query_columns_with_cond::<Task>(&["id", "processed"], Cond::text_equal_to("title", "read")).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(RowID::from_db_data(&rows[0][0]), RowID::ID(2));
+        assert_eq!(i64::from_boxed_db_data(&rows[0][1]), 1);
+
+        assert!(adaptor.query_columns::<Task>(&[]).is_err());
+        assert!(adaptor.query_columns::<Task>(&["title", "missing"]).is_err());
+    }
+
+    #[test]
+    fn test_query_column_errors() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: Some("draft".to_string()), processed: 0 }).unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "read".to_string(), note: None, processed: 0 }).unwrap();
+
+        assert!(adaptor.query_column::<Task, String>("owner", Cond::integer_equal_to("id", 1)).is_err());
+        assert!(adaptor.query_column::<Task, String>("processed", Cond::integer_equal_to("id", 1)).is_err());
+        assert!(adaptor.query_column::<Task, f64>("processed", Cond::integer_equal_to("id", 1)).is_err());
+        // NULL can't be read as String, but non-null values can
+        assert_eq!(adaptor.query_column::<Task, String>("note", Cond::integer_equal_to("id", 1)).unwrap(), vec!["draft"]);
+        assert!(adaptor.query_column::<Task, String>("note", Cond::integer_equal_to("processed", 0)).is_err());
+    }
+
+    #[test]
+    fn test_execute_script() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Task>().unwrap();
+        adaptor.execute_script("
+            -- keep a log of new tasks
+            CREATE TABLE task_log (title TEXT NOT NULL);;
+            CREATE TRIGGER log_task AFTER INSERT ON y_task BEGIN
+                INSERT INTO task_log (title) VALUES (new.title);
+            END;
+            /* done */
+        ").unwrap();
+        adaptor.insert_record(Task { id: RowID::NEW, title: "write".to_string(), note: None, processed: 0 }).unwrap();
+        assert_eq!(adaptor.execute_raw("DELETE FROM task_log WHERE title = 'write';", &[]).unwrap(), 1);
+        adaptor.execute_script("").unwrap();
+    }
+
+    #[test]
+    fn test_execute_script_rolls_back_on_failure() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let result = adaptor.execute_script("
+            CREATE TABLE task_log (title TEXT NOT NULL);
+            INSERT INTO task_log (title) VALUES ('write');
+            INSERT INTO task_log (title) VALUES (NULL);
+        ");
+        match result {
+            Err(DbError::ConstraintViolation(failure, _)) => {
+                assert!(failure.message.starts_with("Statement 3 of the script failed: "), "{}", failure.message);
+                assert_eq!(failure.sql.as_deref(), Some("INSERT INTO task_log (title) VALUES (NULL);"));
+            }
+            other => panic!("unexpected result: {:?}", other)
+        }
+        assert!(adaptor.execute_raw("DELETE FROM task_log;", &[]).is_err());
+        assert!(adaptor.execute_script("CREATE TABLE task_log (title TEXT); SELECT * FROM missing;").is_err());
+        assert!(adaptor.execute_raw("DELETE FROM task_log;", &[]).is_err());
+    }
+}
+
+mod pragma_test {
+    use std::fs;
+    use yoshino_core::db::DbError;
+    use crate::{JournalMode, SQLiteAdaptor, Synchronous};
+    use super::temp_path;
+
+    #[test]
+    fn test_set_journal_mode() {
+        let path = temp_path("journal_mode");
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        adaptor.set_journal_mode(JournalMode::Wal).unwrap();
+        assert_eq!(adaptor.pragma("journal_mode", "wal").unwrap(), "wal");
+        drop(adaptor);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_set_journal_mode_not_applied() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        match adaptor.set_journal_mode(JournalMode::Wal) {
+            Err(DbError::Other(_)) => {}
+            _ => panic!("in-memory databases can't use WAL")
+        }
+        adaptor.set_journal_mode(JournalMode::Memory).unwrap();
+    }
+
+    #[test]
+    fn test_set_synchronous() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.set_synchronous(Synchronous::Normal).unwrap();
+        adaptor.set_synchronous(Synchronous::Off).unwrap();
+    }
+
+    #[test]
+    fn test_pragma() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        assert_eq!(adaptor.pragma("user_version", "7").unwrap(), "");
+        assert_eq!(adaptor.pragma("cache_size", "-4000").unwrap(), "");
+        assert!(matches!(adaptor.pragma("user_version; DROP TABLE x", "1"), Err(DbError::Other(_))));
+    }
+
+    #[test]
+    fn test_set_foreign_keys() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        assert!(adaptor.foreign_keys_enabled().unwrap());
+        adaptor.set_foreign_keys(false).unwrap();
+        assert!(!adaptor.foreign_keys_enabled().unwrap());
+        let transaction = adaptor.begin_transaction().unwrap();
+        assert!(transaction.adaptor.set_foreign_keys(true).is_err());
+        transaction.rollback().unwrap();
+        adaptor.set_foreign_keys(true).unwrap();
+    }
+}
+
+mod transaction_test {
+    use std::panic;
+    use yoshino_core::db::DbAdaptor;
+    use yoshino_core::{IntegerField, TextField};
+    use yoshino_derive::Schema;
+    use crate::SQLiteAdaptor;
+
+    #[derive(Schema)]
+    struct Counter {
+        pub name: String,
+        pub stock: i64
+    }
+
+    fn new_adaptor() -> SQLiteAdaptor {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Counter>().unwrap();
+        adaptor
+    }
+
+    fn counter(name: &str, stock: i64) -> Counter {
+        Counter { name: name.to_string(), stock }
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut adaptor = new_adaptor();
+        let mut transaction = adaptor.begin_transaction().unwrap();
+        transaction.insert_record(counter("milk", 1)).unwrap();
+        transaction.insert_record(counter("cream", 2)).unwrap();
+        assert_eq!(transaction.query_all::<Counter>().unwrap().count(), 2);
+        transaction.commit().unwrap();
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut adaptor = new_adaptor();
+        adaptor.insert_record(counter("milk", 1)).unwrap();
+        let mut transaction = adaptor.begin_transaction().unwrap();
+        transaction.insert_record(counter("cream", 2)).unwrap();
+        transaction.rollback().unwrap();
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_transaction_is_begin_transaction() {
+        let mut adaptor = new_adaptor();
+        let mut transaction = adaptor.transaction().unwrap();
+        transaction.insert_record(counter("milk", 1)).unwrap();
+        transaction.commit().unwrap();
+        let mut transaction = adaptor.transaction().unwrap();
+        transaction.insert_record(counter("cream", 2)).unwrap();
+        drop(transaction);
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_rollback_on_drop() {
+        let mut adaptor = new_adaptor();
+        {
+            let mut transaction = adaptor.begin_transaction().unwrap();
+            transaction.insert_record(counter("milk", 1)).unwrap();
+        }
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_update_and_delete_rolled_back_on_drop() {
+        use yoshino_core::Cond;
+        let mut adaptor = new_adaptor();
+        adaptor.insert_record(counter("milk", 1)).unwrap();
+        adaptor.insert_record(counter("cream", 2)).unwrap();
+        {
+            let mut transaction = adaptor.begin_transaction().unwrap();
+            transaction.update_with_cond(Cond::text_equal_to("name", "milk"), counter("milk", 10)).unwrap();
+            transaction.delete_with_cond::<Counter>(Cond::text_equal_to("name", "cream")).unwrap();
+            assert_eq!(transaction.query_all::<Counter>().unwrap().count(), 1);
+        }
+        let stocks: Vec<i64> = adaptor.query_all::<Counter>().unwrap().map(|c| c.stock).collect();
+        assert_eq!(stocks, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_rollback_on_panic() {
+        let mut adaptor = new_adaptor();
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let mut transaction = adaptor.begin_transaction().unwrap();
+            transaction.insert_record(counter("milk", 1)).unwrap();
+            panic!("failure in the middle of a transaction");
+        }));
+        assert!(result.is_err());
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 0);
+        // the connection is still usable after the rollback
+        adaptor.insert_record(counter("cream", 2)).unwrap();
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_savepoint_partial_rollback() {
+        let mut adaptor = new_adaptor();
+        let mut transaction = adaptor.begin_transaction().unwrap();
+        transaction.insert_record(counter("milk", 1)).unwrap();
+        {
+            let mut group = transaction.savepoint("first group").unwrap();
+            group.insert_record(counter("cream", 2)).unwrap();
+            group.release().unwrap();
+        }
+        {
+            let mut group = transaction.savepoint("second \"group\"").unwrap();
+            group.insert_record(counter("apple", 3)).unwrap();
+            group.rollback().unwrap();
+        }
+        {
+            let mut group = transaction.savepoint("third group").unwrap();
+            group.insert_record(counter("pear", 4)).unwrap();
+            // dropped without release
+        }
+        transaction.commit().unwrap();
+        let names: Vec<String> = adaptor.query_all::<Counter>().unwrap().map(|c| c.name).collect();
+        assert_eq!(names, vec!["milk", "cream"]);
+    }
+
+    #[test]
+    fn test_nested_savepoints() {
+        let mut adaptor = new_adaptor();
+        let mut transaction = adaptor.begin_transaction().unwrap();
+        {
+            let mut outer = transaction.savepoint("outer").unwrap();
+            outer.insert_record(counter("milk", 1)).unwrap();
+            {
+                let mut inner = outer.savepoint("inner").unwrap();
+                inner.insert_record(counter("cream", 2)).unwrap();
+            }
+            outer.release().unwrap();
+        }
+        transaction.commit().unwrap();
+        let names: Vec<String> = adaptor.query_all::<Counter>().unwrap().map(|c| c.name).collect();
+        assert_eq!(names, vec!["milk"]);
+    }
+
+    #[test]
+    fn test_release_after_outer_rollback() {
+        let mut adaptor = new_adaptor();
+        let mut transaction = adaptor.begin_transaction().unwrap();
+        let mut savepoint = transaction.savepoint("group").unwrap();
+        savepoint.insert_record(counter("milk", 1)).unwrap();
+        savepoint.adaptor.execute_sql("ROLLBACK;").unwrap();
+        assert!(savepoint.release().is_err());
+        drop(transaction);
+        assert_eq!(adaptor.query_all::<Counter>().unwrap().count(), 0);
+    }
+}
+
+mod schema_check_test {
+    use yoshino_core::db::DbAdaptor;
+    use yoshino_core::{FloatField, IntegerField, NullableTextField, TextField};
+    use yoshino_derive::Schema;
+    use crate::{ColumnTypeMismatch, SchemaMigration, SchemaMismatch, SQLiteAdaptor};
+
+    #[derive(Schema)]
+    #[yoshino(table = "item")]
+    struct Item {
+        pub name: String,
+        pub stock: i64,
+        pub note: Option<String>
+    }
+
+    #[derive(Schema)]
+    #[yoshino(table = "item")]
+    struct NewItem {
+        pub name: String,
+        pub stock: f64,
+        pub price: f64
+    }
+
+    #[test]
+    fn test_table_exists() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        assert!(!adaptor.table_exists::<Item>().unwrap());
+        adaptor.create_table_for_schema::<Item>().unwrap();
+        assert!(adaptor.table_exists::<Item>().unwrap());
+    }
+
+    #[test]
+    fn test_verify_schema() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        assert!(matches!(adaptor.verify_schema::<Item>(), Err(SchemaMismatch::MissingTable(_))));
+        adaptor.create_table_for_schema::<Item>().unwrap();
+        adaptor.verify_schema::<Item>().unwrap();
+        match adaptor.verify_schema::<NewItem>() {
+            Err(SchemaMismatch::Columns { table, missing_columns, extra_columns, type_mismatches }) => {
+                assert_eq!(table, "item");
+                assert_eq!(missing_columns, vec!["price"]);
+                assert_eq!(extra_columns, vec!["note"]);
+                assert_eq!(type_mismatches, vec![ColumnTypeMismatch {
+                    column: "stock".to_string(),
+                    expected: "REAL".to_string(),
+                    actual: "INTEGER".to_string()
+                }]);
+            }
+            other => panic!("unexpected result: {:?}", other)
+        }
+    }
+
+    #[derive(Schema)]
+    #[yoshino(table = "item")]
+    struct ItemWithSupplier {
+        pub name: String,
+        pub stock: i64,
+        pub supplier: Option<String>
+    }
+
+    #[test]
+    fn test_migrate_schema() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let migration = adaptor.migrate_schema::<Item>().unwrap();
+        assert_eq!(migration.added_columns, vec!["name", "stock", "note"]);
+        adaptor.insert_record(Item { name: "pen".to_string(), stock: 3, note: Some("blue".to_string()) }).unwrap();
+        let migration = adaptor.migrate_schema::<ItemWithSupplier>().unwrap();
+        assert_eq!(migration, SchemaMigration {
+            added_columns: vec!["supplier".to_string()],
+            removed_columns: vec!["note".to_string()]
+        });
+        adaptor.insert_record(ItemWithSupplier { name: "ink".to_string(), stock: 1, supplier: Some("acme".to_string()) }).unwrap();
+        let items: Vec<ItemWithSupplier> = adaptor.query_all::<ItemWithSupplier>().unwrap().collect();
+        assert_eq!(items[0].supplier, None);
+        assert_eq!(items[1].supplier, Some("acme".to_string()));
+        assert!(adaptor.verify_schema::<ItemWithSupplier>().is_err());
+        assert_eq!(adaptor.migrate_schema::<ItemWithSupplier>().unwrap().added_columns, Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_migrate_schema_rejects_not_null_column() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Item>().unwrap();
+        assert!(adaptor.migrate_schema::<NewItem>().is_err());
+        adaptor.verify_schema::<Item>().unwrap();
+    }
+
+    #[test]
+    fn test_migrate_schema_with_rebuild() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Item>().unwrap();
+        adaptor.insert_record(Item { name: "pen".to_string(), stock: 3, note: Some("blue".to_string()) }).unwrap();
+        let migration = adaptor.migrate_schema_with_rebuild::<ItemWithSupplier>().unwrap();
+        assert_eq!(migration.removed_columns, vec!["note"]);
+        adaptor.verify_schema::<ItemWithSupplier>().unwrap();
+        let item = adaptor.query_all::<ItemWithSupplier>().unwrap().next().unwrap();
+        assert_eq!(item.name, "pen");
+        assert_eq!(item.stock, 3);
+        assert_eq!(item.supplier, None);
+    }
+}
+
+mod pool_test {
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::IntegerField;
+    use yoshino_derive::Schema;
+    use crate::SQLitePool;
+    use super::temp_path;
+
+    #[derive(Schema)]
+    struct Counter {
+        pub value: i64
+    }
+
+    fn remove_database(path: &str) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path, suffix));
+        }
+    }
+
+    #[test]
+    fn test_pool_types_can_be_shared_between_threads() {
+        fn assert_send<T: Send>() {}
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send::<crate::SQLiteAdaptor>();
+        assert_send::<crate::PooledSQLiteAdaptor>();
+        assert_send_sync::<SQLitePool>();
+    }
+
+    #[test]
+    fn test_interrupt_handle_is_detached_when_returned() {
+        let path = temp_path("pool_interrupt");
+        let pool = SQLitePool::new(&path, 1).unwrap();
+        let first = pool.get().unwrap();
+        let handle = first.interrupt_handle();
+        assert!(!handle.is_closed());
+        drop(first);
+        assert!(handle.is_closed());
+
+        let second = pool.get().unwrap();
+        handle.interrupt();
+        second.execute_sql("SELECT 1;").unwrap();
+        assert!(!second.interrupt_handle().is_closed());
+        drop(second);
+        drop(pool);
+        remove_database(&path);
+    }
+
+    #[test]
+    fn test_connection_is_reset_when_returned() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let path = temp_path("pool_reset");
+        let attached_path = temp_path("pool_reset_attached");
+        let pool = SQLitePool::new(&path, 1).unwrap();
+        pool.get().unwrap().create_table_for_schema::<Counter>().unwrap();
+        let traced = Arc::new(AtomicUsize::new(0));
+        for _ in 0..2 {
+            let mut adaptor = pool.get().unwrap();
+            let traced_in_callback = traced.clone();
+            adaptor.set_trace(Some(Box::new(move |_| { traced_in_callback.fetch_add(1, Ordering::SeqCst); }))).unwrap();
+            adaptor.set_busy_timeout(Duration::from_secs(10)).unwrap();
+            adaptor.set_lossy_utf8(true);
+            adaptor.set_stmt_cache_capacity(0);
+            adaptor.set_foreign_keys(false).unwrap();
+            adaptor.attach(&attached_path, "other").unwrap();
+            adaptor.execute_sql("BEGIN;").unwrap();
+            adaptor.insert_record(Counter { value: 1 }).unwrap();
+            drop(adaptor);
+
+            let mut adaptor = pool.get().unwrap();
+            let traced_before = traced.load(Ordering::SeqCst);
+            assert_eq!(adaptor.count_all::<Counter>().unwrap(), 0);
+            assert_eq!(traced.load(Ordering::SeqCst), traced_before);
+            assert_eq!(unsafe { libsqlite3_sys::sqlite3_get_autocommit(adaptor.db_handler) }, 1);
+            assert!(adaptor.busy_handler.is_none());
+            assert!(!adaptor.lossy_utf8);
+            assert!(adaptor.foreign_keys_enabled().unwrap());
+            assert_eq!(adaptor.run_pragma_stmt("SELECT count(*) FROM pragma_database_list WHERE seq > 1;").unwrap(), "0");
+            // the statement cache is back on, so a repeated statement is prepared once
+            let prepare_count = adaptor.prepare_count;
+            adaptor.count_all::<Counter>().unwrap();
+            assert_eq!(adaptor.prepare_count, prepare_count);
+        }
+        drop(pool);
+        remove_database(&path);
+        remove_database(&attached_path);
+    }
+
+    #[test]
+    fn test_pool_checkout_timeout() {
+        let path = temp_path("pool_timeout");
+        let pool = SQLitePool::new(&path, 2).unwrap();
+        let first = pool.get().unwrap();
+        let second = pool.get_timeout(Duration::from_millis(10)).unwrap();
+        assert!(matches!(pool.get_timeout(Duration::from_millis(10)), Err(DbError::PoolTimeout)));
+        drop(first);
+        let third = pool.get_timeout(Duration::from_millis(10)).unwrap();
+        drop(second);
+        drop(third);
+        drop(pool);
+        remove_database(&path);
+        assert!(SQLitePool::new(&path, 0).is_err());
+    }
+
+    #[test]
+    fn test_pool_across_threads() {
+        let path = temp_path("pool_threads");
+        let pool = SQLitePool::new(&path, 2).unwrap();
+        {
+            let mut adaptor = pool.get().unwrap();
+            adaptor.create_table_for_schema::<Counter>().unwrap();
+            assert!(fs::metadata(format!("{}-wal", path)).is_ok(), "connections should be in WAL mode");
+        }
+        let workers: Vec<_> = (0..4).map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                let mut adaptor = pool.get().unwrap();
+                adaptor.set_busy_timeout(Duration::from_secs(10)).unwrap();
+                adaptor.insert_record(Counter { value: i }).unwrap();
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(pool.get().unwrap().count_all::<Counter>().unwrap(), 4);
+        drop(pool);
+        remove_database(&path);
+    }
+}
+
+mod backup_test {
+    use std::fs;
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::TextField;
+    use yoshino_derive::Schema;
+    use crate::{BackupProgress, SQLiteAdaptor};
+    use super::temp_path;
+
+    #[derive(Schema)]
+    struct Note {
+        pub text: String
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let path = temp_path("backup.db");
+        let _ = fs::remove_file(&path);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        for i in 0..2000 {
+            adaptor.insert_record(Note { text: format!("note {} {}", i, "x".repeat(200)) }).unwrap();
+        }
+        let mut steps: Vec<BackupProgress> = Vec::new();
+        adaptor.backup_to(&path, Some(|progress| steps.push(progress))).unwrap();
+        assert!(steps.len() > 1, "pages should be copied in chunks");
+        assert!(steps.windows(2).all(|w| w[0].remaining_pages > w[1].remaining_pages));
+        assert_eq!(steps.last().unwrap().remaining_pages, 0);
+        assert!(steps.iter().all(|step| step.total_pages == steps[0].total_pages));
+
+        let mut backup = SQLiteAdaptor::open(&path).unwrap();
+        assert_eq!(backup.count_all::<Note>().unwrap(), 2000);
+        drop(backup);
+
+        let mut restored = SQLiteAdaptor::open_in_memory().unwrap();
+        restored.restore_from(&path, None::<fn(BackupProgress)>).unwrap();
+        assert_eq!(restored.count_all::<Note>().unwrap(), 2000);
+        let _ = fs::remove_file(&path);
+        assert!(restored.restore_from(&path, None::<fn(BackupProgress)>).is_err());
+    }
+
+    #[test]
+    fn test_backup_to_locked_database() {
+        let path = temp_path("backup_locked.db");
+        let _ = fs::remove_file(&path);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.insert_record(Note { text: "hello".to_string() }).unwrap();
+
+        let mut holder = SQLiteAdaptor::open(&path).unwrap();
+        holder.execute_raw("BEGIN EXCLUSIVE;", &[]).unwrap();
+        let error = adaptor.backup_to(&path, None::<fn(BackupProgress)>).unwrap_err();
+        assert!(matches!(error, DbError::Busy(_)), "{:?}", error);
+        holder.execute_raw("COMMIT;", &[]).unwrap();
+        drop(holder);
+        let _ = fs::remove_file(&path);
+    }
+}
+
+mod maintenance_test {
+    use std::fs;
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::{Cond, IntegerField};
+    use yoshino_derive::Schema;
+    use crate::SQLiteAdaptor;
+    use super::temp_path;
+
+    #[derive(Schema)]
+    struct Reading {
+        #[yoshino(index)]
+        pub value: i64
+    }
+
+    #[test]
+    fn test_vacuum() {
+        let path = temp_path("vacuum.db");
+        let _ = fs::remove_file(&path);
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        adaptor.create_table_for_schema::<Reading>().unwrap();
+        adaptor.insert_records((0..5000).map(|value| Reading { value })).unwrap();
+        adaptor.delete_with_cond::<Reading>(Cond::integer_greater_than("value", 9)).unwrap();
+        let size_before = fs::metadata(&path).unwrap().len();
+        adaptor.vacuum().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < size_before);
+        assert_eq!(adaptor.count_all::<Reading>().unwrap(), 10);
+        drop(adaptor);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_vacuum_inside_transaction() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.execute_raw("BEGIN;", &[]).unwrap();
+        match adaptor.vacuum() {
+            Err(DbError::Other(message)) => assert_eq!(message, "VACUUM can't run inside a transaction"),
+            other => panic!("VACUUM inside a transaction should fail: {:?}", other)
+        }
+        assert!(adaptor.vacuum_into(&temp_path("vacuum_in_transaction.db")).is_err());
+        adaptor.execute_raw("COMMIT;", &[]).unwrap();
+        adaptor.vacuum().unwrap();
+    }
+
+    #[test]
+    fn test_vacuum_into() {
+        let path = temp_path("vacuum_into.db");
+        let _ = fs::remove_file(&path);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Reading>().unwrap();
+        adaptor.insert_records((0..100).map(|value| Reading { value })).unwrap();
+        adaptor.vacuum_into(&path).unwrap();
+        let mut copy = SQLiteAdaptor::open(&path).unwrap();
+        assert_eq!(copy.count_all::<Reading>().unwrap(), 100);
+        drop(copy);
+        // the copy is never written over
+        assert!(adaptor.vacuum_into(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_integrity_check() {
+        let path = temp_path("integrity_check.db");
+        let _ = fs::remove_file(&path);
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        adaptor.create_table_for_schema::<Reading>().unwrap();
+        adaptor.create_indexes_for_schema::<Reading>().unwrap();
+        adaptor.insert_records((1..=3).map(|value| Reading { value })).unwrap();
+        assert!(adaptor.integrity_check(0).unwrap().is_empty());
+        // the index no longer matches the order of its entries once the schema is reloaded
+        adaptor.execute_raw("PRAGMA writable_schema = ON;", &[]).unwrap();
+        adaptor.execute_raw("UPDATE sqlite_schema SET sql = replace(sql, '(\"value\")', '(\"value\" DESC)') WHERE type = 'index';", &[]).unwrap();
+        drop(adaptor);
+
+        let mut adaptor = SQLiteAdaptor::open(&path).unwrap();
+        let problems = adaptor.integrity_check(0).unwrap();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].contains("missing from index"), "{:?}", problems);
+        assert_eq!(adaptor.integrity_check(1).unwrap(), problems[..1]);
+        drop(adaptor);
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+mod migration_test {
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::{RowID, TextField};
+    use yoshino_derive::Schema;
+    use crate::{Migration, SQLiteAdaptor};
+
+    #[derive(Schema)]
+    #[yoshino(table = "labels")]
+    struct Label {
+        pub id: RowID,
+        pub name: String
+    }
+
+    fn migrations() -> Vec<Migration> {
+        vec![
+            Migration::from_sql(1, "create items", "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT); INSERT INTO items (name) VALUES ('first');"),
+            Migration::from_fn(2, "create labels", |adaptor| {
+                adaptor.create_table_for_schema::<Label>()?;
+                adaptor.insert_record(Label { id: RowID::NEW, name: "new".to_string() })?;
+                Ok(())
+            })
+        ]
+    }
+
+    fn user_version(adaptor: &mut SQLiteAdaptor) -> String {
+        adaptor.run_pragma_stmt("PRAGMA user_version;").unwrap()
+    }
+
+    #[test]
+    fn test_run_migrations() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let report = adaptor.run_migrations(&migrations()).unwrap();
+        assert_eq!(report.previous_version, 0);
+        assert_eq!(report.current_version, 2);
+        assert_eq!(report.applied, vec![(1, "create items".to_string()), (2, "create labels".to_string())]);
+        assert_eq!(user_version(&mut adaptor), "2");
+        assert_eq!(adaptor.count_all::<Label>().unwrap(), 1);
+
+        // applied migrations are never run again
+        let report = adaptor.run_migrations(&migrations()).unwrap();
+        assert_eq!((report.previous_version, report.current_version), (2, 2));
+        assert!(report.applied.is_empty());
+        assert_eq!(adaptor.count_all::<Label>().unwrap(), 1);
+
+        let mut more_migrations = migrations();
+        more_migrations.push(Migration::from_sql(5, "add label color", "ALTER TABLE labels ADD COLUMN color TEXT;"));
+        let report = adaptor.run_migrations(&more_migrations).unwrap();
+        assert_eq!((report.previous_version, report.current_version), (2, 5));
+        assert_eq!(report.applied, vec![(5, "add label color".to_string())]);
+        assert_eq!(adaptor.execute_raw("UPDATE labels SET color = 'red';", &[]).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let mut migrations = migrations();
+        migrations.insert(1, Migration::from_sql(2, "broken", "CREATE TABLE tags (name TEXT); INSERT INTO missing VALUES (1);"));
+        migrations[2] = Migration::from_fn(3, "create labels", |adaptor| {
+            adaptor.create_table_for_schema::<Label>()
+        });
+        match adaptor.run_migrations(&migrations) {
+            Err(DbError::DatabaseFailure(failure)) => {
+                assert!(failure.message.starts_with("Migration 2 (broken) failed, the database stays at version 1: "), "{}", failure.message);
+                assert!(failure.message.contains("no such table: missing"), "{}", failure.message);
+            }
+            other => panic!("the broken migration should fail: {:?}", other)
+        }
+        assert_eq!(user_version(&mut adaptor), "1");
+        assert!(adaptor.execute_raw("DROP TABLE tags;", &[]).is_err());
+        assert!(!adaptor.table_exists::<Label>().unwrap());
+        assert_eq!(adaptor.execute_raw("UPDATE items SET name = 'first';", &[]).unwrap(), 1);
+
+        let failing_step = Migration::from_fn(2, "failing step", |adaptor| {
+            adaptor.create_table_for_schema::<Label>()?;
+            Err(DbError::Other("not yet".to_string()))
+        });
+        let error = adaptor.run_migrations(&[failing_step]).unwrap_err();
+        assert_eq!(error.to_string(), "Migration 2 (failing step) failed, the database stays at version 1: not yet");
+        assert!(!adaptor.table_exists::<Label>().unwrap());
+    }
+
+    #[test]
+    fn test_failed_migration_keeps_error_kind() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let migrations = vec![
+            Migration::from_sql(1, "create tags", "CREATE TABLE tags (name TEXT UNIQUE); INSERT INTO tags VALUES ('a');"),
+            Migration::from_sql(2, "duplicate tag", "INSERT INTO tags VALUES ('a');")
+        ];
+        match adaptor.run_migrations(&migrations) {
+            Err(DbError::ConstraintViolation(failure, _)) => {
+                assert!(failure.message.starts_with("Migration 2 (duplicate tag) failed, the database stays at version 1: "), "{}", failure.message);
+                assert!(failure.message.contains("UNIQUE constraint failed"), "{}", failure.message);
+            }
+            other => panic!("the duplicate tag should violate the unique constraint: {:?}", other)
+        }
+        assert_eq!(user_version(&mut adaptor), "1");
+    }
+
+    #[test]
+    fn test_invalid_migration_versions() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let invalid_lists = [
+            (vec![Migration::from_sql(1, "a", "CREATE TABLE a (x);"), Migration::from_sql(1, "b", "CREATE TABLE b (x);")],
+                "Migrations a and b have the same version 1"),
+            (vec![Migration::from_sql(2, "a", "CREATE TABLE a (x);"), Migration::from_sql(1, "b", "CREATE TABLE b (x);")],
+                "Migration b (version 1) comes after a (version 2)"),
+            (vec![Migration::from_sql(0, "a", "CREATE TABLE a (x);")], "Version 0 of migration a is out of range"),
+            (vec![Migration::from_sql(1 << 31, "a", "CREATE TABLE a (x);")], "Version 2147483648 of migration a is out of range"),
+        ];
+        for (migrations, expected_message) in invalid_lists {
+            assert_eq!(adaptor.run_migrations(&migrations).unwrap_err().to_string(), expected_message);
+        }
+        // nothing is applied from an invalid list
+        assert_eq!(user_version(&mut adaptor), "0");
+        assert!(adaptor.execute_raw("DROP TABLE a;", &[]).is_err());
+    }
+}
+
+mod attach_test {
+    use std::fs;
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::{Cond, IntegerField, RowID, TextField};
+    use yoshino_derive::Schema;
+    use crate::SQLiteAdaptor;
+    use super::temp_path;
+
+    #[derive(Schema)]
+    #[yoshino(table = "counter")]
+    struct Counter {
+        pub id: RowID,
+        pub name: String,
+        pub value: i64
+    }
+
+    fn counter(name: &str, value: i64) -> Counter {
+        Counter { id: RowID::NEW, name: name.to_string(), value }
+    }
+
+    #[test]
+    fn test_attach_and_query_in() {
+        // the path is bound, so quotes in it need no escaping
+        let path = temp_path("it's archive.db");
+        let _ = fs::remove_file(&path);
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Counter>().unwrap();
+        adaptor.insert_record(counter("hot", 1)).unwrap();
+        adaptor.attach(&path, "archive").unwrap();
+        adaptor.create_table_for_schema_in::<Counter>("archive").unwrap();
+        adaptor.insert_record_in("archive", counter("old", 10)).unwrap();
+        adaptor.insert_record_in("archive", counter("older", 20)).unwrap();
+
+        let names: Vec<String> = adaptor.query_with_cond_in::<Counter>("archive", Cond::integer_greater_than("value", 15))
+            .unwrap().map(|counter| counter.name).collect();
+        assert_eq!(names, vec!["older"]);
+        assert_eq!(adaptor.count_with_cond_in::<Counter>("archive", Cond::is_not_null("name")).unwrap(), 2);
+        assert_eq!(adaptor.delete_with_cond_in::<Counter>("archive", Cond::text_equal_to("name", "old")).unwrap(), 1);
+        assert_eq!(adaptor.query_all_in::<Counter>("archive").unwrap().count(), 1);
+        // the table of the same name in the main database is a different one
+        let names: Vec<String> = adaptor.query_all::<Counter>().unwrap().map(|counter| counter.name).collect();
+        assert_eq!(names, vec!["hot"]);
+        assert_eq!(adaptor.query_all_in::<Counter>("main").unwrap().count(), 1);
+
+        adaptor.detach("archive").unwrap();
+        assert!(adaptor.query_all_in::<Counter>("archive").is_err());
+        let mut archive = SQLiteAdaptor::open(&path).unwrap();
+        assert_eq!(archive.query_all::<Counter>().unwrap().map(|counter| counter.value).collect::<Vec<_>>(), vec![20]);
+        drop(archive);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_aliases() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        for alias in ["", "2nd", "archive-db", "a\"; DROP TABLE counter; --"] {
+            match adaptor.attach(":memory:", alias) {
+                Err(DbError::Other(message)) => assert_eq!(message, format!("Invalid database alias: {}", alias)),
+                other => panic!("{} should be rejected: {:?}", alias, other)
+            }
+            assert!(adaptor.create_table_for_schema_in::<Counter>(alias).is_err());
+        }
+        assert!(adaptor.detach("no such").is_err());
+        // SQLite's own error for an alias that isn't attached
+        assert!(adaptor.detach("archive").is_err());
+    }
+
+    #[test]
+    fn test_detach_with_active_query() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.attach(":memory:", "archive").unwrap();
+        adaptor.create_table_for_schema_in::<Counter>("archive").unwrap();
+        adaptor.insert_record_in("archive", counter("a", 1)).unwrap();
+        adaptor.insert_record_in("archive", counter("b", 2)).unwrap();
+        let mut counters = adaptor.query_all_in::<Counter>("archive").unwrap();
+        assert!(counters.next().is_some());
+        let error = adaptor.detach("archive").unwrap_err();
+        assert!(error.to_string().contains("database archive is locked"), "{}", error);
+        assert!(counters.next().is_some());
+        drop(counters);
+        adaptor.detach("archive").unwrap();
+    }
+}
+
+#[cfg(feature = "async")]
+mod async_test {
+    use yoshino_core::{Cond, IntegerField, RowID, TextField, Uuid};
+    use yoshino_core::db::DbAdaptor;
+    use yoshino_derive::Schema;
+    use crate::{AsyncSQLiteAdaptor, SQLiteAdaptor};
+
+    #[derive(Schema)]
+    struct Counter {
+        pub id: RowID,
+        pub name: String,
+        pub stock: i64
+    }
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_async_adaptor() {
+        block_on(async {
+            let adaptor = AsyncSQLiteAdaptor::from_adaptor(SQLiteAdaptor::open_in_memory().unwrap());
+            adaptor.create_table_for_schema::<Counter>().await.unwrap();
+            let apple = adaptor.insert_record(Counter { id: RowID::NEW, name: "apple".to_string(), stock: 3 }).await.unwrap();
+            adaptor.insert_record(Counter { id: RowID::NEW, name: "pear".to_string(), stock: 0 }).await.unwrap();
+
+            let in_stock = adaptor.query_with_cond::<Counter>(Cond::integer_greater_than("stock", 0)).await.unwrap();
+            assert_eq!(in_stock.len(), 1);
+            assert_eq!(in_stock[0].name, "apple");
+            adaptor.update_record(Counter { id: RowID::ID(apple), name: "apple".to_string(), stock: 5 }).await.unwrap();
+            let apple = adaptor.query_first_with_cond::<Counter>(Cond::text_equal_to("name", "apple")).await.unwrap().unwrap();
+            assert_eq!(apple.stock, 5);
+            assert!(adaptor.delete_record(apple).await.unwrap());
+            assert_eq!(adaptor.count_with_cond::<Counter>(Cond::is_not_null("name")).await.unwrap(), 1);
+
+            let shared = adaptor.clone();
+            let count = shared.run(|adaptor| adaptor.count_all::<Counter>()).await.unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(adaptor.query_all::<Counter>().await.unwrap()[0].name, "pear");
+        });
+    }
+
+    #[derive(Schema)]
+    struct Device {
+        #[yoshino(primary_key)]
+        pub id: Uuid,
+        pub name: String
+    }
+
+    #[test]
+    fn test_async_update_and_delete_by_primary_key() {
+        block_on(async {
+            let adaptor = AsyncSQLiteAdaptor::from_adaptor(SQLiteAdaptor::open_in_memory().unwrap());
+            adaptor.create_table_for_schema::<Device>().await.unwrap();
+            let id = Uuid::new_v4();
+            adaptor.insert_record(Device { id, name: "lamp".to_string() }).await.unwrap();
+            adaptor.update_record(Device { id, name: "desk lamp".to_string() }).await.unwrap();
+            assert_eq!(adaptor.query_all::<Device>().await.unwrap()[0].name, "desk lamp");
+            assert!(adaptor.delete_record(Device { id, name: "desk lamp".to_string() }).await.unwrap());
+            assert_eq!(adaptor.count_with_cond::<Device>(Cond::is_not_null("name")).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn test_async_adaptor_errors() {
+        block_on(async {
+            let adaptor = AsyncSQLiteAdaptor::from_adaptor(SQLiteAdaptor::open_in_memory().unwrap());
+            assert!(adaptor.query_all::<Counter>().await.is_err());
+            assert!(AsyncSQLiteAdaptor::open("/nonexistent/dir/db").await.is_err());
+        });
+    }
+}
+
+mod trace_test {
+    use std::sync::{Arc, Mutex};
+    use yoshino_core::db::DbAdaptor;
+    use yoshino_core::{IntegerField, TextField};
+    use yoshino_derive::Schema;
+    use crate::SQLiteAdaptor;
+
+    #[derive(Schema)]
+    struct Note {
+        pub text: String,
+        pub stars: i64
+    }
+
+    #[test]
+    fn test_trace_statements() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let traced = Arc::new(Mutex::new(Vec::new()));
+        let sink = traced.clone();
+        adaptor.set_trace(Some(Box::new(move |sql| sink.lock().unwrap().push(sql.to_string())))).unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.insert_record(Note { text: "it's".to_string(), stars: 3 }).unwrap();
+        adaptor.insert_record(Note { text: "again".to_string(), stars: 4 }).unwrap();
+        {
+            let traced = traced.lock().unwrap();
+            assert_eq!(traced.len(), 3, "{:?}", traced);
+            assert!(traced[0].starts_with(r#"CREATE TABLE IF NOT EXISTS "y_note""#), "{}", traced[0]);
+            assert!(traced[1].contains("'it''s'") && traced[1].contains("3"), "{}", traced[1]);
+            assert!(traced[2].contains("'again'"), "{}", traced[2]);
+        }
+
+        adaptor.set_trace(None).unwrap();
+        adaptor.count_all::<Note>().unwrap();
+        assert_eq!(traced.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_trace_callback_panic_is_caught() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.set_trace(Some(Box::new(|_| panic!("trace failed")))).unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        adaptor.insert_record(Note { text: "hello".to_string(), stars: 1 }).unwrap();
+        assert_eq!(adaptor.count_all::<Note>().unwrap(), 1);
+    }
+}
+
+mod interrupt_test {
+    use std::thread;
+    use std::time::Duration;
+    use yoshino_core::db::{DbAdaptor, DbError};
+    use yoshino_core::TextField;
+    use yoshino_derive::Schema;
+    use crate::SQLiteAdaptor;
+
+    #[derive(Schema)]
+    struct Note {
+        pub text: String
+    }
+
+    #[test]
+    fn test_interrupt_query() {
+        let mut adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        adaptor.create_table_for_schema::<Note>().unwrap();
+        for i in 0..10 {
+            adaptor.insert_record(Note { text: format!("note {}", i) }).unwrap();
+        }
+        let handle = adaptor.interrupt_handle();
+        let mut result = adaptor.query_all::<Note>().unwrap();
+        assert_eq!(result.next().unwrap().text, "note 0");
+        let other_handle = handle.clone();
+        thread::spawn(move || other_handle.interrupt()).join().unwrap();
+        assert!(result.next().is_none());
+        assert!(matches!(result.error(), Some(DbError::Interrupted)));
+        drop(result);
+
+        // statements started after the interrupt run as usual
+        assert_eq!(adaptor.query_all::<Note>().unwrap().count(), 10);
+    }
+
+    #[test]
+    fn test_interrupt_running_statement() {
+        let adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let handle = adaptor.interrupt_handle();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.interrupt();
+        });
+        let result = adaptor.execute_sql(
+            "WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c WHERE x < 1000000000) SELECT count(*) FROM c;");
+        assert!(matches!(result, Err(DbError::Interrupted)), "{:?}", result);
+        interrupter.join().unwrap();
+    }
+
+    #[test]
+    fn test_interrupt_after_close() {
+        let adaptor = SQLiteAdaptor::open_in_memory().unwrap();
+        let handle = adaptor.interrupt_handle();
+        assert!(!handle.is_closed());
+        drop(adaptor);
+        assert!(handle.is_closed());
+        handle.interrupt();
+    }
+}
@@ -0,0 +1,66 @@
+//! Tracing of the SQL statements run on a connection.
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_uint, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use libsqlite3_sys::sqlite3_stmt;
+use yoshino_core::db::DbError;
+use crate::SQLiteAdaptor;
+
+/// Callback that receives the SQL of the statements run on a connection.
+pub type TraceCallback = Box<dyn FnMut(&str) + Send>;
+
+impl SQLiteAdaptor {
+    /// Call `callback` with the SQL of each statement when it starts running,
+    /// with the values bound to its parameters filled in. A statement that is
+    /// reused is traced every time it runs. `None` stops tracing.
+    ///
+    /// A panic in the callback is caught and ignored, as it can't unwind
+    /// through SQLite.
+    #[doc(alias = "sqlite3_trace_v2")]
+    pub fn set_trace(&mut self, callback: Option<TraceCallback>) -> Result<(), DbError> {
+        // boxed again so SQLite gets a thin pointer, which stays valid when the adaptor moves
+        let mut callback = callback.map(Box::new);
+        unsafe {
+            match callback.as_mut() {
+                Some(callback) => {
+                    let context = &mut **callback as *mut TraceCallback as *mut c_void;
+                    db_try!(self.db_handler, libsqlite3_sys::sqlite3_trace_v2(
+                        self.db_handler,
+                        libsqlite3_sys::SQLITE_TRACE_STMT as c_uint,
+                        Some(trace_stmt),
+                        context
+                    ));
+                }
+                None => self.clear_trace()
+            }
+        }
+        // the old callback is only dropped once SQLite no longer refers to it
+        self.trace_callback = callback;
+        Ok(())
+    }
+
+    /// Stop calling the trace callback, so it can be dropped.
+    pub(crate) fn clear_trace(&self) {
+        unsafe {
+            libsqlite3_sys::sqlite3_trace_v2(self.db_handler, 0, None, ptr::null_mut());
+        }
+    }
+}
+
+unsafe extern "C" fn trace_stmt(event: c_uint, context: *mut c_void, stmt: *mut c_void, _sql: *mut c_void) -> c_int {
+    if event != libsqlite3_sys::SQLITE_TRACE_STMT as c_uint {
+        return 0;
+    }
+    let callback = &mut *(context as *mut TraceCallback);
+    let stmt = stmt as *mut sqlite3_stmt;
+    let expanded_sql = libsqlite3_sys::sqlite3_expanded_sql(stmt);
+    // the expanded SQL is null if it can't be allocated, so the SQL as written is used then
+    let sql_ptr = if expanded_sql.is_null() { libsqlite3_sys::sqlite3_sql(stmt) } else { expanded_sql as *const _ };
+    if !sql_ptr.is_null() {
+        let sql = CStr::from_ptr(sql_ptr).to_string_lossy();
+        let _ = catch_unwind(AssertUnwindSafe(|| callback(&sql)));
+    }
+    libsqlite3_sys::sqlite3_free(expanded_sql as *mut c_void);
+    0
+}
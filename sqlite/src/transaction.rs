@@ -0,0 +1,194 @@
+//! Explicit transactions on a SQLite connection.
+use yoshino_core::{Aggregate, ColumnValue, Cond, FieldUpdates, Schema, SortDir};
+use yoshino_core::db::{DbAdaptor, DbError, DbQueryResult};
+use crate::{quote_identifier, SQLiteAdaptor};
+
+/// A transaction on a `SQLiteAdaptor`, created by `SQLiteAdaptor::begin_transaction`.
+///
+/// It supports the same operations as the adaptor through `DbAdaptor`. Changes
+/// are only kept if `commit` is called: a transaction dropped before that,
+/// including on panic, is rolled back.
+///
+/// Transactions can't be nested, as SQLite rejects `BEGIN` inside a
+/// transaction. Use `savepoint` to scope a part of the transaction instead.
+pub struct SQLiteTransaction<'a> {
+    pub(crate) adaptor: &'a mut SQLiteAdaptor,
+    finished: bool
+}
+
+impl SQLiteAdaptor {
+    /// Begin a transaction on this connection.
+    pub fn begin_transaction(&mut self) -> Result<SQLiteTransaction<'_>, DbError> {
+        self.execute_sql("BEGIN;")?;
+        Ok(SQLiteTransaction { adaptor: self, finished: false })
+    }
+
+    /// Begin a transaction on this connection, the same as `begin_transaction`.
+    pub fn transaction(&mut self) -> Result<SQLiteTransaction<'_>, DbError> {
+        self.begin_transaction()
+    }
+}
+
+impl SQLiteTransaction<'_> {
+    /// Commit all changes made in this transaction. If the commit fails, the
+    /// transaction is rolled back.
+    pub fn commit(mut self) -> Result<(), DbError> {
+        self.adaptor.execute_sql("COMMIT;")?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Discard all changes made in this transaction.
+    pub fn rollback(mut self) -> Result<(), DbError> {
+        self.adaptor.execute_sql("ROLLBACK;")?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Create a savepoint in this transaction.
+    pub fn savepoint(&mut self, name: &str) -> Result<SQLiteSavepoint<'_>, DbError> {
+        SQLiteSavepoint::new(self.adaptor, name)
+    }
+}
+
+/// A savepoint in a transaction, created by `SQLiteTransaction::savepoint`.
+///
+/// Changes made after the savepoint can be rolled back without ending the
+/// outer transaction. They are only kept if `release` is called: a savepoint
+/// dropped before that is rolled back. Savepoints can be nested with `savepoint`.
+pub struct SQLiteSavepoint<'a> {
+    pub(crate) adaptor: &'a mut SQLiteAdaptor,
+    name: String,
+    finished: bool
+}
+
+impl<'a> SQLiteSavepoint<'a> {
+    fn new(adaptor: &'a mut SQLiteAdaptor, name: &str) -> Result<SQLiteSavepoint<'a>, DbError> {
+        let name = quote_identifier(name);
+        adaptor.execute_sql(&format!("SAVEPOINT {};", name))?;
+        Ok(SQLiteSavepoint { adaptor, name, finished: false })
+    }
+
+    /// Keep the changes made after this savepoint as part of the outer transaction.
+    ///
+    /// Fails if the savepoint no longer exists, e.g. SQLite has rolled back the
+    /// outer transaction after an error.
+    pub fn release(mut self) -> Result<(), DbError> {
+        self.adaptor.execute_sql(&format!("RELEASE {};", self.name))?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Discard the changes made after this savepoint. The outer transaction goes on.
+    pub fn rollback(mut self) -> Result<(), DbError> {
+        self.adaptor.execute_sql(&format!("ROLLBACK TO {}; RELEASE {};", self.name, self.name))?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Create a savepoint nested in this one.
+    pub fn savepoint(&mut self, name: &str) -> Result<SQLiteSavepoint<'_>, DbError> {
+        SQLiteSavepoint::new(self.adaptor, name)
+    }
+}
+
+impl Drop for SQLiteSavepoint<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // fails only if the savepoint is already gone with the outer transaction
+            let _ = self.adaptor.execute_sql(&format!("ROLLBACK TO {}; RELEASE {};", self.name, self.name));
+        }
+    }
+}
+
+impl Drop for SQLiteTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // there is no way to report the error here, and a failed rollback
+            // means SQLite has already rolled the transaction back by itself
+            let _ = self.adaptor.execute_sql("ROLLBACK;");
+        }
+    }
+}
+
+/// Implement `DbAdaptor` for a guard type by forwarding to its `adaptor` field.
+macro_rules! delegate_db_adaptor {
+    ($guard: ty) => {
+        impl DbAdaptor for $guard {
+            fn create_table_for_schema<T: Schema>(&mut self) -> Result<(), DbError> {
+                self.adaptor.create_table_for_schema::<T>()
+            }
+
+            fn insert_record<T: Schema>(&mut self, record: T) -> Result<i64, DbError> {
+                self.adaptor.insert_record(record)
+            }
+
+            fn query_all<T: Schema>(&mut self) -> Result<DbQueryResult<T>, DbError> {
+                self.adaptor.query_all::<T>()
+            }
+
+            fn query_with_cond<T: Schema>(&mut self, cond: Cond) -> Result<DbQueryResult<T>, DbError> {
+                self.adaptor.query_with_cond::<T>(cond)
+            }
+
+            fn query_first_with_cond<T: Schema>(&mut self, cond: Cond) -> Result<Option<T>, DbError> {
+                self.adaptor.query_first_with_cond::<T>(cond)
+            }
+
+            fn query_sorted<T: Schema>(&mut self, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError> {
+                self.adaptor.query_sorted::<T>(order)
+            }
+
+            fn query_with_cond_sorted<T: Schema>(&mut self, cond: Cond, order: Vec<(String, SortDir)>) -> Result<DbQueryResult<T>, DbError> {
+                self.adaptor.query_with_cond_sorted::<T>(cond, order)
+            }
+
+            fn query_limit<T: Schema>(&mut self, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError> {
+                self.adaptor.query_limit::<T>(limit, offset)
+            }
+
+            fn query_with_cond_limit<T: Schema>(&mut self, cond: Cond, limit: u64, offset: u64) -> Result<DbQueryResult<T>, DbError> {
+                self.adaptor.query_with_cond_limit::<T>(cond, limit, offset)
+            }
+
+            fn count_all<T: Schema>(&mut self) -> Result<u64, DbError> {
+                self.adaptor.count_all::<T>()
+            }
+
+            fn count_with_cond<T: Schema>(&mut self, cond: Cond) -> Result<u64, DbError> {
+                self.adaptor.count_with_cond::<T>(cond)
+            }
+
+            fn aggregate_with_cond<T: Schema, V: ColumnValue>(&mut self, aggregate: Aggregate, cond: Cond) -> Result<Option<V>, DbError> {
+                self.adaptor.aggregate_with_cond::<T, V>(aggregate, cond)
+            }
+
+            fn delete_with_cond<T: Schema>(&mut self, cond: Cond) -> Result<(), DbError> {
+                self.adaptor.delete_with_cond::<T>(cond)
+            }
+
+            fn delete_all<T: Schema>(&mut self) -> Result<u64, DbError> {
+                self.adaptor.delete_all::<T>()
+            }
+
+            fn delete_record<T: Schema>(&mut self, record: &T) -> Result<bool, DbError> {
+                self.adaptor.delete_record(record)
+            }
+
+            fn update_with_cond<T: Schema>(&mut self, cond: Cond, record: T) -> Result<(), DbError> {
+                self.adaptor.update_with_cond(cond, record)
+            }
+
+            fn update_fields_with_cond<T: Schema>(&mut self, updates: FieldUpdates, cond: Cond) -> Result<u64, DbError> {
+                self.adaptor.update_fields_with_cond::<T>(updates, cond)
+            }
+
+            fn update_record<T: Schema>(&mut self, record: &T) -> Result<(), DbError> {
+                self.adaptor.update_record(record)
+            }
+        }
+    };
+}
+
+delegate_db_adaptor!(SQLiteTransaction<'_>);
+delegate_db_adaptor!(SQLiteSavepoint<'_>);
@@ -1,15 +1,53 @@
-/// Internal code for authenticate a user.
+//! Internal code for authenticate a user.
 
+use argon2::{Algorithm, Argon2, Params, Version};
 use bytes::{Bytes, BytesMut, BufMut, Buf};
 use sha2::{Sha256, Digest};
+use subtle::ConstantTimeEq;
 use yoshino_core::{TextField, db::DbData};
 
 /// To indicate how the useer credential is hashed
 #[derive(Clone, Debug)]
 pub enum UserCredentialHashType {
     /// SHA256 hash with a salt
-    Sha256WithSalt(Bytes)
-} 
+    Sha256WithSalt(Bytes),
+    /// Argon2id hash with a salt of at least 8 bytes, the memory cost in KiB,
+    /// the number of iterations and the degree of parallelism
+    Argon2id {
+        salt: Bytes,
+        memory_cost: u32,
+        time_cost: u32,
+        parallelism: u32
+    },
+    /// Bcrypt hash with a 16-byte salt and a cost between 4 and 31.
+    /// Only the first 72 bytes of the credential are used.
+    Bcrypt {
+        salt: [u8; 16],
+        cost: u32
+    }
+}
+
+const SHA256_WITH_SALT_MAGIC: i32 = 0x35A256;
+const ARGON2ID_MAGIC: i32 = 0x35A2A2;
+const BCRYPT_MAGIC: i32 = 0x35A2BC;
+
+impl UserCredentialHashType {
+    /// Argon2id hash with the recommended parameters: 19 MiB of memory,
+    /// 2 iterations and 1 degree of parallelism.
+    pub fn argon2id(salt: Bytes) -> UserCredentialHashType {
+        UserCredentialHashType::Argon2id {
+            salt,
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST
+        }
+    }
+
+    /// Bcrypt hash with the default cost of 12.
+    pub fn bcrypt(salt: [u8; 16]) -> UserCredentialHashType {
+        UserCredentialHashType::Bcrypt { salt, cost: bcrypt::DEFAULT_COST }
+    }
+}
 
 /// User credential type 
 #[derive(Clone, Debug)]
@@ -21,71 +59,117 @@ pub struct UserCredential {
 impl UserCredential {
     /// Validate whether the plain credential matches this user.
     pub fn validate_credential(&self, credential_plain: Bytes) -> bool {
-        match &self.hash_type {
-            UserCredentialHashType::Sha256WithSalt(salt) => {
-                let mut hasher = Sha256::new();
-                hasher.update(credential_plain.as_ref());
-                hasher.update(salt.as_ref());
-                let result = hasher.finalize();
-                let result_bytes = Bytes::from(result.to_vec());
-                result_bytes.eq(&self.data)
-            }
+        self.verify(&credential_plain)
+    }
+
+    /// Check a candidate password against this credential. The hashes are
+    /// compared in constant time so the check doesn't leak how much of them matched.
+    pub fn verify(&self, password: &Bytes) -> bool {
+        match UserCredential::hash(password, &self.hash_type) {
+            Some(candidate) => candidate.as_ref().ct_eq(self.data.as_ref()).into(),
+            None => false
         }
     }
 
     /// Create a user credential with the plain text and the hash type.
+    ///
+    /// Panics if the salt or the parameters of the hash type are invalid.
     pub fn new(credential_plain: Bytes, hash_type: UserCredentialHashType)-> UserCredential {
-        let data = match &hash_type {
+        let data = UserCredential::hash(&credential_plain, &hash_type)
+            .expect("Invalid user credential hash parameters");
+        UserCredential { data, hash_type}
+    }
+
+    /// Hash the plain credential. Return `None` if the hash type has invalid parameters.
+    fn hash(credential_plain: &[u8], hash_type: &UserCredentialHashType) -> Option<Bytes> {
+        match hash_type {
             UserCredentialHashType::Sha256WithSalt(salt) => {
                 let mut hasher = Sha256::new();
-                hasher.update(credential_plain.as_ref());
+                hasher.update(credential_plain);
                 hasher.update(salt.as_ref());
                 let result = hasher.finalize();
-                Bytes::from(result.to_vec())
-            }            
-        };
-        UserCredential { data, hash_type}
+                Some(Bytes::from(result.to_vec()))
+            }
+            UserCredentialHashType::Argon2id { salt, memory_cost, time_cost, parallelism } => {
+                let params = Params::new(*memory_cost, *time_cost, *parallelism, None).ok()?;
+                let mut result = [0u8; Params::DEFAULT_OUTPUT_LEN];
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(credential_plain, salt, &mut result)
+                    .ok()?;
+                Some(Bytes::copy_from_slice(&result))
+            }
+            UserCredentialHashType::Bcrypt { salt, cost } => {
+                let result = bcrypt::hash_with_salt(credential_plain, *cost, *salt).ok()?;
+                Some(Bytes::from(result.to_string()))
+            }
+        }
     }
 }
 
+fn put_len_prefixed(buf: &mut BytesMut, data: &[u8]) {
+    buf.put_u32(data.len() as u32);
+    buf.put_slice(data);
+}
+
+fn get_len_prefixed(buf: &mut Bytes) -> Bytes {
+    let len = buf.get_u32() as usize;
+    buf.split_to(len)
+}
+
 impl TextField for UserCredential {
     fn to_db_data(&self) -> String {
         let mut buf = BytesMut::new();
         match &self.hash_type {
             UserCredentialHashType::Sha256WithSalt(salt) => {
-                buf.put_i32(0x35A256);
-                buf.put_u32(salt.len() as u32);
-                buf.put_slice(salt);
-                buf.put_u32(self.data.len() as u32);
-                buf.put_slice(&self.data);
+                buf.put_i32(SHA256_WITH_SALT_MAGIC);
+                put_len_prefixed(&mut buf, salt);
+            }
+            UserCredentialHashType::Argon2id { salt, memory_cost, time_cost, parallelism } => {
+                buf.put_i32(ARGON2ID_MAGIC);
+                buf.put_u32(*memory_cost);
+                buf.put_u32(*time_cost);
+                buf.put_u32(*parallelism);
+                put_len_prefixed(&mut buf, salt);
+            }
+            UserCredentialHashType::Bcrypt { salt, cost } => {
+                buf.put_i32(BCRYPT_MAGIC);
+                buf.put_u32(*cost);
+                put_len_prefixed(&mut buf, salt);
             }
         };
+        put_len_prefixed(&mut buf, &self.data);
         base64::encode(buf)
     }
     fn from_db_data(data: &Box<dyn DbData>) -> UserCredential {
         let data_str = <String as DbData>::from_boxed_db_data(data);
         let mut buf = Bytes::from(base64::decode(data_str).unwrap());
         let magic_number = buf.get_i32();
-        match magic_number {
-            0x35A256 => {
-                let salt_len = buf.get_u32() as usize;
-                let mut salt = BytesMut::with_capacity(salt_len);
-                for _i in 0..salt_len {
-                    salt.put_u8(buf.get_u8())
-                }
-                let data_len = buf.get_u32() as usize;
-                let mut data = BytesMut::with_capacity(data_len);
-                for _i in 0..data_len {
-                    data.put_u8(buf.get_u8())
-                }
-                UserCredential {
-                    data: Bytes::from(data),
-                    hash_type: UserCredentialHashType::Sha256WithSalt(Bytes::from(salt))
+        let hash_type = match magic_number {
+            SHA256_WITH_SALT_MAGIC => {
+                UserCredentialHashType::Sha256WithSalt(get_len_prefixed(&mut buf))
+            }
+            ARGON2ID_MAGIC => {
+                let memory_cost = buf.get_u32();
+                let time_cost = buf.get_u32();
+                let parallelism = buf.get_u32();
+                let salt = get_len_prefixed(&mut buf);
+                UserCredentialHashType::Argon2id { salt, memory_cost, time_cost, parallelism }
+            }
+            BCRYPT_MAGIC => {
+                let cost = buf.get_u32();
+                let salt = get_len_prefixed(&mut buf);
+                UserCredentialHashType::Bcrypt {
+                    salt: salt.as_ref().try_into().expect("Invalid bcrypt salt length"),
+                    cost
                 }
             }
             _ => {
                 panic!("Unsupported user credential type");
             }
+        };
+        UserCredential {
+            data: get_len_prefixed(&mut buf),
+            hash_type
         }
     }
 }
@@ -107,6 +191,17 @@ mod tests{
         assert!(!ground_truth.validate_credential(Bytes::from("this is not the plain text")))
     }
 
+    #[test]
+    fn test_sha256_user_credential_verify() {
+        let ground_truth = UserCredential::new(
+            Bytes::from("password"), 
+            UserCredentialHashType::Sha256WithSalt(Bytes::from("salt"))
+        );
+        assert!(ground_truth.verify(&Bytes::from("password")));
+        assert!(!ground_truth.verify(&Bytes::from("Password")));
+        assert!(!ground_truth.verify(&Bytes::new()));
+    }
+
     #[test]
     fn test_sha256_user_base64_serialization() {
         let plain_text = "this_is_the_pain_text";
@@ -116,8 +211,45 @@ mod tests{
         );
         
         let data = ground_truth.to_db_data();
-        let boxed_data : Box<(dyn DbData + 'static)>= Box::new(data);
+        let boxed_data : Box<dyn DbData + 'static>= Box::new(data);
         let new_user = UserCredential::from_db_data(&boxed_data);
         assert!(new_user.validate_credential(Bytes::from(plain_text)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_argon2id_user_credential() {
+        let hash_type = UserCredentialHashType::Argon2id {
+            salt: Bytes::from("saltsalt"),
+            memory_cost: 64,
+            time_cost: 1,
+            parallelism: 1
+        };
+        let ground_truth = UserCredential::new(Bytes::from("password"), hash_type);
+        assert!(ground_truth.verify(&Bytes::from("password")));
+        assert!(!ground_truth.verify(&Bytes::from("Password")));
+
+        let boxed_data : Box<dyn DbData + 'static>= Box::new(ground_truth.to_db_data());
+        let new_user = UserCredential::from_db_data(&boxed_data);
+        assert!(new_user.verify(&Bytes::from("password")));
+        assert!(!new_user.verify(&Bytes::from("Password")));
+    }
+
+    #[test]
+    fn test_bcrypt_user_credential() {
+        let hash_type = UserCredentialHashType::Bcrypt { salt: [7; 16], cost: 4 };
+        let ground_truth = UserCredential::new(Bytes::from("password"), hash_type);
+        assert!(ground_truth.verify(&Bytes::from("password")));
+        assert!(!ground_truth.verify(&Bytes::from("Password")));
+
+        let boxed_data : Box<dyn DbData + 'static>= Box::new(ground_truth.to_db_data());
+        let new_user = UserCredential::from_db_data(&boxed_data);
+        assert!(new_user.verify(&Bytes::from("password")));
+        assert!(!new_user.verify(&Bytes::from("Password")));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_argon2id_short_salt_is_rejected() {
+        UserCredential::new(Bytes::from("password"), UserCredentialHashType::argon2id(Bytes::from("salt")));
+    }
+}
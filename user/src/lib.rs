@@ -1,4 +1,4 @@
-/**
+/*!
  * User related data model.
  */
 
@@ -26,4 +26,9 @@ impl User {
             login_credential
         }
     }
+
+    /// Check whether `password` is the password of this user.
+    pub fn check_password(&self, password: &str) -> bool {
+        self.login_credential.verify(&Bytes::copy_from_slice(password.as_bytes()))
+    }
 }
\ No newline at end of file